@@ -49,6 +49,7 @@ fn main() {
         .header(format!("{interface_path}/SKP_Silk_errors.h"))
         .header(format!("{interface_path}/SKP_Silk_SDK_API.h"))
         .header(format!("{interface_path}/SKP_Silk_typedef.h"))
+        .use_core()
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
         .generate()
         .expect("Unable to generate bindings");
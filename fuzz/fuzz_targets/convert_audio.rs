@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use silk_codec::{AudioConverter, detect_audio_format};
+use std::io::Write;
+
+// ffmpeg only opens paths, so every input goes through a temp file. seeds/convert_audio
+// holds one small file per container we sniff, pass it after the working corpus:
+// cargo fuzz run convert_audio corpus/convert_audio seeds/convert_audio
+fuzz_target!(|data: &[u8]| {
+    let _ = detect_audio_format(data);
+
+    let Ok(converter) = AudioConverter::new() else {
+        return;
+    };
+    let mut input = tempfile::NamedTempFile::new().expect("create fuzz input");
+    input.write_all(data).expect("write fuzz input");
+    let output = tempfile::NamedTempFile::new().expect("create fuzz output");
+
+    let _ = converter.convert_to_pcm(input.path(), output.path());
+    let _ = converter.convert_to_planar_f32(input.path());
+    let _ = converter.probe(input.path());
+});
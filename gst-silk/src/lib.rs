@@ -0,0 +1,40 @@
+//! `silkenc` and `silkdec` elements on top of the streaming [`silk_codec::SilkEncoder`]
+//! and [`silk_codec::SilkDecoder`], for mono S16LE audio at the rates below.
+
+use gst::glib;
+
+mod silkdec;
+mod silkenc;
+
+const RATES: [i32; 4] = [8000, 12000, 16000, 24000];
+const DEFAULT_RATE: i32 = 24000;
+
+fn raw_caps() -> gst::Caps {
+    gst_audio::AudioCapsBuilder::new_interleaved()
+        .format(gst_audio::AudioFormat::S16le)
+        .rate_list(RATES)
+        .channels(1)
+        .build()
+}
+
+fn silk_caps() -> gst::Caps {
+    gst::Caps::builder("audio/x-silk").build()
+}
+
+fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    silkenc::register(plugin)?;
+    silkdec::register(plugin)?;
+    Ok(())
+}
+
+gst::plugin_define!(
+    silk,
+    env!("CARGO_PKG_DESCRIPTION"),
+    plugin_init,
+    concat!(env!("CARGO_PKG_VERSION"), "-", env!("COMMIT_ID")),
+    "MIT/X11",
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_REPOSITORY"),
+    env!("BUILD_REL_DATE")
+);
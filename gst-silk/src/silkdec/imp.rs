@@ -0,0 +1,192 @@
+use gst::glib;
+use gst::subclass::prelude::*;
+use gst_audio::prelude::*;
+use gst_audio::subclass::prelude::*;
+use silk_codec::SilkDecoder;
+use std::sync::{LazyLock, Mutex};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "silkdec",
+        gst::DebugColorFlags::empty(),
+        Some("Silk decoder"),
+    )
+});
+
+struct State {
+    decoder: SilkDecoder,
+    rate: i32,
+}
+
+#[derive(Default)]
+pub struct SilkDec {
+    rate: Mutex<Option<i32>>,
+    state: Mutex<Option<State>>,
+}
+
+impl SilkDec {
+    /// Starts a decoder at `rate` and announces it downstream.
+    fn start_decoder(&self, rate: i32) -> Result<State, gst::LoggableError> {
+        let decoder = SilkDecoder::new(rate)
+            .map_err(|err| gst::loggable_error!(CAT, "Failed to create decoder: {err}"))?;
+        let info = gst_audio::AudioInfo::builder(gst_audio::AudioFormat::S16le, rate as u32, 1)
+            .build()
+            .map_err(|_| gst::loggable_error!(CAT, "Invalid output rate {rate}"))?;
+        self.obj()
+            .set_output_format(&info)
+            .map_err(|_| gst::loggable_error!(CAT, "Failed to set output format"))?;
+        Ok(State { decoder, rate })
+    }
+
+    fn output_rate(&self) -> i32 {
+        self.rate.lock().unwrap().unwrap_or(crate::DEFAULT_RATE)
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for SilkDec {
+    const NAME: &'static str = "GstSilkDec";
+    type Type = super::SilkDec;
+    type ParentType = gst_audio::AudioDecoder;
+}
+
+impl ObjectImpl for SilkDec {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecInt::builder("rate")
+                    .nick("Rate")
+                    .blurb("Output rate when the input caps carry none")
+                    .minimum(crate::RATES[0])
+                    .maximum(crate::DEFAULT_RATE)
+                    .default_value(crate::DEFAULT_RATE)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "rate" => {
+                *self.rate.lock().unwrap() = Some(value.get().expect("type checked upstream"))
+            }
+            name => {
+                gst::error!(CAT, imp = self, "unknown property {name}");
+                unreachable!("unknown property {name}")
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "rate" => self.output_rate().to_value(),
+            name => {
+                gst::error!(CAT, imp = self, "unknown property {name}");
+                unreachable!("unknown property {name}")
+            }
+        }
+    }
+}
+
+impl GstObjectImpl for SilkDec {}
+
+impl ElementImpl for SilkDec {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Silk decoder",
+                "Codec/Decoder/Audio",
+                "Decodes a silk v3 stream, standard or Tencent, to mono S16LE audio",
+                "silk-codec contributors",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &crate::silk_caps(),
+            )
+            .unwrap();
+            let src = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &crate::raw_caps(),
+            )
+            .unwrap();
+            vec![src, sink]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl AudioDecoderImpl for SilkDec {
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock().unwrap() = None;
+        Ok(())
+    }
+
+    // filesrc sends no caps, the decoder is then started by the first buffer at the
+    // rate of the property instead
+    fn set_format(&self, caps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        let rate = caps
+            .structure(0)
+            .and_then(|structure| structure.get::<i32>("rate").ok())
+            .unwrap_or_else(|| self.output_rate());
+        let state = self.start_decoder(rate)?;
+        *self.state.lock().unwrap() = Some(state);
+        Ok(())
+    }
+
+    fn handle_frame(
+        &self,
+        buffer: Option<&gst::Buffer>,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        // the container ends with the packets already decoded, there is nothing to drain
+        let Some(buffer) = buffer else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let mut guard = self.state.lock().unwrap();
+        if guard.is_none() {
+            let state = self.start_decoder(self.output_rate()).map_err(|err| {
+                err.log_with_imp(self);
+                gst::FlowError::NotNegotiated
+            })?;
+            *guard = Some(state);
+        }
+        let state = guard.as_mut().expect("started above");
+        let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+        let samples = state.decoder.feed_container_bytes(&map).map_err(|err| {
+            gst::element_imp_error!(self, gst::StreamError::Decode, ["{err}"]);
+            gst::FlowError::Error
+        })?;
+        drop(guard);
+
+        gst::trace!(CAT, imp = self, "decoded {} samples", samples.len());
+        let output = (!samples.is_empty()).then(|| {
+            let pcm = samples
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect::<Vec<_>>();
+            gst::Buffer::from_mut_slice(pcm)
+        });
+        self.obj().finish_frame(output, 1)
+    }
+
+    fn flush(&self, _hard: bool) {
+        // a new stream after a flush starts with its own header
+        if let Some(state) = self.state.lock().unwrap().as_mut()
+            && let Ok(decoder) = SilkDecoder::new(state.rate)
+        {
+            state.decoder = decoder;
+        }
+    }
+}
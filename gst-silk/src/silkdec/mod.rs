@@ -0,0 +1,18 @@
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct SilkDec(ObjectSubclass<imp::SilkDec>)
+        @extends gst_audio::AudioDecoder, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "silkdec",
+        gst::Rank::PRIMARY,
+        SilkDec::static_type(),
+    )
+}
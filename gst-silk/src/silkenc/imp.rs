@@ -0,0 +1,229 @@
+use gst::glib;
+use gst::subclass::prelude::*;
+use gst_audio::prelude::*;
+use gst_audio::subclass::prelude::*;
+use silk_codec::{SampleRate, SilkEncodeOptions, SilkEncoder};
+use std::sync::{LazyLock, Mutex};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "silkenc",
+        gst::DebugColorFlags::empty(),
+        Some("Silk encoder"),
+    )
+});
+
+const DEFAULT_BITRATE: i32 = 24000;
+const DEFAULT_COMPLEXITY: u32 = 2;
+const PACKET_DURATION_MS: i32 = 20;
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    bitrate: i32,
+    complexity: u32,
+    tencent: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bitrate: DEFAULT_BITRATE,
+            complexity: DEFAULT_COMPLEXITY,
+            tencent: false,
+        }
+    }
+}
+
+struct State {
+    options: SilkEncodeOptions,
+    // taken by the drain at EOS, which terminates the stream
+    encoder: Option<SilkEncoder>,
+}
+
+#[derive(Default)]
+pub struct SilkEnc {
+    settings: Mutex<Settings>,
+    state: Mutex<Option<State>>,
+}
+
+impl SilkEnc {
+    fn encode_error(&self, err: silk_codec::SilkError) -> gst::FlowError {
+        gst::element_imp_error!(self, gst::StreamError::Encode, ["{err}"]);
+        gst::FlowError::Error
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for SilkEnc {
+    const NAME: &'static str = "GstSilkEnc";
+    type Type = super::SilkEnc;
+    type ParentType = gst_audio::AudioEncoder;
+}
+
+impl ObjectImpl for SilkEnc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecInt::builder("bitrate")
+                    .nick("Bitrate")
+                    .blurb("Target bit rate in bits per second")
+                    .minimum(5000)
+                    .maximum(100000)
+                    .default_value(DEFAULT_BITRATE)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("complexity")
+                    .nick("Complexity")
+                    .blurb("Encoder complexity, 0 is the fastest and 2 the best")
+                    .maximum(2)
+                    .default_value(DEFAULT_COMPLEXITY)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoolean::builder("tencent")
+                    .nick("Tencent")
+                    .blurb("Write the 0x02 prefix of the Tencent variant")
+                    .default_value(false)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "bitrate" => settings.bitrate = value.get().expect("type checked upstream"),
+            "complexity" => settings.complexity = value.get().expect("type checked upstream"),
+            "tencent" => settings.tencent = value.get().expect("type checked upstream"),
+            name => {
+                gst::error!(CAT, imp = self, "unknown property {name}");
+                unreachable!("unknown property {name}")
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "bitrate" => settings.bitrate.to_value(),
+            "complexity" => settings.complexity.to_value(),
+            "tencent" => settings.tencent.to_value(),
+            name => {
+                gst::error!(CAT, imp = self, "unknown property {name}");
+                unreachable!("unknown property {name}")
+            }
+        }
+    }
+}
+
+impl GstObjectImpl for SilkEnc {}
+
+impl ElementImpl for SilkEnc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Silk encoder",
+                "Codec/Encoder/Audio",
+                "Encodes mono S16LE audio to a silk v3 stream",
+                "silk-codec contributors",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &crate::raw_caps(),
+            )
+            .unwrap();
+            let src = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &crate::silk_caps(),
+            )
+            .unwrap();
+            vec![src, sink]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl AudioEncoderImpl for SilkEnc {
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn set_format(&self, info: &gst_audio::AudioInfo) -> Result<(), gst::LoggableError> {
+        let settings = *self.settings.lock().unwrap();
+        let rate = info.rate() as i32;
+        let sample_rate = SampleRate::try_from(rate)
+            .map_err(|err| gst::loggable_error!(CAT, "Unsupported rate: {err}"))?;
+        let options = SilkEncodeOptions::new()
+            .sample_rate(sample_rate)
+            .bit_rate(settings.bitrate)
+            .complexity(settings.complexity as u8)
+            .tencent(settings.tencent);
+        let encoder = SilkEncoder::new(&options)
+            .map_err(|err| gst::loggable_error!(CAT, "Failed to create encoder: {err}"))?;
+
+        let caps = gst::Caps::builder("audio/x-silk")
+            .field("rate", rate)
+            .field("tencent", settings.tencent)
+            .build();
+        self.obj()
+            .set_output_format(&caps)
+            .map_err(|_| gst::loggable_error!(CAT, "Failed to set output format"))?;
+        // one packet per frame keeps the output timestamps on packet boundaries
+        let packet_samples = rate / 1000 * PACKET_DURATION_MS;
+        self.obj().set_frame_samples_min(packet_samples);
+        self.obj().set_frame_samples_max(packet_samples);
+
+        *self.state.lock().unwrap() = Some(State {
+            options,
+            encoder: Some(encoder),
+        });
+        Ok(())
+    }
+
+    fn handle_frame(
+        &self,
+        buffer: Option<&gst::Buffer>,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let mut guard = self.state.lock().unwrap();
+        let state = guard.as_mut().ok_or(gst::FlowError::NotNegotiated)?;
+        let (output, samples) = match buffer {
+            Some(buffer) => {
+                let encoder = state
+                    .encoder
+                    .as_mut()
+                    .ok_or(gst::FlowError::NotNegotiated)?;
+                let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+                let output = encoder.feed(&map).map_err(|err| self.encode_error(err))?;
+                (output, (map.len() / 2) as i32)
+            }
+            None => match state.encoder.take() {
+                Some(encoder) => (encoder.finish().map_err(|err| self.encode_error(err))?, -1),
+                None => return Ok(gst::FlowSuccess::Ok),
+            },
+        };
+        drop(guard);
+
+        gst::trace!(CAT, imp = self, "encoded {} bytes", output.len());
+        let output = (!output.is_empty()).then(|| gst::Buffer::from_mut_slice(output));
+        self.obj().finish_frame(output, samples)
+    }
+
+    fn flush(&self) {
+        // the next buffer starts a new stream, header included
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            state.encoder = SilkEncoder::new(&state.options).ok();
+        }
+    }
+}
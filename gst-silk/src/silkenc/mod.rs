@@ -0,0 +1,18 @@
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct SilkEnc(ObjectSubclass<imp::SilkEnc>)
+        @extends gst_audio::AudioEncoder, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "silkenc",
+        gst::Rank::PRIMARY,
+        SilkEnc::static_type(),
+    )
+}
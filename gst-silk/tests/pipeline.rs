@@ -0,0 +1,107 @@
+use gst::prelude::*;
+use silk_codec::{TestSignal, decode_silk, detect_silk_variant, parse_wav};
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Once;
+use std::time::Duration;
+
+fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        gstsilk::plugin_register_static().expect("register the silk plugin");
+    });
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gst-silk-{}-{name}", std::process::id()))
+}
+
+/// Runs `description` to EOS, panicking on the first error message.
+fn run(description: &str) {
+    let pipeline = gst::parse::launch(description).unwrap();
+    pipeline.set_state(gst::State::Playing).unwrap();
+    let bus = pipeline.bus().unwrap();
+    for message in bus.iter_timed(gst::ClockTime::from_seconds(30)) {
+        match message.view() {
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => {
+                pipeline.set_state(gst::State::Null).unwrap();
+                panic!("{description}: {} ({:?})", err.error(), err.debug());
+            }
+            _ => {}
+        }
+    }
+    pipeline.set_state(gst::State::Null).unwrap();
+}
+
+fn speech_wav(name: &str, sample_rate: u32) -> PathBuf {
+    let path = temp_path(name);
+    TestSignal::speech_like(180.0)
+        .sample_rate(sample_rate)
+        .duration(Duration::from_secs(1))
+        .write_wav(File::create(&path).unwrap())
+        .unwrap();
+    path
+}
+
+#[test]
+fn transcodes_wav_to_silk_and_back() {
+    init();
+    for rate in [8000, 16000, 24000] {
+        let input = speech_wav(&format!("in-{rate}.wav"), rate);
+        let silk = temp_path(&format!("{rate}.silk"));
+        let output = temp_path(&format!("out-{rate}.wav"));
+
+        run(&format!(
+            "filesrc location={} ! wavparse ! silkenc bitrate=20000 ! filesink location={}",
+            input.display(),
+            silk.display()
+        ));
+        let encoded = fs::read(&silk).unwrap();
+        assert!(detect_silk_variant(&encoded).is_some());
+        // a second is exactly 50 packets of 20 ms
+        let decoded = decode_silk(&encoded, rate as i32).unwrap();
+        assert_eq!(decoded.len(), rate as usize * 2);
+
+        run(&format!(
+            "filesrc location={} ! silkdec rate={rate} ! wavenc ! filesink location={}",
+            silk.display(),
+            output.display()
+        ));
+        let wav = parse_wav(&fs::read(&output).unwrap()).unwrap();
+        assert_eq!((wav.sample_rate, wav.channels), (rate, 1));
+        assert_eq!(wav.data, decoded);
+
+        for path in [input, silk, output] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
+
+#[test]
+fn caps_carry_the_rate_and_variant() {
+    init();
+    let input = speech_wav("tencent.wav", 16000);
+    let silk = temp_path("tencent.silk");
+    let output = temp_path("tencent-out.wav");
+    // silkdec takes the rate from the silkenc caps and ignores its property
+    run(&format!(
+        "filesrc location={} ! wavparse ! silkenc tencent=true ! tee name=t \
+         t. ! queue ! filesink location={} \
+         t. ! queue ! silkdec rate=8000 ! wavenc ! filesink location={}",
+        input.display(),
+        silk.display(),
+        output.display()
+    ));
+
+    let encoded = fs::read(&silk).unwrap();
+    assert_eq!(encoded[0], 0x02);
+    let wav = parse_wav(&fs::read(&output).unwrap()).unwrap();
+    assert_eq!(wav.sample_rate, 16000);
+    assert_eq!(wav.data, decode_silk(&encoded, 16000).unwrap());
+
+    for path in [input, silk, output] {
+        fs::remove_file(path).unwrap();
+    }
+}
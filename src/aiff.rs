@@ -0,0 +1,112 @@
+use bytes::BufMut;
+use std::io::{self, Seek, SeekFrom, Write};
+
+const BITS_PER_SAMPLE: u16 = 16;
+const HEADER_LEN: u64 = 54;
+const FRAME_COUNT_OFFSET: u64 = 22;
+const SOUND_CHUNK_SIZE_OFFSET: u64 = 42;
+
+/// Streams s16le PCM into an AIFF container as big-endian samples, the chunk sizes
+/// and frame count are patched in `finish`.
+pub struct AiffWriter<W: Write + Seek> {
+    inner: W,
+    channels: u16,
+    data_len: u64,
+    pending_byte: Option<u8>,
+}
+
+impl<W: Write + Seek> AiffWriter<W> {
+    pub fn new(mut inner: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        inner.write_all(&aiff_header(sample_rate, channels))?;
+        Ok(Self {
+            inner,
+            channels,
+            data_len: 0,
+            pending_byte: None,
+        })
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        // samples are always whole, so the sound chunk never needs a pad byte
+        let form_len = HEADER_LEN - 8 + self.data_len;
+        let block_align = (self.channels.max(1) * BITS_PER_SAMPLE / 8) as u64;
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&chunk_len(form_len).to_be_bytes())?;
+        self.inner.seek(SeekFrom::Start(FRAME_COUNT_OFFSET))?;
+        self.inner
+            .write_all(&chunk_len(self.data_len / block_align).to_be_bytes())?;
+        self.inner.seek(SeekFrom::Start(SOUND_CHUNK_SIZE_OFFSET))?;
+        self.inner
+            .write_all(&chunk_len(8 + self.data_len).to_be_bytes())?;
+        self.inner.seek(SeekFrom::End(0))?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Seek> Write for AiffWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = buf;
+        let mut swapped = Vec::with_capacity(buf.len() + 1);
+        if let Some(low) = self.pending_byte.take() {
+            match bytes.split_first() {
+                Some((&high, rest)) => {
+                    swapped.put_slice(&[high, low]);
+                    bytes = rest;
+                }
+                None => self.pending_byte = Some(low),
+            }
+        }
+
+        for sample in bytes.chunks_exact(2) {
+            swapped.put_slice(&[sample[1], sample[0]]);
+        }
+        if bytes.len() % 2 == 1 {
+            self.pending_byte = bytes.last().copied();
+        }
+        self.inner.write_all(&swapped)?;
+        self.data_len += swapped.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn aiff_header(sample_rate: u32, channels: u16) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    header.put_slice(b"FORM");
+    header.put_u32(HEADER_LEN as u32 - 8);
+    header.put_slice(b"AIFF");
+    header.put_slice(b"COMM");
+    header.put_u32(18);
+    header.put_u16(channels);
+    header.put_u32(0);
+    header.put_u16(BITS_PER_SAMPLE);
+    header.put_slice(&extended_sample_rate(sample_rate));
+    header.put_slice(b"SSND");
+    header.put_u32(8);
+    // offset and block size, samples are not block aligned
+    header.put_u32(0);
+    header.put_u32(0);
+    header
+}
+
+/// Sample rate as an 80-bit IEEE 754 extended precision float.
+fn extended_sample_rate(sample_rate: u32) -> [u8; 10] {
+    let mut extended = [0u8; 10];
+    if sample_rate == 0 {
+        return extended;
+    }
+
+    let shift = sample_rate.leading_zeros();
+    let exponent = 16383 + 31 - shift as u16;
+    let mantissa = (sample_rate as u64) << (32 + shift);
+    extended[..2].copy_from_slice(&exponent.to_be_bytes());
+    extended[2..].copy_from_slice(&mantissa.to_be_bytes());
+    extended
+}
+
+fn chunk_len(len: u64) -> u32 {
+    u32::try_from(len).unwrap_or(u32::MAX)
+}
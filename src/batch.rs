@@ -0,0 +1,21 @@
+use crate::silk::{SilkEncodeOptions, SilkEncoderHandle, SilkError};
+use rayon::prelude::*;
+
+/// Encodes every clip of `inputs` on the rayon thread pool with one encoder per worker.
+/// Results are in input order and a failing clip only fails its own slot.
+pub fn encode_silk_batch<R: AsRef<[u8]> + Sync>(
+    inputs: &[R],
+    options: &SilkEncodeOptions,
+) -> Vec<Result<Vec<u8>, SilkError>> {
+    inputs
+        .par_iter()
+        .map_init(
+            || SilkEncoderHandle::new(options).ok(),
+            |handle, input| match handle {
+                Some(handle) => handle.encode(input),
+                // the options are rejected, building again reports it for every clip
+                None => SilkEncoderHandle::new(options)?.encode(input),
+            },
+        )
+        .collect()
+}
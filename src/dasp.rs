@@ -0,0 +1,140 @@
+use crate::silk::{
+    SampleRate, SilkEncodeOptions, SilkError, decode_silk, encode_silk_with_options,
+};
+use dasp_frame::Frame;
+use dasp_interpolate::linear::Linear;
+use dasp_sample::{FromSample, Sample, ToSample};
+use dasp_signal::Signal;
+use std::marker::PhantomData;
+
+pub struct SilkSignal<S = i16> {
+    samples: std::vec::IntoIter<i16>,
+    _sample: PhantomData<S>,
+}
+
+impl<S> SilkSignal<S> {
+    pub fn new<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Self, SilkError> {
+        let pcm = decode_silk(src, sample_rate)?;
+        let samples = pcm
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>();
+        Ok(Self {
+            samples: samples.into_iter(),
+            _sample: PhantomData,
+        })
+    }
+}
+
+impl<S> Signal for SilkSignal<S>
+where
+    S: Sample + FromSample<i16>,
+{
+    type Frame = [S; 1];
+
+    fn next(&mut self) -> Self::Frame {
+        match self.samples.next() {
+            Some(sample) => [S::from_sample_(sample)],
+            None => [S::EQUILIBRIUM],
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.samples.as_slice().is_empty()
+    }
+}
+
+/// Encodes a finite signal into silk. When `signal_rate` differs from the encoder
+/// sample rate the signal is linearly resampled if `resample` is set, otherwise
+/// `SilkError::SampleRateMismatch` is returned.
+pub fn encode_signal<S>(
+    signal: S,
+    signal_rate: i32,
+    options: &SilkEncodeOptions,
+    resample: bool,
+) -> Result<Vec<u8>, SilkError>
+where
+    S: Signal,
+    <S::Frame as Frame>::Sample: ToSample<f32>,
+{
+    let target_rate = options.sample_rate.hz();
+    let mut mono = signal.until_exhausted().map(downmix).collect::<Vec<_>>();
+    if signal_rate != target_rate {
+        if !resample {
+            return Err(SilkError::SampleRateMismatch {
+                signal: signal_rate,
+                target: target_rate,
+            });
+        }
+        mono = resample_linear(mono, signal_rate, target_rate);
+    }
+
+    encode_silk_with_options(pcm_bytes(mono), options)
+}
+
+/// Encodes s16le mono `pcm` at any `input_rate`. A rate the encoder does not take is
+/// linearly resampled to `target_rate_hint`, 24 kHz by default, supported rates are
+/// encoded as they are. Returns the rate the stream was encoded at.
+pub fn encode_silk_auto<R: AsRef<[u8]>>(
+    pcm: R,
+    input_rate: i32,
+    target_rate_hint: Option<SampleRate>,
+    bit_rate: i32,
+    tencent: bool,
+) -> Result<(Vec<u8>, SampleRate), SilkError> {
+    if input_rate <= 0 {
+        return Err(SilkError::UnsupportedSampleRate(input_rate));
+    }
+    let sample_rate =
+        SampleRate::try_from(input_rate).unwrap_or(target_rate_hint.unwrap_or(SampleRate::Hz24000));
+    let options = SilkEncodeOptions::new()
+        .sample_rate(sample_rate)
+        .bit_rate(bit_rate)
+        .tencent(tencent);
+    if sample_rate.hz() == input_rate {
+        return Ok((encode_silk_with_options(pcm, &options)?, sample_rate));
+    }
+
+    let mono = pcm
+        .as_ref()
+        .chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]).to_sample::<f32>())
+        .collect();
+    let resampled = resample_linear(mono, input_rate, sample_rate.hz());
+    Ok((
+        encode_silk_with_options(pcm_bytes(resampled), &options)?,
+        sample_rate,
+    ))
+}
+
+fn pcm_bytes(samples: Vec<f32>) -> Vec<u8> {
+    let mut pcm = Vec::with_capacity(samples.len() * 2);
+    for sample in samples {
+        pcm.extend_from_slice(&sample.to_sample::<i16>().to_le_bytes());
+    }
+    pcm
+}
+
+fn downmix<F>(frame: F) -> f32
+where
+    F: Frame,
+    F::Sample: ToSample<f32>,
+{
+    frame
+        .channels()
+        .map(|sample| sample.to_sample::<f32>())
+        .sum::<f32>()
+        / F::CHANNELS as f32
+}
+
+fn resample_linear(samples: Vec<f32>, from_rate: i32, to_rate: i32) -> Vec<f32> {
+    let expected_len = (samples.len() as u64 * to_rate as u64 / from_rate as u64) as usize;
+    let mut source = dasp_signal::from_iter(samples.into_iter().map(|sample| [sample]));
+    let interpolator = Linear::new(source.next(), source.next());
+    source
+        .from_hz_to_hz(interpolator, from_rate as f64, to_rate as f64)
+        .until_exhausted()
+        .take(expected_len)
+        .map(|[sample]| sample)
+        .collect()
+}
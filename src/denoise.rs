@@ -0,0 +1,78 @@
+use nnnoiseless::DenoiseState;
+
+pub(crate) const DENOISE_SAMPLE_RATE: u32 = 48000;
+const FRAME_SIZE: usize = DenoiseState::FRAME_SIZE;
+// rnnoise expects samples in the i16 range
+const SAMPLE_SCALE: f32 = 32768.0;
+
+/// Runs RNNoise over mono 48 kHz samples. The model delays its output by one
+/// frame, which is dropped from the head and flushed from the tail in `finish`
+/// so the output lines up with the input sample for sample.
+pub(crate) struct Denoiser {
+    state: Box<DenoiseState<'static>>,
+    pending: Vec<f32>,
+    output: Vec<f32>,
+    delay_left: usize,
+    input_len: u64,
+    output_len: u64,
+}
+
+impl Denoiser {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: DenoiseState::new(),
+            pending: Vec::with_capacity(FRAME_SIZE),
+            output: vec![0.0; FRAME_SIZE],
+            delay_left: FRAME_SIZE,
+            input_len: 0,
+            output_len: 0,
+        }
+    }
+
+    pub(crate) fn process<F, E>(&mut self, samples: &[f32], on_output: &mut F) -> Result<(), E>
+    where
+        F: FnMut(&[f32]) -> Result<(), E>,
+    {
+        for &sample in samples {
+            self.pending.push(sample * SAMPLE_SCALE);
+            self.input_len += 1;
+            if self.pending.len() == FRAME_SIZE {
+                self.process_frame(on_output)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish<F, E>(mut self, on_output: &mut F) -> Result<(), E>
+    where
+        F: FnMut(&[f32]) -> Result<(), E>,
+    {
+        while self.output_len < self.input_len {
+            self.pending.resize(FRAME_SIZE, 0.0);
+            self.process_frame(on_output)?;
+        }
+        Ok(())
+    }
+
+    fn process_frame<F, E>(&mut self, on_output: &mut F) -> Result<(), E>
+    where
+        F: FnMut(&[f32]) -> Result<(), E>,
+    {
+        self.state.process_frame(&mut self.output, &self.pending);
+        self.pending.clear();
+
+        let skip = self.delay_left.min(FRAME_SIZE);
+        self.delay_left -= skip;
+        let available = (self.input_len - self.output_len).min((FRAME_SIZE - skip) as u64) as usize;
+        if available == 0 {
+            return Ok(());
+        }
+
+        let samples = self.output[skip..skip + available]
+            .iter()
+            .map(|sample| sample / SAMPLE_SCALE)
+            .collect::<Vec<_>>();
+        self.output_len += available as u64;
+        on_output(&samples)
+    }
+}
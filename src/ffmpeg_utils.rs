@@ -1,11 +1,22 @@
 use ffmpeg_next as ffmpeg;
 #[cfg(feature = "ffmpeg-tracing")]
 use std::cell::Cell;
-#[cfg(feature = "ffmpeg-tracing")]
 use std::ffi::CStr;
 #[cfg(feature = "ffmpeg-tracing")]
-use std::os::raw::{c_char, c_int, c_void};
+use std::os::raw::c_void;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
 use std::sync::OnceLock;
+use std::time::Duration;
+
+const FALLBACK_FORMAT_TIME_BASE_MICROS: i64 = 1_000_000;
+// real start delays are a few seconds at most, crafted timestamps are cut here so
+// they can't ask for hours of padding
+const MAX_START_OFFSET_SECS: f64 = 600.0;
+// prefix handed to the demuxer probes, ffmpeg's own first probe pass reads 2 KB
+const MAX_PROBE_BYTES: usize = 4096;
+// AVPROBE_SCORE_RETRY, what ffmpeg answers when it wants more data to be sure
+const PROBE_SCORE_RETRY: c_int = 25;
 
 #[cfg(all(feature = "ffmpeg-tracing", target_os = "linux"))]
 type FfmpegVaList = *mut ffmpeg::ffi::__va_list_tag;
@@ -25,6 +36,86 @@ pub(crate) fn ensure_ffmpeg_initialized() -> Result<(), ffmpeg::Error> {
     }
 }
 
+/// The demuxer ffmpeg picks for data starting with `bytes`, as its name, its first
+/// extension and the probe score out of 100. Only the first 4 KB are probed and no
+/// demuxer is opened; guesses at the retry score or below count as no match.
+pub(crate) fn probe_container_format(bytes: &[u8]) -> Option<(&'static str, &'static str, i32)> {
+    let len = bytes.len().min(MAX_PROBE_BYTES);
+    // the probes may read a little past the end, ffmpeg wants zeroed padding there
+    let mut buf = vec![0u8; len + ffmpeg::ffi::AVPROBE_PADDING_SIZE as usize];
+    buf[..len].copy_from_slice(&bytes[..len]);
+    let mut data = ffmpeg::ffi::AVProbeData {
+        filename: c"".as_ptr(),
+        buf: buf.as_mut_ptr(),
+        buf_size: len as c_int,
+        mime_type: ptr::null(),
+    };
+    let mut score: c_int = 0;
+    let format = unsafe { ffmpeg::ffi::av_probe_input_format3(&mut data, 1, &mut score) };
+    if format.is_null() || score <= PROBE_SCORE_RETRY {
+        return None;
+    }
+
+    // demuxer descriptors are statics, their strings live as long as the program
+    let first = |list: *const c_char| -> Option<&'static str> {
+        if list.is_null() {
+            return None;
+        }
+        let list = unsafe { CStr::from_ptr(list) }.to_str().ok()?;
+        list.split(',').next().filter(|entry| !entry.is_empty())
+    };
+    let (name, extensions) = unsafe { ((*format).name, (*format).extensions) };
+    let name = first(name)?;
+    Some((name, first(extensions).unwrap_or(name), score))
+}
+
+pub(crate) fn stream_duration_to_duration(stream: &ffmpeg::Stream<'_>) -> Option<Duration> {
+    let raw_duration = stream.duration();
+    if raw_duration <= 0 {
+        return None;
+    }
+
+    rational_units_to_duration(raw_duration, stream.time_base())
+}
+
+pub(crate) fn format_duration_to_duration(raw_duration: i64) -> Option<Duration> {
+    if raw_duration <= 0 {
+        return None;
+    }
+
+    let seconds = raw_duration as f64 / FALLBACK_FORMAT_TIME_BASE_MICROS as f64;
+    duration_from_seconds(seconds)
+}
+
+fn rational_units_to_duration(value: i64, time_base: ffmpeg::Rational) -> Option<Duration> {
+    let denominator = time_base.denominator();
+    if denominator <= 0 {
+        return None;
+    }
+
+    let seconds = value as f64 * f64::from(time_base);
+    duration_from_seconds(seconds)
+}
+
+/// Start offset in whole samples at `sample_rate`, clamped to +-10 minutes; a
+/// non-finite offset counts as none.
+pub(crate) fn start_offset_samples(offset_secs: f64, sample_rate: u32) -> i64 {
+    if !offset_secs.is_finite() {
+        return 0;
+    }
+    let offset_secs = offset_secs.clamp(-MAX_START_OFFSET_SECS, MAX_START_OFFSET_SECS);
+    (offset_secs * sample_rate as f64).round() as i64
+}
+
+fn duration_from_seconds(seconds: f64) -> Option<Duration> {
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return None;
+    }
+
+    // a bogus time base can push the value past what Duration holds
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
 #[cfg(feature = "ffmpeg-tracing")]
 pub fn install_ffmpeg_tracing(level: ffmpeg::log::Level) -> Result<(), ffmpeg::Error> {
     static FFMPEG_TRACING_INIT: OnceLock<()> = OnceLock::new();
@@ -107,3 +198,38 @@ unsafe extern "C" fn ffmpeg_tracing_callback(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // minimised from the fuzz target: the largest timestamp over a time base of
+    // i32::MAX seconds per tick
+    #[test]
+    fn bogus_time_bases_have_no_duration() {
+        let time_base = ffmpeg::Rational::new(i32::MAX, 1);
+        assert_eq!(rational_units_to_duration(i64::MAX, time_base), None);
+        assert_eq!(
+            rational_units_to_duration(90_000, ffmpeg::Rational::new(1, 90_000)),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    // minimised from the fuzz target: a start time of i64::MIN ticks used to saturate
+    // the sample count, overflowing its negation, and i64::MAX asked for years of padding
+    #[test]
+    fn bogus_start_times_are_clamped() {
+        let time_base = f64::from(ffmpeg::Rational::new(i32::MAX, 1));
+        assert_eq!(
+            start_offset_samples(i64::MIN as f64 * time_base, 48000),
+            -600 * 48000
+        );
+        assert_eq!(
+            start_offset_samples(i64::MAX as f64 * time_base, 48000),
+            600 * 48000
+        );
+        assert_eq!(start_offset_samples(f64::NAN, 48000), 0);
+        assert_eq!(start_offset_samples(0.2, 48000), 9600);
+        assert_eq!(start_offset_samples(-0.0125, 48000), -600);
+    }
+}
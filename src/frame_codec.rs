@@ -0,0 +1,157 @@
+use crate::silk::{MAX_PACKET_BYTES, SILK_HEADER, TENCENT_PREFIX};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
+
+const FRAME_LEN_SIZE: usize = 2;
+const TERMINATOR: i16 = -1;
+
+#[derive(Error, Debug)]
+pub enum SilkFrameError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("stream does not start with the silk header")]
+    InvalidHeader,
+    #[error("frame length {0} is invalid")]
+    InvalidFrameLength(i16),
+    #[error("frame of {len} bytes exceeds the limit of {max} bytes")]
+    FrameTooLarge { len: usize, max: usize },
+}
+
+/// [`Decoder`]/[`Encoder`] for the length-prefixed packets of a silk stream (`i16` LE
+/// length + payload), for sending individual packets through `Framed`. Decoding stops
+/// at the `-1` terminator, frames longer than `max_frame_len` are rejected.
+#[derive(Debug, Clone)]
+pub struct SilkFrameCodec {
+    header: bool,
+    tencent: bool,
+    max_frame_len: usize,
+    header_read: bool,
+    header_written: bool,
+    finished: bool,
+}
+
+impl Default for SilkFrameCodec {
+    fn default() -> Self {
+        Self {
+            header: false,
+            tencent: false,
+            max_frame_len: MAX_PACKET_BYTES,
+            header_read: false,
+            header_written: false,
+            finished: false,
+        }
+    }
+}
+
+impl SilkFrameCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects the `#!SILK_V3` header once at the start of the incoming stream and
+    /// writes it before the first outgoing frame.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Writes the tencent prefix before the header, incoming streams may have it or not.
+    pub fn tencent(mut self, tencent: bool) -> Self {
+        self.tencent = tencent;
+        self
+    }
+
+    /// Maximum payload length accepted from the peer (and sent), capped at `i16::MAX`.
+    /// Defaults to 1024 bytes, the longest packet the decoder takes.
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len.min(i16::MAX as usize);
+        self
+    }
+
+    fn read_header(&mut self, src: &mut BytesMut) -> Result<bool, SilkFrameError> {
+        let prefix = usize::from(src.first() == Some(&TENCENT_PREFIX));
+        let received = &src[prefix..];
+        if received.len() < SILK_HEADER.len() {
+            if !SILK_HEADER.starts_with(received) {
+                return Err(SilkFrameError::InvalidHeader);
+            }
+            return Ok(false);
+        }
+        if !received.starts_with(SILK_HEADER) {
+            return Err(SilkFrameError::InvalidHeader);
+        }
+        src.advance(prefix + SILK_HEADER.len());
+        self.header_read = true;
+        Ok(true)
+    }
+}
+
+impl Decoder for SilkFrameCodec {
+    type Item = Bytes;
+    type Error = SilkFrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, SilkFrameError> {
+        if self.finished {
+            // anything after the terminator is not part of the stream
+            src.clear();
+            return Ok(None);
+        }
+        if self.header && !self.header_read && !self.read_header(src)? {
+            return Ok(None);
+        }
+        if src.len() < FRAME_LEN_SIZE {
+            return Ok(None);
+        }
+
+        let len = i16::from_le_bytes([src[0], src[1]]);
+        if len == TERMINATOR {
+            self.finished = true;
+            src.clear();
+            return Ok(None);
+        }
+        if len < 0 {
+            return Err(SilkFrameError::InvalidFrameLength(len));
+        }
+        let len = len as usize;
+        if len > self.max_frame_len {
+            return Err(SilkFrameError::FrameTooLarge {
+                len,
+                max: self.max_frame_len,
+            });
+        }
+        if src.len() < FRAME_LEN_SIZE + len {
+            src.reserve(FRAME_LEN_SIZE + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(FRAME_LEN_SIZE);
+        Ok(Some(src.split_to(len).freeze()))
+    }
+}
+
+impl Encoder<Bytes> for SilkFrameCodec {
+    type Error = SilkFrameError;
+
+    fn encode(&mut self, frame: Bytes, dst: &mut BytesMut) -> Result<(), SilkFrameError> {
+        if frame.len() > self.max_frame_len {
+            return Err(SilkFrameError::FrameTooLarge {
+                len: frame.len(),
+                max: self.max_frame_len,
+            });
+        }
+        if self.header && !self.header_written {
+            if self.tencent {
+                dst.put_u8(TENCENT_PREFIX);
+            }
+            dst.extend_from_slice(SILK_HEADER);
+            self.header_written = true;
+        }
+
+        dst.reserve(FRAME_LEN_SIZE + frame.len());
+        dst.put_i16_le(frame.len() as i16);
+        dst.extend_from_slice(&frame);
+        Ok(())
+    }
+}
@@ -1,6 +1,85 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 mod silk;
-pub use silk::{SilkError, decode_silk, encode_silk};
+pub use silk::{
+    CorruptPacketFill, DecodeReport, EncodeProgress, EncodeStats, EncoderStatus, InputReport,
+    NormalizeMode, SampleRate, SilkDecodeOptions, SilkDecoder, SilkEncodeOptions, SilkEncoder,
+    SilkEncoderHandle, SilkError, SilkFrame, SilkFrameIter, SilkHeaderVariant, SilkInfo,
+    SilkPreset, analyze_pcm, assemble_silk, decode_silk, decode_silk_f32,
+    decode_silk_f32_with_options, decode_silk_i16, decode_silk_i16_with_options, decode_silk_into,
+    decode_silk_packets, decode_silk_prefix, decode_silk_with_options, decode_silk_with_report,
+    encode_silk_cancellable, encode_silk_f32_with_options, encode_silk_from_iter,
+    encode_silk_multi, encode_silk_packets, encode_silk_preset, encode_silk_samples_with_options,
+    encode_silk_to_size, encode_silk_with_callback, encode_silk_with_options,
+    encode_silk_with_progress, encode_silk_with_stats, estimate_encoded_size,
+    estimate_encoded_size_with_options, estimate_silk_size, max_bitrate_for_size, pad_silk,
+    probe_silk, resample_silk, silk_duration, silk_waveform, transcode_silk,
+};
+
+#[allow(deprecated)]
+pub use silk::encode_silk;
+
+mod sniff;
+pub use sniff::{DetectedFormat, detect_audio_format, detect_silk_variant, is_silk};
+
+#[cfg(feature = "std")]
+mod aiff;
+#[cfg(feature = "std")]
+pub use aiff::AiffWriter;
+#[cfg(feature = "std")]
+mod quality;
+#[cfg(feature = "std")]
+pub use quality::{QualityReport, measure_roundtrip_quality};
+#[cfg(feature = "std")]
+mod silk_reader;
+#[cfg(feature = "std")]
+pub use silk_reader::SilkReader;
+#[cfg(feature = "std")]
+mod silk_writer;
+#[cfg(feature = "std")]
+pub use silk_writer::{SilkWriter, decode_silk_to_writer, encode_silk_stream};
+#[cfg(feature = "std")]
+mod testsignal;
+#[cfg(feature = "std")]
+pub use testsignal::{TestSignal, Waveform};
+#[cfg(feature = "std")]
+mod transcode;
+#[cfg(feature = "std")]
+pub use transcode::{
+    StereoMergeOptions, TranscodeError, TranscodeOptions, merge_silk_to_stereo_wav,
+    merge_silk_to_stereo_wav_with_options, transcode,
+};
+#[cfg(feature = "std")]
+mod vad;
+#[cfg(feature = "std")]
+pub use vad::{SpeechDetectionOptions, SpeechDetector, detect_speech_segments_pcm};
+#[cfg(feature = "std")]
+mod wav;
+#[cfg(feature = "std")]
+pub use wav::{
+    WavError, WavInfo, WavPcm, WavWriter, decode_silk_to_wav, decode_silk_to_wav_with_options,
+    encode_wav_to_silk, parse_wav,
+};
+
+#[cfg(feature = "dasp")]
+mod dasp;
+#[cfg(feature = "dasp")]
+pub use dasp::{SilkSignal, encode_signal, encode_silk_auto};
+
+#[cfg(feature = "rayon")]
+mod batch;
+#[cfg(feature = "rayon")]
+pub use batch::encode_silk_batch;
+
+#[cfg(feature = "async")]
+mod frame_codec;
+#[cfg(feature = "async")]
+pub use frame_codec::{SilkFrameCodec, SilkFrameError};
 
+#[cfg(feature = "denoise")]
+mod denoise;
 #[cfg(feature = "ffmpeg")]
 mod ffmpeg_utils;
 #[cfg(feature = "ffmpeg")]
@@ -11,6 +90,10 @@ mod video;
 #[cfg(feature = "ffmpeg-tracing")]
 pub use ffmpeg_utils::install_ffmpeg_tracing;
 #[cfg(feature = "ffmpeg")]
-pub use pcm::{AudioConverter, PcmError, convert_audio_to_pcm};
+pub use pcm::{
+    AudioConverter, AudioInfo, ChapterConversion, ConversionStats, ConversionWarning,
+    ConvertedChapter, FormatChangePolicy, OutputContainer, PcmError, PcmReader, PcmSegment,
+    RawChunk, ReplayGainMode, convert_audio_to_pcm, detect_speech_segments,
+};
 #[cfg(feature = "ffmpeg")]
 pub use video::{VideoError, VideoMetadata, get_video_metadata, save_video_first_frame_png};
@@ -1,8 +1,22 @@
-use crate::ffmpeg_utils::ensure_ffmpeg_initialized;
+use crate::aiff::AiffWriter;
+#[cfg(feature = "denoise")]
+use crate::denoise::{DENOISE_SAMPLE_RATE, Denoiser};
+use crate::ffmpeg_utils::{
+    ensure_ffmpeg_initialized, format_duration_to_duration, start_offset_samples,
+    stream_duration_to_duration,
+};
+use crate::silk::db_to_gain;
+use crate::vad::{SpeechDetectionOptions, SpeechDetector};
+use crate::wav::{WavInfo, WavWriter};
 use ffmpeg_next as ffmpeg;
+use std::cell::Cell;
 use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use thiserror::Error;
 
 const FILTER_INPUT_NAME: &str = "in";
@@ -12,7 +26,44 @@ const TARGET_CHANNEL_LAYOUT: ffmpeg::ChannelLayout = ffmpeg::ChannelLayout::MONO
 const TARGET_SAMPLE_FORMAT: ffmpeg::format::Sample =
     ffmpeg::format::Sample::I16(ffmpeg::format::sample::Type::Packed);
 const PCM_BYTES_PER_SAMPLE: usize = 2;
+const NANOS_PER_SEC: u128 = 1_000_000_000;
 const FILTER_SPEC: &str = "aformat=sample_fmts=s16:sample_rates=24000:channel_layouts=mono";
+const PLANAR_SAMPLE_FORMAT: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar);
+const PLANAR_FILTER_SPEC: &str = "aformat=sample_fmts=fltp:sample_rates=24000";
+// converts the sample format only, so raw chunks keep the decoded rate and length
+const RAW_FILTER_SPEC: &str = "aformat=sample_fmts=fltp";
+const MIXDOWN_FILTER_SPEC: &str = "aformat=channel_layouts=mono";
+const LIMITER_FORMAT_SPEC: &str = "aformat=sample_rates=24000:channel_layouts=mono";
+const TARGET_SPEC: PcmSpec = PcmSpec {
+    sample_rate: TARGET_SAMPLE_RATE,
+    channel_layout: TARGET_CHANNEL_LAYOUT,
+};
+const EQ_LOW_SHELF_FREQUENCY: u32 = 200;
+const EQ_PRESENCE_FREQUENCY: u32 = 3000;
+const EQ_HIGH_SHELF_FREQUENCY: u32 = 6000;
+const SPEECH_COMPRESSOR_THRESHOLD_DB: f32 = -18.0;
+const SPEECH_COMPRESSOR_RATIO: f32 = 3.0;
+const SPEECH_COMPRESSOR_ATTACK_MS: f32 = 10.0;
+const SPEECH_COMPRESSOR_RELEASE_MS: f32 = 150.0;
+const SPEECH_COMPRESSOR_MAKEUP_DB: f32 = 3.0;
+const LIMITER_LOOKAHEAD_MS: f32 = 5.0;
+const LIMITER_MIN_LIMIT: f32 = 0.0625;
+// running peak of the signal entering the limiter, exported in the frame metadata
+const LIMITER_METER_SPEC: &str =
+    "astats=metadata=1:reset=0:measure_perchannel=none:measure_overall=Peak_level";
+const LIMITER_METER_PEAK_KEY: &str = "lavfi.astats.Overall.Peak_level";
+const LIVE_RECONNECT_DELAY_MAX_SECS: &str = "5";
+const MIN_TEMPO: f32 = 0.25;
+const MAX_TEMPO: f32 = 4.0;
+// range of a single atempo instance that keeps its default speech-sized window
+const ATEMPO_MIN_STEP: f32 = 0.5;
+const ATEMPO_MAX_STEP: f32 = 2.0;
+#[cfg(feature = "denoise")]
+const DENOISE_SAMPLE_FORMAT: ffmpeg::format::Sample =
+    ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed);
+#[cfg(feature = "denoise")]
+const DENOISE_FILTER_SPEC: &str = "aformat=sample_fmts=flt:sample_rates=48000:channel_layouts=mono";
 
 #[derive(Error, Debug)]
 pub enum PcmError {
@@ -27,71 +78,1413 @@ pub enum PcmError {
     #[error("filter context `{0}` not found")]
     MissingFilterContext(&'static str),
     #[error(
-        "unexpected filtered pcm frame: format={format}, sample_rate={sample_rate}, channels={channels}; expected format=s16, sample_rate=24000, channels=1"
+        "unexpected filtered pcm frame: format={format}, sample_rate={sample_rate}, channels={channels}"
     )]
     UnexpectedFilteredFrame {
         format: &'static str,
         sample_rate: u32,
         channels: u16,
     },
+    #[error("audio format changed mid-stream while keeping the native spec")]
+    FormatChanged,
+    #[error("invalid downmix matrix: {0}")]
+    InvalidDownmixMatrix(String),
     #[error("invalid filtered pcm frame: expected at least {expected} bytes, got {actual} bytes")]
     InvalidFilteredFrame { expected: usize, actual: usize },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputContainer {
+    /// Headerless s16le samples.
+    #[default]
+    Raw,
+    Wav,
+    Aiff,
+}
+
+/// What a conversion with [`AudioConverter::with_native_spec`] does when the source
+/// changes its sample rate or channel layout after the first decoded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FormatChangePolicy {
+    /// Fails with [`PcmError::FormatChanged`].
+    #[default]
+    Error,
+    /// Carries on at the new spec and starts a new entry of
+    /// [`ConversionStats::segments`]. WAV and AIFF outputs hold a single spec and
+    /// still fail.
+    Segment,
+}
+
+/// A run of the output at one spec, see [`FormatChangePolicy::Segment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmSegment {
+    /// Output offset of the first byte at this spec.
+    pub start_byte: u64,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertedChapter {
+    pub title: Option<String>,
+    pub duration: Duration,
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterConversion {
+    pub chapters: Vec<ConvertedChapter>,
+    /// `false` when the input has no chapter metadata and was converted as a single output.
+    pub chapters_found: bool,
+}
+
+/// What one conversion produced, returned by the `convert_*` calls and
+/// [`PcmReader::stats`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConversionStats {
+    /// Spec of the written samples, the source one with
+    /// [`AudioConverter::with_native_spec`]. The spec of the first segment when the
+    /// output has several.
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// s16le bytes written, the figure [`AudioConverter::estimate_output_len`] predicts.
+    pub output_bytes: u64,
+    /// Gain applied from the ReplayGain tags after the peak cap.
+    pub replaygain_db: Option<f32>,
+    /// Start of the audio track relative to the container in seconds, negative for
+    /// edit lists starting before it. Only applied with
+    /// [`AudioConverter::with_preserve_start_offset`].
+    pub start_offset_secs: f64,
+    /// Largest gain reduction of the limiter in dB, from the peak entering it.
+    pub max_gain_reduction_db: f32,
+    /// Specs of the output in order with [`FormatChangePolicy::Segment`], the first
+    /// one starts at byte 0. Empty with the other policies.
+    pub segments: Vec<PcmSegment>,
+    pub warnings: Vec<ConversionWarning>,
+}
+
+impl ConversionStats {
+    fn record_frame(
+        &mut self,
+        frame: &ffmpeg::util::frame::Audio,
+        written: usize,
+        limiter: Option<Limiter>,
+    ) {
+        self.output_bytes += written as u64;
+        if let Some(limiter) = limiter
+            && let Some(peak_db) = frame
+                .metadata()
+                .get(LIMITER_METER_PEAK_KEY)
+                .and_then(|peak| peak.parse::<f32>().ok())
+                .filter(|peak| peak.is_finite())
+        {
+            self.max_gain_reduction_db =
+                self.max_gain_reduction_db.max(peak_db - limiter.limit_db());
+        }
+    }
+
+    /// Moves `spec` to the one of `frame` when they differ, starting a new segment.
+    fn follow_segment(&mut self, spec: &mut PcmSpec, frame: &ffmpeg::util::frame::Audio) {
+        let frame_spec = PcmSpec::of_frame(frame);
+        if frame_spec.sample_rate == spec.sample_rate && frame_spec.channels() == spec.channels() {
+            return;
+        }
+        *spec = frame_spec;
+        self.segments.push(PcmSegment {
+            start_byte: self.output_bytes,
+            sample_rate: spec.sample_rate,
+            channels: spec.channels(),
+        });
+    }
+}
+
+/// Source problems the conversion worked around instead of failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionWarning {
+    /// The stream declared `declared` channels but the decoder delivered `decoded`,
+    /// the frames were converted with the channels they actually hold.
+    ChannelMismatch { declared: u16, decoded: u16 },
+}
+
+/// One decoded frame before any of the converter's processing, as f32 planes at the
+/// source rate and channel count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawChunk {
+    pub planes: Vec<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+impl RawChunk {
+    pub fn channels(&self) -> usize {
+        self.planes.len()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.planes.first().map_or(0, Vec::len)
+    }
+}
+
+/// Where a conversion stops before the source ends: the max duration of the output
+/// and the cancel flag. The decode loops check it before every packet.
+#[derive(Debug, Default)]
+struct OutputLimit {
+    // output time still allowed in nanoseconds, `None` without a max duration
+    remaining: Cell<Option<u128>>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl OutputLimit {
+    fn reached(&self) -> bool {
+        self.remaining.get() == Some(0)
+            || self
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Takes up to `frames` sample frames at `sample_rate` from the budget, returns
+    /// how many fit. The budget is kept in time so it survives spec changes.
+    fn take(&self, frames: usize, sample_rate: u32) -> usize {
+        let Some(remaining) = self.remaining.get() else {
+            return frames;
+        };
+        let sample_rate = sample_rate.max(1) as u128;
+        let fit = remaining * sample_rate / NANOS_PER_SEC;
+        if fit < frames as u128 {
+            // the budget ends inside this frame
+            self.remaining.set(Some(0));
+            return fit as usize;
+        }
+        self.remaining.set(Some(
+            remaining - frames as u128 * NANOS_PER_SEC / sample_rate,
+        ));
+        frames
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PcmSpec {
+    sample_rate: u32,
+    channel_layout: ffmpeg::ChannelLayout,
+}
+
+impl PcmSpec {
+    fn of_frame(frame: &ffmpeg::util::frame::Audio) -> Self {
+        Self {
+            sample_rate: frame.rate(),
+            channel_layout: channel_layout_or_default(frame.channel_layout(), frame.channels()),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channel_layout.channels() as u16
+    }
+
+    fn bytes_per_second(&self) -> u64 {
+        self.sample_rate as u64 * self.channels() as u64 * PCM_BYTES_PER_SAMPLE as u64
+    }
+
+    fn filter(&self) -> String {
+        format!(
+            "aformat=sample_fmts=s16:sample_rates={}:channel_layouts=0x{:x}",
+            self.sample_rate,
+            self.channel_layout.bits()
+        )
+    }
+}
+
+struct ChapterStart {
+    title: Option<String>,
+    seconds: f64,
+}
+
+struct ChapterSplitter<'a> {
+    output_dir: &'a Path,
+    filter_spec: String,
+    spec: PcmSpec,
+    live_stream: bool,
+    limit: OutputLimit,
+    starts: Vec<ChapterStart>,
+    index: usize,
+    position: f64,
+    filter_graph: ffmpeg::filter::Graph,
+    #[cfg(feature = "denoise")]
+    denoise_filters: Option<Vec<String>>,
+    #[cfg(feature = "denoise")]
+    denoise: Option<DenoiseStage>,
+    output: BufWriter<File>,
+    converted: Vec<ConvertedChapter>,
+}
+
+impl<'a> ChapterSplitter<'a> {
+    fn new(
+        converter: &AudioConverter,
+        input: &AudioInput,
+        output_dir: &'a Path,
+        starts: Vec<ChapterStart>,
+    ) -> Result<Self, PcmError> {
+        let filter_spec = converter.input_filter_spec(input)?;
+        let spec = converter.output_spec(input);
+        let filter_graph = build_pcm_filter_graph(&input.decoder, &filter_spec)?;
+        #[cfg(feature = "denoise")]
+        let denoise_filters = converter
+            .denoise
+            .then(|| converter.processing_filters(spec));
+        #[cfg(feature = "denoise")]
+        let denoise = denoise_filters
+            .as_deref()
+            .map(DenoiseStage::new)
+            .transpose()?;
+        let output = BufWriter::new(File::create(chapter_output_path(output_dir, 0))?);
+        Ok(Self {
+            output_dir,
+            filter_spec,
+            spec,
+            live_stream: converter.live_stream,
+            limit: converter.output_limit(),
+            starts,
+            index: 0,
+            position: 0.0,
+            filter_graph,
+            #[cfg(feature = "denoise")]
+            denoise_filters,
+            #[cfg(feature = "denoise")]
+            denoise,
+            output,
+            converted: vec![],
+        })
+    }
+
+    fn write_frame(
+        &mut self,
+        decoder: &ffmpeg::codec::decoder::Audio,
+        frame: &ffmpeg::util::frame::Audio,
+    ) -> Result<(), PcmError> {
+        while self
+            .starts
+            .get(self.index + 1)
+            .is_some_and(|next| self.position >= next.seconds)
+        {
+            self.finish_chapter()?;
+            self.index += 1;
+            self.filter_graph = build_pcm_filter_graph(decoder, &self.filter_spec)?;
+            #[cfg(feature = "denoise")]
+            {
+                self.denoise = self
+                    .denoise_filters
+                    .as_deref()
+                    .map(DenoiseStage::new)
+                    .transpose()?;
+            }
+            self.output = BufWriter::new(File::create(chapter_output_path(
+                self.output_dir,
+                self.index,
+            ))?);
+        }
+
+        add_frame_to_filter(&mut self.filter_graph, frame)?;
+        self.drain(false)?;
+        if self.live_stream {
+            self.output.flush()?;
+        }
+        if frame.rate() > 0 {
+            self.position += frame.samples() as f64 / frame.rate() as f64;
+        }
+        Ok(())
+    }
+
+    /// Writes the frames ready in the filter graph, through the denoiser when enabled.
+    fn drain(&mut self, flush: bool) -> Result<(), PcmError> {
+        let Self {
+            spec,
+            limit,
+            filter_graph,
+            #[cfg(feature = "denoise")]
+            denoise,
+            output,
+            ..
+        } = self;
+        let mut write = |frame: &ffmpeg::util::frame::Audio| {
+            write_pcm_frame(&mut *output, frame, *spec, limit)?;
+            Ok(())
+        };
+        let mut on_frame = |frame: &ffmpeg::util::frame::Audio| {
+            #[cfg(feature = "denoise")]
+            if let Some(stage) = denoise.as_mut() {
+                return stage.push(frame, &mut write);
+            }
+            write(frame)
+        };
+        if flush {
+            flush_filter_graph(filter_graph, &mut on_frame)
+        } else {
+            drain_filtered_frames(filter_graph, &mut on_frame)
+        }
+    }
+
+    fn finish_chapter(&mut self) -> Result<(), PcmError> {
+        self.drain(true)?;
+        #[cfg(feature = "denoise")]
+        if let Some(stage) = self.denoise.take() {
+            let (output, spec, limit) = (&mut self.output, self.spec, &self.limit);
+            stage.finish(&mut |frame| {
+                write_pcm_frame(&mut *output, frame, spec, limit)?;
+                Ok(())
+            })?;
+        }
+        self.output.flush()?;
+
+        let written = self.output.get_ref().metadata()?.len();
+        let bytes_per_second = self.spec.bytes_per_second().max(1);
+        self.converted.push(ConvertedChapter {
+            title: self
+                .starts
+                .get(self.index)
+                .and_then(|start| start.title.clone()),
+            duration: Duration::from_secs_f64(written as f64 / bytes_per_second as f64),
+            output_path: chapter_output_path(self.output_dir, self.index),
+        });
+        Ok(())
+    }
+}
+
+fn chapter_output_path(output_dir: &Path, index: usize) -> PathBuf {
+    output_dir.join(format!("{:03}.pcm", index + 1))
+}
+
+fn input_chapter_starts(format_context: &ffmpeg::format::context::Input) -> Vec<ChapterStart> {
+    let mut starts = format_context
+        .chapters()
+        .map(|chapter| ChapterStart {
+            title: chapter.metadata().get("title").map(str::to_owned),
+            seconds: chapter.start() as f64 * f64::from(chapter.time_base()),
+        })
+        .collect::<Vec<_>>();
+    starts.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+    starts
+}
+
 struct AudioInput {
     format_context: ffmpeg::format::context::Input,
     stream_index: usize,
     decoder: ffmpeg::codec::decoder::Audio,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct AudioConverter;
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Equalizer {
+    low_gain_db: f32,
+    mid_gain_db: f32,
+    high_gain_db: f32,
+}
+
+impl Equalizer {
+    fn filters(&self) -> Vec<String> {
+        let mut filters = vec![];
+        if self.low_gain_db != 0.0 {
+            filters.push(format!(
+                "lowshelf=f={EQ_LOW_SHELF_FREQUENCY}:t=q:w=0.707:g={}",
+                self.low_gain_db
+            ));
+        }
+        if self.mid_gain_db != 0.0 {
+            filters.push(format!(
+                "equalizer=f={EQ_PRESENCE_FREQUENCY}:t=q:w=1:g={}",
+                self.mid_gain_db
+            ));
+        }
+        if self.high_gain_db != 0.0 {
+            filters.push(format!(
+                "highshelf=f={EQ_HIGH_SHELF_FREQUENCY}:t=q:w=0.707:g={}",
+                self.high_gain_db
+            ));
+        }
+        filters
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Compressor {
+    threshold_db: f32,
+    ratio: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    makeup_db: f32,
+}
+
+impl Compressor {
+    fn filter(&self) -> String {
+        format!(
+            "acompressor=threshold={}:ratio={}:attack={}:release={}:makeup={}",
+            db_to_gain(self.threshold_db).clamp(0.000_976_563, 1.0),
+            self.ratio.clamp(1.0, 20.0),
+            self.attack_ms.clamp(0.01, 2000.0),
+            self.release_ms.clamp(0.01, 9000.0),
+            db_to_gain(self.makeup_db).clamp(1.0, 64.0)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Limiter {
+    threshold_dbfs: f32,
+    release_ms: f32,
+}
+
+impl Limiter {
+    fn limit(&self) -> f32 {
+        db_to_gain(self.threshold_dbfs).clamp(LIMITER_MIN_LIMIT, 1.0)
+    }
+
+    fn limit_db(&self) -> f32 {
+        20.0 * self.limit().log10()
+    }
+
+    fn filter(&self) -> String {
+        let limit = self.limit();
+        // level=0 disables auto leveling, latency=1 compensates the lookahead delay
+        format!(
+            "alimiter=limit={limit}:attack={LIMITER_LOOKAHEAD_MS}:release={}:level=0:latency=1",
+            self.release_ms.clamp(1.0, 8000.0)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    fn tag_keys(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Self::Off => None,
+            Self::Track => Some(("REPLAYGAIN_TRACK_GAIN", "REPLAYGAIN_TRACK_PEAK")),
+            Self::Album => Some(("REPLAYGAIN_ALBUM_GAIN", "REPLAYGAIN_ALBUM_PEAK")),
+        }
+    }
+}
+
+fn replaygain_filter(mode: ReplayGainMode, input: &AudioInput) -> Option<String> {
+    replaygain_db(mode, input).map(|gain_db| format!("volume={gain_db}dB"))
+}
+
+fn replaygain_db(mode: ReplayGainMode, input: &AudioInput) -> Option<f32> {
+    let (gain_key, peak_key) = mode.tag_keys()?;
+    let mut gain_db = input_tag(input, gain_key).and_then(|gain| parse_replaygain_value(&gain))?;
+    if let Some(peak) = input_tag(input, peak_key).and_then(|peak| parse_replaygain_value(&peak))
+        && peak > 0.0
+    {
+        // never push the stored peak above full scale
+        gain_db = gain_db.min(-20.0 * peak.log10());
+    }
+    (gain_db != 0.0).then_some(gain_db)
+}
+
+fn start_offset_filter(input: &AudioInput) -> Option<String> {
+    let samples = start_offset_samples(start_offset_secs(input)?, input.decoder.rate());
+    match samples {
+        0 => None,
+        1.. => Some(format!("adelay=delays={samples}S:all=1")),
+        _ => Some(format!("atrim=start_sample={}", samples.unsigned_abs())),
+    }
+}
+
+fn start_offset_secs(input: &AudioInput) -> Option<f64> {
+    let stream = input.format_context.stream(input.stream_index)?;
+    let stream_start = stream.start_time();
+    if stream_start == ffmpeg::ffi::AV_NOPTS_VALUE {
+        return None;
+    }
+
+    // transport streams start at arbitrary clocks, mp4 edit lists can start below zero.
+    // ffmpeg-next has no accessor for the container start time
+    let format_start = match unsafe { (*input.format_context.as_ptr()).start_time } {
+        ffmpeg::ffi::AV_NOPTS_VALUE => 0.0,
+        start => (start as f64 / ffmpeg::ffi::AV_TIME_BASE as f64).max(0.0),
+    };
+    Some(stream_start as f64 * f64::from(stream.time_base()) - format_start)
+        .filter(|offset| offset.is_finite())
+}
+
+fn input_tag(input: &AudioInput, key: &str) -> Option<String> {
+    let stream_tag = input
+        .format_context
+        .stream(input.stream_index)
+        .and_then(|stream| stream.metadata().get(key).map(str::to_owned));
+    stream_tag.or_else(|| input.format_context.metadata().get(key).map(str::to_owned))
+}
+
+fn input_wav_info(input: &AudioInput) -> WavInfo {
+    WavInfo {
+        title: input_tag(input, "title"),
+        artist: input_tag(input, "artist"),
+        album: input_tag(input, "album"),
+        comment: input_tag(input, "comment"),
+    }
+}
+
+fn parse_replaygain_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+}
+
+fn downmix_matrix_filter(matrix: &[Vec<f32>], source_channels: usize) -> Result<String, PcmError> {
+    if matrix.is_empty() {
+        return Err(PcmError::InvalidDownmixMatrix(
+            "matrix must have at least one output row".to_owned(),
+        ));
+    }
+
+    let mut outputs = vec![];
+    for (output_channel, row) in matrix.iter().enumerate() {
+        if row.len() != source_channels {
+            return Err(PcmError::InvalidDownmixMatrix(format!(
+                "row {output_channel} has {} coefficients but the source has {source_channels} channels",
+                row.len()
+            )));
+        }
+        if let Some(column) = row.iter().position(|gain| !gain.is_finite()) {
+            return Err(PcmError::InvalidDownmixMatrix(format!(
+                "coefficient at row {output_channel}, column {column} is not finite"
+            )));
+        }
+
+        let terms = row
+            .iter()
+            .enumerate()
+            .map(|(source_channel, gain)| format!("{gain}*c{source_channel}"))
+            .collect::<Vec<_>>()
+            .join("+");
+        outputs.push(format!("c{output_channel}={terms}"));
+    }
+
+    let layout = if matrix.len() == 1 {
+        "mono".to_owned()
+    } else {
+        format!("{}c", matrix.len())
+    };
+    Ok(format!("pan={layout}|{}", outputs.join("|")))
+}
+
+fn tempo_filters(tempo: f32) -> Vec<String> {
+    let mut filters = vec![];
+    let mut remaining = tempo;
+    while remaining > ATEMPO_MAX_STEP {
+        filters.push(format!("atempo={ATEMPO_MAX_STEP}"));
+        remaining /= ATEMPO_MAX_STEP;
+    }
+    while remaining < ATEMPO_MIN_STEP {
+        filters.push(format!("atempo={ATEMPO_MIN_STEP}"));
+        remaining /= ATEMPO_MIN_STEP;
+    }
+    filters.push(format!("atempo={remaining}"));
+    filters
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioConverter {
+    eq: Option<Equalizer>,
+    compressor: Option<Compressor>,
+    limiter: Option<Limiter>,
+    replaygain: ReplayGainMode,
+    downmix_matrix: Option<Vec<Vec<f32>>>,
+    wav_metadata: bool,
+    live_stream: bool,
+    max_duration: Option<Duration>,
+    cancel: Option<Arc<AtomicBool>>,
+    tempo: Option<f32>,
+    preserve_start_offset: bool,
+    native_spec: bool,
+    format_change_policy: FormatChangePolicy,
+    #[cfg(feature = "denoise")]
+    denoise: bool,
+}
 
 impl AudioConverter {
     pub fn new() -> Result<Self, PcmError> {
         ensure_ffmpeg_initialized()?;
-        Ok(Self)
+        Ok(Self {
+            eq: None,
+            compressor: None,
+            limiter: None,
+            replaygain: ReplayGainMode::Off,
+            downmix_matrix: None,
+            wav_metadata: false,
+            live_stream: false,
+            max_duration: None,
+            cancel: None,
+            tempo: None,
+            preserve_start_offset: false,
+            native_spec: false,
+            format_change_policy: FormatChangePolicy::Error,
+            #[cfg(feature = "denoise")]
+            denoise: false,
+        })
+    }
+
+    /// Applies the stored ReplayGain tag of the source, capped by its peak tag so
+    /// the gain never clips. Sources without the tags are left untouched.
+    pub fn with_replaygain(mut self, mode: ReplayGainMode) -> Self {
+        self.replaygain = mode;
+        self
+    }
+
+    /// Low shelf (200 Hz), presence peak (3 kHz) and high shelf (6 kHz), applied
+    /// after mixdown and before resampling. All-zero gains leave the output untouched.
+    pub fn with_eq(mut self, low_gain_db: f32, mid_gain_db: f32, high_gain_db: f32) -> Self {
+        self.eq = Some(Equalizer {
+            low_gain_db,
+            mid_gain_db,
+            high_gain_db,
+        });
+        self
+    }
+
+    /// Feed-forward compressor applied after the EQ and before the limiter.
+    pub fn with_compressor(
+        mut self,
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+    ) -> Self {
+        self.compressor = Some(Compressor {
+            threshold_db,
+            ratio,
+            attack_ms,
+            release_ms,
+            makeup_db,
+        });
+        self
+    }
+
+    /// Compressor with speech defaults: -18 dB threshold, 3:1 ratio, 10 ms attack,
+    /// 150 ms release and 3 dB makeup gain.
+    pub fn with_speech_compressor(self) -> Self {
+        self.with_compressor(
+            SPEECH_COMPRESSOR_THRESHOLD_DB,
+            SPEECH_COMPRESSOR_RATIO,
+            SPEECH_COMPRESSOR_ATTACK_MS,
+            SPEECH_COMPRESSOR_RELEASE_MS,
+            SPEECH_COMPRESSOR_MAKEUP_DB,
+        )
     }
 
-    pub fn convert_to_pcm<P, Q>(&self, input_path: P, output_path: Q) -> Result<(), PcmError>
+    /// Look-ahead peak limiter placed just before quantization, `threshold_dbfs` is
+    /// clamped to the -24..=0 dBFS range supported by ffmpeg's `alimiter`.
+    pub fn with_limiter(mut self, threshold_dbfs: f32, release_ms: f32) -> Self {
+        self.limiter = Some(Limiter {
+            threshold_dbfs,
+            release_ms,
+        });
+        self
+    }
+
+    /// Writes one numbered PCM file per chapter into `output_dir`, each converted
+    /// with the same processing as [`convert_to_pcm`](Self::convert_to_pcm).
+    pub fn convert_by_chapters<P, Q>(
+        &self,
+        input_path: P,
+        output_dir: Q,
+    ) -> Result<ChapterConversion, PcmError>
     where
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        let mut input = open_audio_input(input_path.as_ref())?;
-        let mut filter_graph = build_pcm_filter_graph(&input.decoder)?;
-        let output_file = File::create(output_path)?;
-        let mut output = BufWriter::new(output_file);
+        let mut input = self.open_input(input_path.as_ref())?;
+        let mut starts = input_chapter_starts(&input.format_context);
+        let chapters_found = !starts.is_empty();
+        if !chapters_found {
+            starts.push(ChapterStart {
+                title: None,
+                seconds: 0.0,
+            });
+        }
 
+        let mut splitter = ChapterSplitter::new(self, &input, output_dir.as_ref(), starts)?;
+        let mut decoded = ffmpeg::util::frame::Audio::empty();
         for (stream, packet) in input.format_context.packets() {
+            if splitter.limit.reached() {
+                break;
+            }
             if stream.index() != input.stream_index {
                 continue;
             }
 
             input.decoder.send_packet(&packet)?;
-            receive_decoded_frames(
-                &mut input.decoder,
-                &mut filter_graph,
-                &mut output,
-                TARGET_CHANNEL_LAYOUT,
-            )?;
+            while receive_decoded_frame(&mut input.decoder, &mut decoded)? {
+                splitter.write_frame(&input.decoder, &decoded)?;
+            }
         }
 
         input.decoder.send_eof()?;
-        receive_decoded_frames(
-            &mut input.decoder,
-            &mut filter_graph,
-            &mut output,
-            TARGET_CHANNEL_LAYOUT,
+        while receive_decoded_frame(&mut input.decoder, &mut decoded)? {
+            splitter.write_frame(&input.decoder, &decoded)?;
+        }
+        splitter.finish_chapter()?;
+
+        Ok(ChapterConversion {
+            chapters: splitter.converted,
+            chapters_found,
+        })
+    }
+
+    pub fn probe<P: AsRef<Path>>(&self, input_path: P) -> Result<AudioInfo, PcmError> {
+        let input = open_audio_input(input_path.as_ref())?;
+        let duration = input
+            .format_context
+            .stream(input.stream_index)
+            .and_then(|stream| stream_duration_to_duration(&stream))
+            .or_else(|| format_duration_to_duration(input.format_context.duration()));
+
+        Ok(AudioInfo {
+            sample_rate: input.decoder.rate(),
+            channels: input.decoder.channels(),
+            duration,
+        })
+    }
+
+    /// Expected PCM output size in bytes, `None` when the source duration is unknown.
+    /// [`ConversionStats::output_bytes`] reports what the conversion actually wrote.
+    pub fn estimate_output_len(&self, input_info: &AudioInfo) -> Option<u64> {
+        let (sample_rate, channels) = if self.native_spec {
+            (input_info.sample_rate, input_info.channels.max(1))
+        } else {
+            (TARGET_SAMPLE_RATE, TARGET_SPEC.channels())
+        };
+        let duration = input_info.duration?.as_secs_f64() / self.tempo.unwrap_or(1.0) as f64;
+        let samples = (duration * sample_rate as f64).round() as u64;
+        Some(samples.saturating_mul(channels as u64 * PCM_BYTES_PER_SAMPLE as u64))
+    }
+
+    pub fn estimate_output_len_for_path<P: AsRef<Path>>(
+        &self,
+        input_path: P,
+    ) -> Result<Option<u64>, PcmError> {
+        let input_info = self.probe(input_path)?;
+        Ok(self.estimate_output_len(&input_info))
+    }
+
+    /// Overrides the built-in downmix, rows are output channels and columns are the
+    /// source channels. The matrix is checked against the source layout on conversion.
+    pub fn with_downmix_matrix(mut self, matrix: Vec<Vec<f32>>) -> Self {
+        self.downmix_matrix = Some(matrix);
+        self
+    }
+
+    /// Copies the source title/artist/album/comment tags into a `LIST`/`INFO` chunk
+    /// of WAV outputs. Off by default so outputs stay byte-stable.
+    pub fn with_wav_metadata(mut self, wav_metadata: bool) -> Self {
+        self.wav_metadata = wav_metadata;
+        self
+    }
+
+    /// Time-stretches the mono signal with ffmpeg's WSOLA `atempo` so the pitch is
+    /// kept, `tempo` is clamped to 0.25..=4.0 and 1.5 plays back 1.5 times faster.
+    pub fn with_tempo(mut self, tempo: f32) -> Self {
+        self.tempo = Some(tempo.clamp(MIN_TEMPO, MAX_TEMPO));
+        self
+    }
+
+    /// Keeps the audio aligned with the other tracks of the source: a track starting
+    /// after the container start is padded with silence, one starting before it (negative
+    /// edit lists) is trimmed. Offsets beyond 10 minutes either way are capped there.
+    pub fn with_preserve_start_offset(mut self, preserve_start_offset: bool) -> Self {
+        self.preserve_start_offset = preserve_start_offset;
+        self
+    }
+
+    /// Keeps the source sample rate and channel layout instead of 24 kHz mono, the EQ
+    /// and tempo stages then run per channel. A format change after the first decoded
+    /// frame is handled per [`with_format_change_policy`](Self::with_format_change_policy);
+    /// [`PcmReader`] reports the current spec.
+    pub fn with_native_spec(mut self) -> Self {
+        self.native_spec = true;
+        self
+    }
+
+    /// What [`with_native_spec`](Self::with_native_spec) does on a mid-stream format
+    /// change, [`FormatChangePolicy::Error`] by default.
+    pub fn with_format_change_policy(mut self, policy: FormatChangePolicy) -> Self {
+        self.format_change_policy = policy;
+        self
+    }
+
+    fn segments(&self) -> bool {
+        self.native_spec && self.format_change_policy == FormatChangePolicy::Segment
+    }
+
+    fn conversion_stats(&self, input: &AudioInput) -> ConversionStats {
+        let spec = self.output_spec(input);
+        let segments = self.segments().then(|| PcmSegment {
+            start_byte: 0,
+            sample_rate: spec.sample_rate,
+            channels: spec.channels(),
+        });
+        ConversionStats {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels(),
+            segments: segments.into_iter().collect(),
+            replaygain_db: replaygain_db(self.replaygain, input),
+            start_offset_secs: start_offset_secs(input).unwrap_or(0.0),
+            ..ConversionStats::default()
+        }
+    }
+
+    fn output_spec(&self, input: &AudioInput) -> PcmSpec {
+        if !self.native_spec {
+            return TARGET_SPEC;
+        }
+        PcmSpec {
+            sample_rate: input.decoder.rate(),
+            channel_layout: decoder_input_channel_layout(&input.decoder),
+        }
+    }
+
+    /// Treats the input as unbounded (internet radio, live captures): network inputs
+    /// reconnect on drops and the output is flushed after every converted frame. Set
+    /// a [`with_max_duration`](Self::with_max_duration) or
+    /// [`with_cancel_flag`](Self::with_cancel_flag) to end the conversion.
+    pub fn with_live_stream(mut self, live_stream: bool) -> Self {
+        self.live_stream = live_stream;
+        self
+    }
+
+    /// Cuts the output at `max_duration` and stops decoding there, the output is
+    /// finished as if the source had ended.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Stops before the next packet once `cancel` is set, what was converted so far
+    /// is kept and the output is finished as if the source had ended.
+    pub fn with_cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    fn output_limit(&self) -> OutputLimit {
+        OutputLimit {
+            remaining: Cell::new(self.max_duration.map(|duration| duration.as_nanos())),
+            cancel: self.cancel.clone(),
+        }
+    }
+
+    fn open_input(&self, input_path: &Path) -> Result<AudioInput, PcmError> {
+        if !self.live_stream {
+            return open_audio_input(input_path);
+        }
+
+        open_audio_input_with_options(input_path, live_input_options())
+    }
+
+    /// Runs the RNNoise model over the mixed-down signal before the EQ, the signal
+    /// is resampled to 48 kHz for the model and back to 24 kHz afterwards.
+    #[cfg(feature = "denoise")]
+    pub fn with_denoise(mut self, denoise: bool) -> Self {
+        self.denoise = denoise;
+        self
+    }
+
+    fn source_filters(&self, input: &AudioInput) -> Result<Vec<String>, PcmError> {
+        let mut filters = vec![];
+        if self.preserve_start_offset
+            && let Some(start_offset) = start_offset_filter(input)
+        {
+            filters.push(start_offset);
+        }
+        if let Some(replaygain) = replaygain_filter(self.replaygain, input) {
+            filters.push(replaygain);
+        }
+        let source_channels = decoder_input_channel_layout(&input.decoder).channels() as usize;
+        filters.extend(self.downmix_filter(source_channels)?);
+        Ok(filters)
+    }
+
+    fn downmix_filter(&self, source_channels: usize) -> Result<Option<String>, PcmError> {
+        self.downmix_matrix
+            .as_ref()
+            .map(|matrix| downmix_matrix_filter(matrix, source_channels))
+            .transpose()
+    }
+
+    fn processing_filters(&self, spec: PcmSpec) -> Vec<String> {
+        let mut filters = vec![];
+        let eq_filters = self.eq.map(|eq| eq.filters()).unwrap_or_default();
+        let tempo = self.tempo.filter(|&tempo| tempo != 1.0);
+        if !self.native_spec && (!eq_filters.is_empty() || tempo.is_some()) {
+            filters.push(MIXDOWN_FILTER_SPEC.to_owned());
+        }
+        filters.extend(eq_filters);
+        if let Some(tempo) = tempo {
+            filters.extend(tempo_filters(tempo));
+        }
+        if let Some(compressor) = self.compressor {
+            filters.push(compressor.filter());
+        }
+        if let Some(limiter) = self.limiter {
+            if !self.native_spec {
+                filters.push(LIMITER_FORMAT_SPEC.to_owned());
+            }
+            filters.push(LIMITER_METER_SPEC.to_owned());
+            filters.push(limiter.filter());
+        }
+        if self.native_spec {
+            // pins the source spec, which also brings the denoised signal back to it
+            filters.push(spec.filter());
+        } else {
+            filters.push(FILTER_SPEC.to_owned());
+        }
+        filters
+    }
+
+    /// Spec of the graph fed by the decoder.
+    fn input_filter_spec(&self, input: &AudioInput) -> Result<String, PcmError> {
+        let mut filters = self.source_filters(input)?;
+        filters.extend(self.tail_filters(self.output_spec(input)));
+        Ok(filters.join(","))
+    }
+
+    /// Filters after the source ones, they stop at the 48 kHz model input when
+    /// denoising and the remaining stages run in the `DenoiseStage` graph.
+    fn tail_filters(&self, spec: PcmSpec) -> Vec<String> {
+        #[cfg(feature = "denoise")]
+        if self.denoise {
+            return vec![DENOISE_FILTER_SPEC.to_owned()];
+        }
+        self.processing_filters(spec)
+    }
+
+    fn graph_rebuild(&self, input: &AudioInput) -> GraphRebuild {
+        if !self.native_spec {
+            return GraphRebuild::Keep;
+        }
+        if self.format_change_policy == FormatChangePolicy::Error {
+            return GraphRebuild::Fail;
+        }
+        // the start offset only applies at the head of the output
+        let replaygain = replaygain_filter(self.replaygain, input);
+        let converter = self.clone();
+        GraphRebuild::Respec(Box::new(move |spec| {
+            let mut filters: Vec<String> = replaygain.iter().cloned().collect();
+            filters.extend(converter.downmix_filter(spec.channels() as usize)?);
+            filters.extend(converter.tail_filters(spec));
+            Ok(filters.join(","))
+        }))
+    }
+
+    #[cfg(feature = "denoise")]
+    fn denoise_stage(&self, input: &AudioInput) -> Result<Option<DenoiseStage>, PcmError> {
+        self.denoise
+            .then(|| DenoiseStage::new(&self.processing_filters(self.output_spec(input))))
+            .transpose()
+    }
+
+    fn process<F>(
+        &self,
+        input: &mut AudioInput,
+        limit: &OutputLimit,
+        on_frame: &mut F,
+    ) -> Result<Vec<ConversionWarning>, PcmError>
+    where
+        F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+    {
+        self.process_with_tap(input, None, limit, on_frame)
+    }
+
+    fn process_with_tap<F>(
+        &self,
+        input: &mut AudioInput,
+        tap: Option<&mut RawTap>,
+        limit: &OutputLimit,
+        on_frame: &mut F,
+    ) -> Result<Vec<ConversionWarning>, PcmError>
+    where
+        F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+    {
+        let filter_spec = self.input_filter_spec(input)?;
+        let rebuild = self.graph_rebuild(input);
+        #[cfg(feature = "denoise")]
+        if let Some(mut stage) = self.denoise_stage(input)? {
+            let warnings =
+                process_input_with_tap(input, &filter_spec, rebuild, tap, limit, &mut |frame| {
+                    stage.push(frame, on_frame)
+                })?;
+            stage.finish(on_frame)?;
+            return Ok(warnings);
+        }
+        process_input_with_tap(input, &filter_spec, rebuild, tap, limit, on_frame)
+    }
+
+    /// Calls `on_chunk` with every decoded frame before the converter touches it, for
+    /// custom DSP. The converted s16le output is written to `output` as usual, pass
+    /// `None` to skip the conversion when the callback is the only consumer.
+    /// Returning `ControlFlow::Break` stops decoding, the output then ends early.
+    pub fn convert_with_raw_access<P, F>(
+        &self,
+        input_path: P,
+        output: Option<&mut dyn Write>,
+        mut on_chunk: F,
+    ) -> Result<ConversionStats, PcmError>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&RawChunk) -> ControlFlow<()>,
+    {
+        let mut input = self.open_input(input_path.as_ref())?;
+        let mut stats = self.conversion_stats(&input);
+        let mut spec = self.output_spec(&input);
+        let segments = self.segments();
+        let limit = self.output_limit();
+        let mut tap = RawTap::new(&input.decoder, &mut on_chunk)?;
+        let Some(mut output) = output else {
+            tap.run(&mut input, &limit)?;
+            return Ok(stats);
+        };
+
+        let warnings = self.process_with_tap(&mut input, Some(&mut tap), &limit, &mut |frame| {
+            if segments {
+                stats.follow_segment(&mut spec, frame);
+            }
+            let written = write_pcm_frame(&mut output, frame, spec, &limit)?;
+            stats.record_frame(frame, written, self.limiter);
+            if self.live_stream && written > 0 {
+                output.flush()?;
+            }
+            Ok(())
+        })?;
+        Ok(ConversionStats { warnings, ..stats })
+    }
+
+    /// Pull-based conversion, packets are decoded lazily as the reader is drained.
+    pub fn reader<P: AsRef<Path>>(&self, input_path: P) -> Result<PcmReader, PcmError> {
+        let input = self.open_input(input_path.as_ref())?;
+        let filter_graph = DecoderGraph::new(
+            &input.decoder,
+            &self.input_filter_spec(&input)?,
+            self.graph_rebuild(&input),
         )?;
+        Ok(PcmReader {
+            spec: self.output_spec(&input),
+            segments: self.segments(),
+            stats: self.conversion_stats(&input),
+            limiter: self.limiter,
+            limit: self.output_limit(),
+            #[cfg(feature = "denoise")]
+            denoise: self.denoise_stage(&input)?,
+            input,
+            filter_graph,
+            buffer: vec![],
+            position: 0,
+            finished: false,
+        })
+    }
 
-        flush_filter_graph(&mut filter_graph, &mut output, TARGET_CHANNEL_LAYOUT)?;
+    pub fn convert_to_pcm<P, Q>(
+        &self,
+        input_path: P,
+        output_path: Q,
+    ) -> Result<ConversionStats, PcmError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let output_file = File::create(output_path)?;
+        let mut output = BufWriter::new(output_file);
+        let stats = self.convert_into(input_path.as_ref(), &mut output)?;
         output.flush()?;
-        Ok(())
+        Ok(stats)
+    }
+
+    pub fn convert_to_wav<P, Q>(
+        &self,
+        input_path: P,
+        output_path: Q,
+    ) -> Result<ConversionStats, PcmError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut input = self.open_input(input_path.as_ref())?;
+        let info = self.wav_metadata.then(|| input_wav_info(&input));
+
+        let spec = self.output_spec(&input);
+        let output = BufWriter::new(File::create(output_path)?);
+        let mut writer = WavWriter::new(output, spec.sample_rate, spec.channels(), info.as_ref())?;
+        let stats = self
+            .single_spec()
+            .convert_input_into(&mut input, &mut writer)?;
+        writer.finish()?.flush()?;
+        Ok(stats)
+    }
+
+    pub fn convert_to_aiff<P, Q>(
+        &self,
+        input_path: P,
+        output_path: Q,
+    ) -> Result<ConversionStats, PcmError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut input = self.open_input(input_path.as_ref())?;
+        let spec = self.output_spec(&input);
+        let output = BufWriter::new(File::create(output_path)?);
+        let mut writer = AiffWriter::new(output, spec.sample_rate, spec.channels())?;
+        let stats = self
+            .single_spec()
+            .convert_input_into(&mut input, &mut writer)?;
+        writer.finish()?.flush()?;
+        Ok(stats)
+    }
+
+    pub fn convert_to_file<P, Q>(
+        &self,
+        input_path: P,
+        output_path: Q,
+        container: OutputContainer,
+    ) -> Result<ConversionStats, PcmError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        match container {
+            OutputContainer::Raw => self.convert_to_pcm(input_path, output_path),
+            OutputContainer::Wav => self.convert_to_wav(input_path, output_path),
+            OutputContainer::Aiff => self.convert_to_aiff(input_path, output_path),
+        }
+    }
+
+    pub fn detect_speech_segments<P: AsRef<Path>>(
+        &self,
+        input_path: P,
+        options: &SpeechDetectionOptions,
+    ) -> Result<Vec<(Duration, Duration)>, PcmError> {
+        // the detector expects the 24 kHz mono output
+        let converter = Self {
+            native_spec: false,
+            ..self.clone()
+        };
+        let mut detector = SpeechDetector::new(TARGET_SAMPLE_RATE, *options);
+        converter.convert_into(input_path.as_ref(), &mut detector)?;
+        Ok(detector.finish())
+    }
+
+    /// Decodes and resamples to 24 kHz keeping the source channels, one plane per channel.
+    /// Only the ReplayGain setting is applied, the downmix, EQ, tempo, dynamics and
+    /// denoise stages all work on the interleaved output and are skipped here. The
+    /// input is opened like for the other outputs, live streams included. There is no
+    /// variant for bytes in memory as the converter only reads through ffmpeg's
+    /// protocols, a pipe or a temporary file stands in for them.
+    pub fn convert_to_planar_f32<P: AsRef<Path>>(
+        &self,
+        input_path: P,
+    ) -> Result<Vec<Vec<f32>>, PcmError> {
+        let mut input = self.open_input(input_path.as_ref())?;
+        let mut filters = vec![];
+        if let Some(replaygain) = replaygain_filter(self.replaygain, &input) {
+            filters.push(replaygain);
+        }
+        filters.push(PLANAR_FILTER_SPEC.to_owned());
+
+        let limit = self.output_limit();
+        let mut planes: Vec<Vec<f32>> = vec![];
+        process_input(
+            &mut input,
+            &filters.join(","),
+            GraphRebuild::Keep,
+            &limit,
+            &mut |frame| append_planar_frame(&mut planes, frame, &limit),
+        )?;
+        Ok(planes)
+    }
+
+    fn convert_into<W: Write>(
+        &self,
+        input_path: &Path,
+        output: &mut W,
+    ) -> Result<ConversionStats, PcmError> {
+        let mut input = self.open_input(input_path)?;
+        self.convert_input_into(&mut input, output)
+    }
+
+    fn convert_input_into<W: Write>(
+        &self,
+        input: &mut AudioInput,
+        output: &mut W,
+    ) -> Result<ConversionStats, PcmError> {
+        let mut spec = self.output_spec(input);
+        let segments = self.segments();
+        let limit = self.output_limit();
+        let mut stats = self.conversion_stats(input);
+        let warnings = self.process(input, &limit, &mut |frame| {
+            if segments {
+                stats.follow_segment(&mut spec, frame);
+            }
+            let written = write_pcm_frame(output, frame, spec, &limit)?;
+            stats.record_frame(frame, written, self.limiter);
+            if self.live_stream && written > 0 {
+                output.flush()?;
+            }
+            Ok(())
+        })?;
+        Ok(ConversionStats { warnings, ..stats })
+    }
+
+    /// The converter for outputs with a single header, which can't follow format changes.
+    fn single_spec(&self) -> Self {
+        Self {
+            format_change_policy: FormatChangePolicy::Error,
+            ..self.clone()
+        }
+    }
+}
+
+/// Converted s16le PCM as a [`Read`] source. Errors are reported as `io::Error`
+/// wrapping the original [`PcmError`], which can be recovered with `into_inner`.
+pub struct PcmReader {
+    spec: PcmSpec,
+    segments: bool,
+    stats: ConversionStats,
+    limiter: Option<Limiter>,
+    limit: OutputLimit,
+    input: AudioInput,
+    filter_graph: DecoderGraph,
+    #[cfg(feature = "denoise")]
+    denoise: Option<DenoiseStage>,
+    buffer: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl PcmReader {
+    /// Spec of the bytes read last, it moves with the source under
+    /// [`FormatChangePolicy::Segment`].
+    pub fn sample_rate(&self) -> u32 {
+        self.spec.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.spec.channels()
+    }
+
+    /// Stats of the output read so far, complete once the reader is drained.
+    pub fn stats(&self) -> ConversionStats {
+        ConversionStats {
+            warnings: self.filter_graph.warnings.clone(),
+            ..self.stats.clone()
+        }
+    }
+
+    /// Decodes the next packet of the audio stream, or flushes the pipeline at the end.
+    fn pump(&mut self) -> Result<(), PcmError> {
+        let Self {
+            spec,
+            segments,
+            stats,
+            limiter,
+            limit,
+            input,
+            filter_graph,
+            #[cfg(feature = "denoise")]
+            denoise,
+            buffer,
+            finished,
+            ..
+        } = self;
+        let mut write = |frame: &ffmpeg::util::frame::Audio| {
+            if *segments {
+                stats.follow_segment(spec, frame);
+            }
+            let written = write_pcm_frame(&mut *buffer, frame, *spec, limit)?;
+            stats.record_frame(frame, written, *limiter);
+            Ok(())
+        };
+        let mut on_frame = |frame: &ffmpeg::util::frame::Audio| {
+            #[cfg(feature = "denoise")]
+            if let Some(stage) = denoise.as_mut() {
+                return stage.push(frame, &mut write);
+            }
+            write(frame)
+        };
+
+        let stream_index = input.stream_index;
+        let packet = if limit.reached() {
+            None
+        } else {
+            input
+                .format_context
+                .packets()
+                .find(|(stream, _)| stream.index() == stream_index)
+        };
+        match packet {
+            Some((_, packet)) => {
+                input.decoder.send_packet(&packet)?;
+                receive_decoded_frames(&mut input.decoder, filter_graph, None, &mut on_frame)
+            }
+            None => {
+                *finished = true;
+                input.decoder.send_eof()?;
+                receive_decoded_frames(&mut input.decoder, filter_graph, None, &mut on_frame)?;
+                filter_graph.flush(&mut on_frame)?;
+                #[cfg(feature = "denoise")]
+                if let Some(stage) = denoise.take() {
+                    stage.finish(&mut write)?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
-pub fn convert_audio_to_pcm<P, Q>(input_path: P, output_path: Q) -> Result<(), PcmError>
+impl Read for PcmReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() && !self.finished {
+            self.buffer.clear();
+            self.position = 0;
+            self.pump().map_err(|err| match err {
+                PcmError::Io(err) => err,
+                err => io::Error::other(err),
+            })?;
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.position);
+        buf[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+pub fn detect_speech_segments<P: AsRef<Path>>(
+    input_path: P,
+    options: &SpeechDetectionOptions,
+) -> Result<Vec<(Duration, Duration)>, PcmError> {
+    AudioConverter::new()?.detect_speech_segments(input_path, options)
+}
+
+pub fn convert_audio_to_pcm<P, Q>(
+    input_path: P,
+    output_path: Q,
+) -> Result<ConversionStats, PcmError>
 where
     P: AsRef<Path>,
     Q: AsRef<Path>,
@@ -100,7 +1493,23 @@ where
 }
 
 fn open_audio_input(path: &Path) -> Result<AudioInput, PcmError> {
-    let format_context = ffmpeg::format::input(path)?;
+    open_audio_input_with_options(path, ffmpeg::Dictionary::new())
+}
+
+/// Reconnects network inputs that drop, local files ignore these.
+fn live_input_options() -> ffmpeg::Dictionary<'static> {
+    let mut options = ffmpeg::Dictionary::new();
+    options.set("reconnect", "1");
+    options.set("reconnect_streamed", "1");
+    options.set("reconnect_delay_max", LIVE_RECONNECT_DELAY_MAX_SECS);
+    options
+}
+
+fn open_audio_input_with_options(
+    path: &Path,
+    options: ffmpeg::Dictionary,
+) -> Result<AudioInput, PcmError> {
+    let format_context = ffmpeg::format::input_with_dictionary(path, options)?;
     let (stream_index, parameters) = {
         let input_stream = format_context
             .streams()
@@ -119,70 +1528,455 @@ fn open_audio_input(path: &Path) -> Result<AudioInput, PcmError> {
     })
 }
 
+fn process_input<F>(
+    input: &mut AudioInput,
+    filter_spec: &str,
+    rebuild: GraphRebuild,
+    limit: &OutputLimit,
+    on_frame: &mut F,
+) -> Result<Vec<ConversionWarning>, PcmError>
+where
+    F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+{
+    process_input_with_tap(input, filter_spec, rebuild, None, limit, on_frame)
+}
+
+fn process_input_with_tap<F>(
+    input: &mut AudioInput,
+    filter_spec: &str,
+    rebuild: GraphRebuild,
+    mut tap: Option<&mut RawTap>,
+    limit: &OutputLimit,
+    on_frame: &mut F,
+) -> Result<Vec<ConversionWarning>, PcmError>
+where
+    F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+{
+    let mut filter_graph = DecoderGraph::new(&input.decoder, filter_spec, rebuild)?;
+
+    for (stream, packet) in input.format_context.packets() {
+        if limit.reached() || tap.as_ref().is_some_and(|tap| tap.stopped) {
+            break;
+        }
+        if stream.index() != input.stream_index {
+            continue;
+        }
+
+        input.decoder.send_packet(&packet)?;
+        receive_decoded_frames(
+            &mut input.decoder,
+            &mut filter_graph,
+            tap.as_deref_mut(),
+            on_frame,
+        )?;
+    }
+
+    input.decoder.send_eof()?;
+    receive_decoded_frames(
+        &mut input.decoder,
+        &mut filter_graph,
+        tap.as_deref_mut(),
+        on_frame,
+    )?;
+
+    if let Some(tap) = tap {
+        tap.flush()?;
+    }
+    filter_graph.flush(on_frame)?;
+    Ok(filter_graph.warnings)
+}
+
+/// Side graph converting decoded frames to f32 planes for
+/// [`AudioConverter::convert_with_raw_access`].
+struct RawTap<'a> {
+    graph: DecoderGraph,
+    on_chunk: &'a mut dyn FnMut(&RawChunk) -> ControlFlow<()>,
+    stopped: bool,
+}
+
+impl<'a> RawTap<'a> {
+    fn new(
+        decoder: &ffmpeg::codec::decoder::Audio,
+        on_chunk: &'a mut dyn FnMut(&RawChunk) -> ControlFlow<()>,
+    ) -> Result<Self, PcmError> {
+        Ok(Self {
+            graph: DecoderGraph::new(decoder, RAW_FILTER_SPEC, GraphRebuild::Keep)?,
+            on_chunk,
+            stopped: false,
+        })
+    }
+
+    fn add_frame(&mut self, frame: &ffmpeg::util::frame::Audio) -> Result<(), PcmError> {
+        let Self {
+            graph,
+            on_chunk,
+            stopped,
+        } = self;
+        graph.add_frame(frame, &mut |frame| {
+            emit_raw_chunk(frame, &mut **on_chunk, stopped)
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), PcmError> {
+        let Self {
+            graph,
+            on_chunk,
+            stopped,
+        } = self;
+        graph.flush(&mut |frame| emit_raw_chunk(frame, &mut **on_chunk, stopped))
+    }
+
+    /// Decodes the whole input into the tap only, `limit` only stops it when cancelled.
+    fn run(&mut self, input: &mut AudioInput, limit: &OutputLimit) -> Result<(), PcmError> {
+        let mut decoded = ffmpeg::util::frame::Audio::empty();
+        for (stream, packet) in input.format_context.packets() {
+            if self.stopped || limit.reached() {
+                return Ok(());
+            }
+            if stream.index() != input.stream_index {
+                continue;
+            }
+
+            input.decoder.send_packet(&packet)?;
+            while receive_decoded_frame(&mut input.decoder, &mut decoded)? {
+                self.add_frame(&decoded)?;
+            }
+        }
+
+        input.decoder.send_eof()?;
+        while receive_decoded_frame(&mut input.decoder, &mut decoded)? {
+            self.add_frame(&decoded)?;
+        }
+        self.flush()
+    }
+}
+
+fn emit_raw_chunk(
+    frame: &ffmpeg::util::frame::Audio,
+    on_chunk: &mut dyn FnMut(&RawChunk) -> ControlFlow<()>,
+    stopped: &mut bool,
+) -> Result<(), PcmError> {
+    if *stopped {
+        return Ok(());
+    }
+    let mut planes = vec![];
+    append_planar_frame_at(&mut planes, frame, frame.rate(), frame.samples())?;
+    let chunk = RawChunk {
+        planes,
+        sample_rate: frame.rate(),
+    };
+    *stopped = on_chunk(&chunk).is_break();
+    Ok(())
+}
+
+#[cfg(feature = "denoise")]
+struct DenoiseStage {
+    denoiser: Denoiser,
+    output_graph: ffmpeg::filter::Graph,
+    pts: i64,
+}
+
+#[cfg(feature = "denoise")]
+impl DenoiseStage {
+    fn new(processing_filters: &[String]) -> Result<Self, PcmError> {
+        let output_args = format!(
+            "time_base=1/{DENOISE_SAMPLE_RATE}:sample_rate={DENOISE_SAMPLE_RATE}:sample_fmt={}:channel_layout=0x{:x}",
+            DENOISE_SAMPLE_FORMAT.name(),
+            ffmpeg::ChannelLayout::MONO.bits()
+        );
+        Ok(Self {
+            denoiser: Denoiser::new(),
+            output_graph: build_filter_graph(&output_args, &processing_filters.join(","))?,
+            pts: 0,
+        })
+    }
+
+    fn push<F>(
+        &mut self,
+        frame: &ffmpeg::util::frame::Audio,
+        on_frame: &mut F,
+    ) -> Result<(), PcmError>
+    where
+        F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+    {
+        check_denoise_frame(frame)?;
+
+        let Self {
+            denoiser,
+            output_graph,
+            pts,
+        } = self;
+        let samples = frame.plane::<f32>(0);
+        denoiser.process(
+            &samples[..frame.samples().min(samples.len())],
+            &mut |denoised| push_denoised_samples(output_graph, pts, denoised, on_frame),
+        )
+    }
+
+    fn finish<F>(self, on_frame: &mut F) -> Result<(), PcmError>
+    where
+        F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+    {
+        let Self {
+            denoiser,
+            mut output_graph,
+            mut pts,
+        } = self;
+        denoiser.finish(&mut |denoised| {
+            push_denoised_samples(&mut output_graph, &mut pts, denoised, on_frame)
+        })?;
+        flush_filter_graph(&mut output_graph, on_frame)
+    }
+}
+
+/// The denoiser reads the first plane as f32 samples, anything else is rejected.
+#[cfg(feature = "denoise")]
+fn check_denoise_frame(frame: &ffmpeg::util::frame::Audio) -> Result<(), PcmError> {
+    if frame.format() != DENOISE_SAMPLE_FORMAT
+        || frame.rate() != DENOISE_SAMPLE_RATE
+        || frame.planes() == 0
+    {
+        return Err(PcmError::UnexpectedFilteredFrame {
+            format: frame.format().name(),
+            sample_rate: frame.rate(),
+            channels: frame.channels(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "denoise")]
+fn push_denoised_samples<F>(
+    output_graph: &mut ffmpeg::filter::Graph,
+    pts: &mut i64,
+    samples: &[f32],
+    on_frame: &mut F,
+) -> Result<(), PcmError>
+where
+    F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+{
+    let mut frame = ffmpeg::util::frame::Audio::new(
+        DENOISE_SAMPLE_FORMAT,
+        samples.len(),
+        ffmpeg::ChannelLayout::MONO,
+    );
+    frame.set_rate(DENOISE_SAMPLE_RATE);
+    frame.set_pts(Some(*pts));
+    frame.plane_mut::<f32>(0)[..samples.len()].copy_from_slice(samples);
+    *pts += samples.len() as i64;
+
+    add_frame_to_filter(output_graph, &frame)?;
+    drain_filtered_frames(output_graph, on_frame)
+}
+
+/// How a [`DecoderGraph`] handles a mid-stream change of the decoded frames.
+enum GraphRebuild {
+    /// Rebuilds with the same filters, they convert to a fixed output spec.
+    Keep,
+    /// Native spec with [`FormatChangePolicy::Error`].
+    Fail,
+    /// Native spec with [`FormatChangePolicy::Segment`], builds the filters for
+    /// frames of the new spec.
+    Respec(Box<dyn Fn(PcmSpec) -> Result<String, PcmError>>),
+}
+
+/// Filter graph fed by the decoder, rebuilt when the decoded frame parameters change
+/// mid-stream (chained streams, in-band format changes) instead of failing in `abuffer`.
+struct DecoderGraph {
+    graph: ffmpeg::filter::Graph,
+    filter_spec: String,
+    time_base: ffmpeg::Rational,
+    input_args: String,
+    rebuild: GraphRebuild,
+    started: bool,
+    declared_channels: u16,
+    warnings: Vec<ConversionWarning>,
+}
+
+impl DecoderGraph {
+    fn new(
+        decoder: &ffmpeg::codec::decoder::Audio,
+        filter_spec: &str,
+        rebuild: GraphRebuild,
+    ) -> Result<Self, PcmError> {
+        let input_args = decoder_filter_args(decoder);
+        Ok(Self {
+            graph: build_filter_graph(&input_args, filter_spec)?,
+            filter_spec: filter_spec.to_owned(),
+            time_base: decoder.time_base(),
+            input_args,
+            rebuild,
+            started: false,
+            declared_channels: decoder.channels(),
+            warnings: vec![],
+        })
+    }
+
+    fn add_frame<F>(
+        &mut self,
+        frame: &ffmpeg::util::frame::Audio,
+        on_frame: &mut F,
+    ) -> Result<(), PcmError>
+    where
+        F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+    {
+        // some codecs only learn the channel count from the first frame
+        let decoded = frame.channels();
+        if self.declared_channels != 0 && decoded != self.declared_channels {
+            let warning = ConversionWarning::ChannelMismatch {
+                declared: self.declared_channels,
+                decoded,
+            };
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+
+        let frame_spec = PcmSpec::of_frame(frame);
+        let input_args = filter_input_args(
+            self.time_base,
+            frame_spec.sample_rate,
+            frame.format(),
+            frame_spec.channel_layout,
+        );
+        if input_args != self.input_args {
+            // the codec parameters may disagree with the first decoded frame, only
+            // later changes count as mid-stream
+            if self.started {
+                match &self.rebuild {
+                    GraphRebuild::Keep => {}
+                    GraphRebuild::Fail => return Err(PcmError::FormatChanged),
+                    GraphRebuild::Respec(filter_spec) => {
+                        self.filter_spec = filter_spec(frame_spec)?;
+                    }
+                }
+            }
+            flush_filter_graph(&mut self.graph, on_frame)?;
+            self.graph = build_filter_graph(&input_args, &self.filter_spec)?;
+            self.input_args = input_args;
+        }
+
+        self.started = true;
+        add_frame_to_filter(&mut self.graph, frame)?;
+        drain_filtered_frames(&mut self.graph, on_frame)
+    }
+
+    fn flush<F>(&mut self, on_frame: &mut F) -> Result<(), PcmError>
+    where
+        F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+    {
+        flush_filter_graph(&mut self.graph, on_frame)
+    }
+}
+
 fn build_pcm_filter_graph(
     decoder: &ffmpeg::codec::decoder::Audio,
+    filter_spec: &str,
 ) -> Result<ffmpeg::filter::Graph, PcmError> {
-    let mut filter_graph = ffmpeg::filter::Graph::new();
-    let input_channel_layout = decoder_input_channel_layout(decoder);
-    let input_args = format!(
-        "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+    build_filter_graph(&decoder_filter_args(decoder), filter_spec)
+}
+
+fn decoder_filter_args(decoder: &ffmpeg::codec::decoder::Audio) -> String {
+    filter_input_args(
         decoder.time_base(),
         decoder.rate(),
-        decoder.format().name(),
-        input_channel_layout.bits()
-    );
+        decoder.format(),
+        decoder_input_channel_layout(decoder),
+    )
+}
+
+fn filter_input_args(
+    time_base: ffmpeg::Rational,
+    sample_rate: u32,
+    format: ffmpeg::format::Sample,
+    channel_layout: ffmpeg::ChannelLayout,
+) -> String {
+    format!(
+        "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+        time_base,
+        sample_rate,
+        format.name(),
+        channel_layout.bits()
+    )
+}
 
+fn build_filter_graph(
+    input_args: &str,
+    filter_spec: &str,
+) -> Result<ffmpeg::filter::Graph, PcmError> {
+    let mut filter_graph = ffmpeg::filter::Graph::new();
     let abuffer = ffmpeg::filter::find("abuffer").ok_or(PcmError::MissingFilter("abuffer"))?;
     let abuffersink =
         ffmpeg::filter::find("abuffersink").ok_or(PcmError::MissingFilter("abuffersink"))?;
 
-    filter_graph.add(&abuffer, FILTER_INPUT_NAME, &input_args)?;
+    filter_graph.add(&abuffer, FILTER_INPUT_NAME, input_args)?;
     filter_graph.add(&abuffersink, FILTER_OUTPUT_NAME, "")?;
     filter_graph
         .output(FILTER_INPUT_NAME, 0)?
         .input(FILTER_OUTPUT_NAME, 0)?
-        .parse(FILTER_SPEC)?;
+        .parse(filter_spec)?;
     filter_graph.validate()?;
 
     Ok(filter_graph)
 }
 
 fn decoder_input_channel_layout(decoder: &ffmpeg::codec::decoder::Audio) -> ffmpeg::ChannelLayout {
-    let layout = decoder.channel_layout();
+    channel_layout_or_default(decoder.channel_layout(), decoder.channels())
+}
+
+fn channel_layout_or_default(
+    layout: ffmpeg::ChannelLayout,
+    channels: u16,
+) -> ffmpeg::ChannelLayout {
     if !layout.is_empty() {
         return layout;
     }
 
-    match decoder.channels() {
+    // plain PCM WAVs leave the order unspecified, the matrix columns follow the
+    // default layout for the count (5.1 for six channels)
+    match channels {
         0 | 1 => ffmpeg::ChannelLayout::MONO,
-        _ => ffmpeg::ChannelLayout::STEREO,
+        2 => ffmpeg::ChannelLayout::STEREO,
+        _ => ffmpeg::ChannelLayout::default(channels as i32),
     }
 }
 
-fn receive_decoded_frames<W: Write>(
+fn receive_decoded_frames<F>(
     decoder: &mut ffmpeg::codec::decoder::Audio,
-    filter_graph: &mut ffmpeg::filter::Graph,
-    output: &mut W,
-    target_channel_layout: ffmpeg::ChannelLayout,
-) -> Result<(), PcmError> {
+    filter_graph: &mut DecoderGraph,
+    mut tap: Option<&mut RawTap>,
+    on_frame: &mut F,
+) -> Result<(), PcmError>
+where
+    F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+{
     let mut decoded = ffmpeg::util::frame::Audio::empty();
-    loop {
-        match decoder.receive_frame(&mut decoded) {
-            Ok(()) => {
-                let timestamp = decoded.timestamp();
-                decoded.set_pts(timestamp);
-                add_frame_to_filter(filter_graph, &decoded)?;
-                drain_filtered_frames(filter_graph, output, target_channel_layout)?;
-            }
-            Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::util::error::EAGAIN => break,
-            Err(ffmpeg::Error::Eof) => break,
-            Err(err) => return Err(err.into()),
+    while receive_decoded_frame(decoder, &mut decoded)? {
+        if let Some(tap) = tap.as_deref_mut() {
+            tap.add_frame(&decoded)?;
         }
+        filter_graph.add_frame(&decoded, on_frame)?;
     }
 
     Ok(())
 }
 
+fn receive_decoded_frame(
+    decoder: &mut ffmpeg::codec::decoder::Audio,
+    decoded: &mut ffmpeg::util::frame::Audio,
+) -> Result<bool, PcmError> {
+    match decoder.receive_frame(decoded) {
+        Ok(()) => {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            Ok(true)
+        }
+        Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::util::error::EAGAIN => Ok(false),
+        Err(ffmpeg::Error::Eof) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
 fn add_frame_to_filter(
     filter_graph: &mut ffmpeg::filter::Graph,
     frame: &ffmpeg::util::frame::Audio,
@@ -194,23 +1988,27 @@ fn add_frame_to_filter(
     Ok(())
 }
 
-fn flush_filter_graph<W: Write>(
+fn flush_filter_graph<F>(
     filter_graph: &mut ffmpeg::filter::Graph,
-    output: &mut W,
-    target_channel_layout: ffmpeg::ChannelLayout,
-) -> Result<(), PcmError> {
+    on_frame: &mut F,
+) -> Result<(), PcmError>
+where
+    F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+{
     let mut input = filter_graph
         .get(FILTER_INPUT_NAME)
         .ok_or(PcmError::MissingFilterContext(FILTER_INPUT_NAME))?;
     input.source().flush()?;
-    drain_filtered_frames(filter_graph, output, target_channel_layout)
+    drain_filtered_frames(filter_graph, on_frame)
 }
 
-fn drain_filtered_frames<W: Write>(
+fn drain_filtered_frames<F>(
     filter_graph: &mut ffmpeg::filter::Graph,
-    output: &mut W,
-    target_channel_layout: ffmpeg::ChannelLayout,
-) -> Result<(), PcmError> {
+    on_frame: &mut F,
+) -> Result<(), PcmError>
+where
+    F: FnMut(&ffmpeg::util::frame::Audio) -> Result<(), PcmError>,
+{
     let mut filtered = ffmpeg::util::frame::Audio::empty();
     loop {
         let mut sink = filter_graph
@@ -218,7 +2016,7 @@ fn drain_filtered_frames<W: Write>(
             .ok_or(PcmError::MissingFilterContext(FILTER_OUTPUT_NAME))?;
 
         match sink.sink().frame(&mut filtered) {
-            Ok(()) => write_pcm_frame(output, &filtered, target_channel_layout)?,
+            Ok(()) => on_frame(&filtered)?,
             Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::util::error::EAGAIN => break,
             Err(ffmpeg::Error::Eof) => break,
             Err(err) => return Err(err.into()),
@@ -228,14 +2026,16 @@ fn drain_filtered_frames<W: Write>(
     Ok(())
 }
 
+/// Writes the part of `frame` that `limit` still allows, returns the bytes written.
 fn write_pcm_frame<W: Write>(
     output: &mut W,
     frame: &ffmpeg::util::frame::Audio,
-    target_channel_layout: ffmpeg::ChannelLayout,
-) -> Result<(), PcmError> {
+    spec: PcmSpec,
+    limit: &OutputLimit,
+) -> Result<usize, PcmError> {
     if frame.format() != TARGET_SAMPLE_FORMAT
-        || frame.rate() != TARGET_SAMPLE_RATE
-        || frame.channels() != target_channel_layout.channels() as u16
+        || frame.rate() != spec.sample_rate
+        || frame.channels() != spec.channels()
     {
         return Err(PcmError::UnexpectedFilteredFrame {
             format: frame.format().name(),
@@ -244,8 +2044,7 @@ fn write_pcm_frame<W: Write>(
         });
     }
 
-    let expected_bytes =
-        frame.samples() * target_channel_layout.channels() as usize * PCM_BYTES_PER_SAMPLE;
+    let expected_bytes = frame.samples() * spec.channels() as usize * PCM_BYTES_PER_SAMPLE;
     let pcm_bytes = frame.data(0);
 
     if pcm_bytes.len() < expected_bytes {
@@ -255,7 +2054,47 @@ fn write_pcm_frame<W: Write>(
         });
     }
 
-    write_s16le_pcm_bytes(output, &pcm_bytes[..expected_bytes])?;
+    let written = limit.take(frame.samples(), spec.sample_rate)
+        * spec.channels() as usize
+        * PCM_BYTES_PER_SAMPLE;
+    write_s16le_pcm_bytes(output, &pcm_bytes[..written])?;
+    Ok(written)
+}
+
+fn append_planar_frame(
+    planes: &mut Vec<Vec<f32>>,
+    frame: &ffmpeg::util::frame::Audio,
+    limit: &OutputLimit,
+) -> Result<(), PcmError> {
+    let frames = limit.take(frame.samples(), TARGET_SAMPLE_RATE);
+    append_planar_frame_at(planes, frame, TARGET_SAMPLE_RATE, frames)
+}
+
+/// Appends the first `frames` samples of every channel of `frame`.
+fn append_planar_frame_at(
+    planes: &mut Vec<Vec<f32>>,
+    frame: &ffmpeg::util::frame::Audio,
+    sample_rate: u32,
+    frames: usize,
+) -> Result<(), PcmError> {
+    let channels = (frame.channels() as usize).min(frame.planes());
+    if planes.is_empty() {
+        planes.resize_with(channels, Vec::new);
+    }
+    if frame.format() != PLANAR_SAMPLE_FORMAT || frame.rate() != sample_rate || channels == 0 {
+        return Err(PcmError::UnexpectedFilteredFrame {
+            format: frame.format().name(),
+            sample_rate: frame.rate(),
+            channels: frame.channels(),
+        });
+    }
+
+    for (channel, plane) in planes.iter_mut().enumerate() {
+        // malformed sources can drop channels mid-stream, repeat the first one instead
+        let source_channel = if channel < channels { channel } else { 0 };
+        let samples = frame.plane::<f32>(source_channel);
+        plane.extend_from_slice(&samples[..frames.min(samples.len())]);
+    }
     Ok(())
 }
 
@@ -276,3 +2115,82 @@ fn write_s16le_pcm_bytes<W: Write>(output: &mut W, pcm_bytes: &[u8]) -> Result<(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // minimised from the fuzz target: a stream claiming the longest duration the
+    // container can hold, slowed down as far as the tempo goes
+    #[test]
+    fn absurd_durations_saturate_the_estimate() {
+        let converter = AudioConverter::new().unwrap().with_tempo(MIN_TEMPO);
+        let info = AudioInfo {
+            sample_rate: 48000,
+            channels: 2,
+            duration: Some(Duration::MAX),
+        };
+        assert_eq!(converter.estimate_output_len(&info), Some(u64::MAX));
+    }
+
+    // a source dropping from stereo to mono mid-stream used to fail the planar output
+    // with UnexpectedFilteredFrame, the missing channel now repeats the first one
+    #[test]
+    fn planar_output_survives_a_channel_drop() {
+        let frame = |layout, value| {
+            let mut frame = ffmpeg::util::frame::Audio::new(PLANAR_SAMPLE_FORMAT, 4, layout);
+            frame.set_rate(TARGET_SAMPLE_RATE);
+            for plane in 0..frame.planes() {
+                frame.plane_mut::<f32>(plane)[..4].fill(value);
+            }
+            frame
+        };
+        let (mut planes, limit) = (vec![], OutputLimit::default());
+        append_planar_frame(
+            &mut planes,
+            &frame(ffmpeg::ChannelLayout::STEREO, 0.5),
+            &limit,
+        )
+        .unwrap();
+        append_planar_frame(
+            &mut planes,
+            &frame(ffmpeg::ChannelLayout::MONO, 0.25),
+            &limit,
+        )
+        .unwrap();
+        assert_eq!(planes, [[0.5, 0.5, 0.5, 0.5, 0.25, 0.25, 0.25, 0.25]; 2]);
+    }
+
+    #[test]
+    fn live_inputs_reconnect_on_drops() {
+        let options = live_input_options();
+        assert_eq!(options.get("reconnect"), Some("1"));
+        // streamed (non-seekable) sources like Icecast reconnect too
+        assert_eq!(options.get("reconnect_streamed"), Some("1"));
+        assert_eq!(
+            options.get("reconnect_delay_max"),
+            Some(LIVE_RECONNECT_DELAY_MAX_SECS)
+        );
+    }
+
+    // minimised from the fuzz target: a filter graph handing s16 frames to the denoiser
+    #[cfg(feature = "denoise")]
+    #[test]
+    fn denoise_rejects_frames_that_are_not_f32() {
+        let mut frame =
+            ffmpeg::util::frame::Audio::new(TARGET_SAMPLE_FORMAT, 480, ffmpeg::ChannelLayout::MONO);
+        frame.set_rate(DENOISE_SAMPLE_RATE);
+        assert!(matches!(
+            check_denoise_frame(&frame),
+            Err(PcmError::UnexpectedFilteredFrame { format: "s16", .. })
+        ));
+
+        let mut frame = ffmpeg::util::frame::Audio::new(
+            DENOISE_SAMPLE_FORMAT,
+            480,
+            ffmpeg::ChannelLayout::MONO,
+        );
+        frame.set_rate(DENOISE_SAMPLE_RATE);
+        assert!(check_denoise_frame(&frame).is_ok());
+    }
+}
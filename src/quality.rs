@@ -0,0 +1,118 @@
+use crate::silk::{
+    SampleRate, SilkEncodeOptions, SilkError, decode_silk, encode_silk_with_options,
+};
+
+const MAX_DELAY_MS: usize = 40;
+// the delay search only needs enough signal to find the correlation peak
+const DELAY_SEARCH_WINDOW_MS: usize = 2000;
+const SEGMENT_MS: usize = 20;
+const MIN_SEGMENT_SNR_DB: f64 = -10.0;
+const MAX_SEGMENT_SNR_DB: f64 = 35.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityReport {
+    /// Infinite when the decoded signal matches the original exactly.
+    pub snr_db: f64,
+    /// Mean of the per-20 ms SNRs clamped to -10..=35 dB, silent segments are skipped.
+    pub segmental_snr_db: f64,
+    pub max_abs_error: u16,
+    /// Codec delay removed from the decoded signal before comparing.
+    pub delay_samples: usize,
+}
+
+/// Encodes mono `pcm` with `options` at `sample_rate`, decodes it back and compares
+/// the delay-aligned result with the original.
+pub fn measure_roundtrip_quality(
+    pcm: &[i16],
+    sample_rate: i32,
+    options: &SilkEncodeOptions,
+) -> Result<QualityReport, SilkError> {
+    let options = options
+        .sample_rate(SampleRate::try_from(sample_rate)?)
+        .channels(1);
+    let input = pcm
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect::<Vec<_>>();
+    let encoded = encode_silk_with_options(&input, &options)?;
+    let decoded = decode_silk(&encoded, sample_rate)?
+        .chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect::<Vec<_>>();
+
+    let rate = sample_rate.max(0) as usize;
+    let delay = find_delay(
+        pcm,
+        &decoded,
+        rate * MAX_DELAY_MS / 1000,
+        rate * DELAY_SEARCH_WINDOW_MS / 1000,
+    );
+    let aligned = decoded.get(delay..).unwrap_or_default();
+    let len = pcm.len().min(aligned.len());
+    let (original, aligned) = (&pcm[..len], &aligned[..len]);
+
+    let max_abs_error = original
+        .iter()
+        .zip(aligned)
+        .map(|(&a, &b)| (a as i32 - b as i32).unsigned_abs().min(u16::MAX as u32) as u16)
+        .max()
+        .unwrap_or(0);
+
+    let segment_len = (rate * SEGMENT_MS / 1000).max(1);
+    let segment_snrs = original
+        .chunks(segment_len)
+        .zip(aligned.chunks(segment_len))
+        .filter_map(|(original, decoded)| {
+            let (signal, noise) = signal_and_noise(original, decoded);
+            (signal > 0.0)
+                .then(|| snr_db(signal, noise).clamp(MIN_SEGMENT_SNR_DB, MAX_SEGMENT_SNR_DB))
+        })
+        .collect::<Vec<_>>();
+    let segmental_snr_db = if segment_snrs.is_empty() {
+        0.0
+    } else {
+        segment_snrs.iter().sum::<f64>() / segment_snrs.len() as f64
+    };
+
+    let (signal, noise) = signal_and_noise(original, aligned);
+    Ok(QualityReport {
+        snr_db: snr_db(signal, noise),
+        segmental_snr_db,
+        max_abs_error,
+        delay_samples: delay,
+    })
+}
+
+fn find_delay(original: &[i16], decoded: &[i16], max_delay: usize, window: usize) -> usize {
+    let mut best = (0, f64::MIN);
+    for delay in 0..=max_delay.min(decoded.len()) {
+        let shifted = &decoded[delay..];
+        let len = original.len().min(shifted.len()).min(window);
+        let (mut cross, mut energy) = (0.0, 0.0);
+        for (&a, &b) in original[..len].iter().zip(&shifted[..len]) {
+            cross += a as f64 * b as f64;
+            energy += b as f64 * b as f64;
+        }
+        if energy > 0.0 && cross / energy.sqrt() > best.1 {
+            best = (delay, cross / energy.sqrt());
+        }
+    }
+    best.0
+}
+
+fn signal_and_noise(original: &[i16], decoded: &[i16]) -> (f64, f64) {
+    original
+        .iter()
+        .zip(decoded)
+        .fold((0.0, 0.0), |(signal, noise), (&a, &b)| {
+            let error = a as f64 - b as f64;
+            (signal + a as f64 * a as f64, noise + error * error)
+        })
+}
+
+fn snr_db(signal: f64, noise: f64) -> f64 {
+    if noise == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (signal / noise).log10()
+}
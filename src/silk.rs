@@ -1,5 +1,12 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
 use bytes::{Buf, BufMut};
-use std::ffi::c_void;
+use core::ffi::c_void;
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 use thiserror::Error;
 
 #[allow(
@@ -12,6 +19,29 @@ pub(crate) mod sdk {
     include!(concat!(env!("OUT_DIR"), "/silk_bindings.rs"));
 }
 
+pub(crate) const SILK_HEADER: &[u8] = b"#!SILK_V3";
+pub(crate) const TENCENT_PREFIX: u8 = 0x02;
+const PACKET_DURATION_MS: u64 = 20;
+const PACKET_DURATIONS_MS: [u16; 5] = [20, 40, 60, 80, 100];
+// SDK limits: 5 frames per packet, and a range coder of MAX_ARITHM_BYTES per packet
+// whatever the bit rate (up to 100 kbps) and duration. The decoder refuses longer
+// payloads, and in-band FEC data is only appended while it fits the output buffer, so
+// encoding into MAX_PACKET_BYTES never fails with EncPayloadBufTooShort and always
+// yields packets the decoder takes.
+const MAX_FRAMES_PER_PACKET: usize = 5;
+pub(crate) const MAX_PACKET_BYTES: usize = 1024;
+const MIN_BIT_RATE: i32 = 5000;
+const MAX_BIT_RATE: i32 = 100000;
+const STATE_ALIGN: usize = 16;
+const INTERNAL_SAMPLE_RATES: [i32; 4] = [8000, 12000, 16000, 24000];
+// loss rate the encoder plans its redundancy for when FEC is on, the SDK only adds
+// FEC data with a non-zero expected loss
+const FEC_PACKET_LOSS_PERCENTAGE: i32 = 10;
+// 10 minutes of 48 kHz stereo, far beyond any voice message
+pub(crate) const DEFAULT_MAX_OUTPUT_BYTES: u64 = 10 * 60 * 48000 * 2 * 2;
+// packets after a lost one that may carry its FEC copy
+const MAX_LBRR_DELAY: usize = 2;
+
 macro_rules! fast_check {
     ($call:expr) => {{
         unsafe {
@@ -23,152 +53,2756 @@ macro_rules! fast_check {
     }};
 }
 
-pub fn decode_silk<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Vec<u8>, SilkError> {
-    unsafe { _decode_silk(src.as_ref(), sample_rate) }
-}
+/// Rates the SDK accepts for encoder input and decoder output. The decoder resamples
+/// from the internal rate of the stream to any of them, 44.1 kHz included; frame sizes
+/// are `rate * ms / 1000`, so a 20 ms frame there is exactly 882 samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SampleRate {
+    Hz8000,
+    Hz12000,
+    Hz16000,
+    Hz24000,
+    Hz32000,
+    Hz44100,
+    Hz48000,
+}
+
+impl SampleRate {
+    pub const fn hz(self) -> i32 {
+        match self {
+            Self::Hz8000 => 8000,
+            Self::Hz12000 => 12000,
+            Self::Hz16000 => 16000,
+            Self::Hz24000 => 24000,
+            Self::Hz32000 => 32000,
+            Self::Hz44100 => 44100,
+            Self::Hz48000 => 48000,
+        }
+    }
+
+    /// Samples per channel in `duration_ms` of audio.
+    const fn samples(self, duration_ms: usize) -> usize {
+        self.hz() as usize * duration_ms / 1000
+    }
+}
+
+impl TryFrom<i32> for SampleRate {
+    type Error = SilkError;
+
+    fn try_from(sample_rate: i32) -> Result<Self, SilkError> {
+        match sample_rate {
+            8000 => Ok(Self::Hz8000),
+            12000 => Ok(Self::Hz12000),
+            16000 => Ok(Self::Hz16000),
+            24000 => Ok(Self::Hz24000),
+            32000 => Ok(Self::Hz32000),
+            44100 => Ok(Self::Hz44100),
+            48000 => Ok(Self::Hz48000),
+            _ => Err(SilkError::UnsupportedSampleRate(sample_rate)),
+        }
+    }
+}
+
+impl From<SampleRate> for i32 {
+    fn from(sample_rate: SampleRate) -> Self {
+        sample_rate.hz()
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct StateBlock([u8; STATE_ALIGN]);
+
+/// Zeroed encoder/decoder state. A byte buffer is only 1-byte aligned while the SDK
+/// structs hold ints and assume natural alignment, so it is made of aligned blocks.
+struct CodecState {
+    blocks: Vec<StateBlock>,
+}
+
+impl CodecState {
+    fn new(size: usize) -> Self {
+        Self {
+            blocks: vec![StateBlock([0; STATE_ALIGN]); size.div_ceil(STATE_ALIGN)],
+        }
+    }
+
+    fn as_ptr(&self) -> *const c_void {
+        self.blocks.as_ptr() as *const c_void
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        let ptr = self.blocks.as_mut_ptr();
+        debug_assert!(ptr.is_aligned());
+        ptr as *mut c_void
+    }
+}
+
+/// What [`SilkDecodeOptions::lenient`] puts in place of a packet the decoder rejects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorruptPacketFill {
+    Drop,
+    /// One frame of silence.
+    Silence,
+    /// Concealment from the packets before it, as for a lost packet.
+    Conceal,
+}
+
+/// Decoder configuration for [`decode_silk_with_options`], [`decode_silk_with_report`]
+/// and [`decode_silk_packets`]; [`decode_silk`] uses the defaults with only the output
+/// rate set. Each decoded frame goes through `skip_leading_ms`, then `max_duration`,
+/// then the channel layout, and `max_output_bytes` counts what is left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilkDecodeOptions {
+    pub(crate) sample_rate: SampleRate,
+    pub(crate) skip_leading_ms: u16,
+    pub(crate) output_channels: u8,
+    pub(crate) constant_power: bool,
+    pub(crate) max_duration: Option<Duration>,
+    pub(crate) in_band_fec: bool,
+    pub(crate) lenient: Option<CorruptPacketFill>,
+    pub(crate) max_output_bytes: u64,
+}
+
+impl Default for SilkDecodeOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: SampleRate::Hz24000,
+            skip_leading_ms: 0,
+            output_channels: 1,
+            constant_power: false,
+            max_duration: None,
+            in_band_fec: true,
+            lenient: None,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        }
+    }
+}
+
+impl SilkDecodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Output rate, 24 kHz by default. The SDK resamples from the internal rate of the
+    /// stream. Functions taking the rate in Hz convert it with `SampleRate::try_from`
+    /// and fail with [`SilkError::UnsupportedSampleRate`] before decoding anything.
+    pub fn sample_rate(mut self, sample_rate: SampleRate) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Drops the first milliseconds of output, e.g. the decoder warm-up transient. 0 by
+    /// default. `max_duration` counts from the first sample kept.
+    pub fn skip_leading_ms(mut self, skip_leading_ms: u16) -> Self {
+        self.skip_leading_ms = skip_leading_ms;
+        self
+    }
+
+    /// 1 by default. With 2 channels every decoded sample is duplicated into
+    /// interleaved L/R, other counts fail with [`SilkError::UnsupportedChannels`].
+    /// Durations stay per channel, `max_output_bytes` counts both.
+    pub fn output_channels(mut self, output_channels: u8) -> Self {
+        self.output_channels = output_channels;
+        self
+    }
+
+    /// Scales duplicated stereo output by -3 dB (constant-power pan law). Off by
+    /// default, mono output is never scaled.
+    pub fn constant_power(mut self, constant_power: bool) -> Self {
+        self.constant_power = constant_power;
+        self
+    }
+
+    /// Stops decoding once this much audio has been produced after
+    /// `skip_leading_ms`, the remaining packets are not decoded at all. The cut is
+    /// exact to the sample, not rounded to frames. Unlimited by default.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Rebuilds a lost packet from the FEC copy in the packets after it, when the
+    /// encoder sent one (see [`SilkEncodeOptions::in_band_fec`]). On by default. Only
+    /// applies to the `None` packets of [`decode_silk_packets`], a container has no
+    /// lost packets; without a FEC copy, or with this off, the loss is concealed.
+    pub fn in_band_fec(mut self, in_band_fec: bool) -> Self {
+        self.in_band_fec = in_band_fec;
+        self
+    }
+
+    /// Replaces packets the decoder rejects with `fill` and carries on, by default
+    /// they fail the decode. A broken length prefix ends the stream, nothing after it
+    /// can be found. Filled frames count towards `max_duration` like decoded ones;
+    /// [`decode_silk_with_report`] lists what was skipped.
+    pub fn lenient(mut self, fill: CorruptPacketFill) -> Self {
+        self.lenient = Some(fill);
+        self
+    }
+
+    /// Fails with [`SilkError::OutputLimitExceeded`] before the output grows past this,
+    /// as a few bytes of DTX packets expand to 100 ms of audio each. Defaults to 10
+    /// minutes of 48 kHz stereo, `u64::MAX` turns it off.
+    pub fn max_output_bytes(mut self, max_output_bytes: u64) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+}
+
+impl SilkDecodeOptions {
+    /// The defaults at `sample_rate` Hz, for the functions taking the rate as a number.
+    pub(crate) fn at(sample_rate: i32) -> Result<Self, SilkError> {
+        Ok(Self::new().sample_rate(SampleRate::try_from(sample_rate)?))
+    }
+}
+
+/// Packets skipped by a [`SilkDecodeOptions::lenient`] decode.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeReport {
+    /// Offsets of the length prefixes in the container, packet indices for
+    /// [`decode_silk_packets`].
+    pub skipped: Vec<usize>,
+}
+
+/// Decodes a silk container to s16le PCM at `sample_rate`.
+///
+/// The output is capped at the default [`SilkDecodeOptions::max_output_bytes`], 10
+/// minutes of 48 kHz stereo (115.2 MB): a longer message fails with
+/// [`SilkError::OutputLimitExceeded`] instead of being decoded. The other decoders
+/// taking only a rate share the cap, use [`decode_silk_with_options`] to raise it.
+pub fn decode_silk<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Vec<u8>, SilkError> {
+    decode_silk_with_options(src, &SilkDecodeOptions::at(sample_rate)?)
+}
+
+/// [`decode_silk`] into `out`, which is cleared first and only grows when the output
+/// does not fit. Returns the number of bytes written. Only the output buffer is
+/// reused, every call allocates a new decoder; [`SilkDecoder::decode_into`] keeps one.
+pub fn decode_silk_into<R: AsRef<[u8]>>(
+    src: R,
+    sample_rate: i32,
+    out: &mut Vec<u8>,
+) -> Result<usize, SilkError> {
+    out.clear();
+    let options = SilkDecodeOptions::at(sample_rate)?;
+    decode_with_options(DecodeInput::Container(src.as_ref()), &options, |sample| {
+        out.extend_from_slice(&sample.to_le_bytes())
+    })?;
+    Ok(out.len())
+}
+
+/// [`decode_silk`] as samples instead of s16le bytes.
+pub fn decode_silk_i16<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Vec<i16>, SilkError> {
+    decode_silk_i16_with_options(src, &SilkDecodeOptions::at(sample_rate)?)
+}
+
+/// [`decode_silk_with_options`] as samples, interleaved when `output_channels` is 2.
+pub fn decode_silk_i16_with_options<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkDecodeOptions,
+) -> Result<Vec<i16>, SilkError> {
+    let mut result = vec![];
+    decode_with_options(DecodeInput::Container(src.as_ref()), options, |sample| {
+        result.push(sample)
+    })?;
+    Ok(result)
+}
+
+/// [`decode_silk`] as f32 samples in -1..1.
+pub fn decode_silk_f32<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Vec<f32>, SilkError> {
+    decode_silk_f32_with_options(src, &SilkDecodeOptions::at(sample_rate)?)
+}
+
+/// [`decode_silk_i16_with_options`] as f32 samples in -1..1.
+pub fn decode_silk_f32_with_options<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkDecodeOptions,
+) -> Result<Vec<f32>, SilkError> {
+    let mut result = vec![];
+    decode_with_options(DecodeInput::Container(src.as_ref()), options, |sample| {
+        result.push(sample as f32 / FULL_SCALE)
+    })?;
+    Ok(result)
+}
+
+pub fn decode_silk_with_options<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkDecodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    let mut result = vec![];
+    decode_with_options(DecodeInput::Container(src.as_ref()), options, |sample| {
+        result.extend_from_slice(&sample.to_le_bytes());
+    })?;
+    Ok(result)
+}
+
+/// [`decode_silk_with_options`] along with the packets a lenient decode skipped.
+pub fn decode_silk_with_report<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkDecodeOptions,
+) -> Result<(Vec<u8>, DecodeReport), SilkError> {
+    let mut result = vec![];
+    let report = decode_with_options(DecodeInput::Container(src.as_ref()), options, |sample| {
+        result.extend_from_slice(&sample.to_le_bytes());
+    })?;
+    Ok((result, report))
+}
+
+/// Decodes bare SDK packets as received, `None` marks a packet that never arrived and
+/// is concealed from the previous ones, so the output keeps its full duration.
+pub fn decode_silk_packets(
+    packets: &[Option<&[u8]>],
+    options: &SilkDecodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    let mut result = vec![];
+    decode_with_options(DecodeInput::Packets(packets), options, |sample| {
+        result.extend_from_slice(&sample.to_le_bytes());
+    })?;
+    Ok(result)
+}
+
+/// Decodes at most the first `duration` of `src`, for previews of long messages.
+pub fn decode_silk_prefix<R: AsRef<[u8]>>(
+    src: R,
+    sample_rate: i32,
+    duration: Duration,
+) -> Result<Vec<u8>, SilkError> {
+    let options = SilkDecodeOptions::at(sample_rate)?.max_duration(duration);
+    decode_silk_with_options(src, &options)
+}
+
+enum DecodeInput<'a> {
+    Container(&'a [u8]),
+    Packets(&'a [Option<&'a [u8]>]),
+}
+
+fn decode_with_options<F>(
+    input: DecodeInput<'_>,
+    options: &SilkDecodeOptions,
+    mut on_sample: F,
+) -> Result<DecodeReport, SilkError>
+where
+    F: FnMut(i16),
+{
+    if !(1..=2).contains(&options.output_channels) {
+        return Err(SilkError::UnsupportedChannels(options.output_channels));
+    }
+
+    let sample_rate = options.sample_rate;
+    let mut skip = sample_rate.samples(options.skip_leading_ms as usize);
+    let mut remaining = options.max_duration.map_or(usize::MAX, |duration| {
+        (duration.as_micros() * sample_rate.hz() as u128 / 1_000_000) as usize
+    });
+    let mut output_bytes = 0u64;
+    let mut exceeded = false;
+    let on_frame = |mut samples: &[i16]| {
+        let skipped = skip.min(samples.len());
+        samples = &samples[skipped..];
+        skip -= skipped;
+        let taken = remaining.min(samples.len());
+        samples = &samples[..taken];
+        remaining -= taken;
+
+        output_bytes += (samples.len() * 2 * options.output_channels as usize) as u64;
+        if output_bytes > options.max_output_bytes {
+            exceeded = true;
+            return ControlFlow::Break(());
+        }
+        for &sample in samples {
+            if options.output_channels == 1 {
+                on_sample(sample);
+                continue;
+            }
+
+            let sample = if options.constant_power {
+                round_to_i16(sample as f32 * core::f32::consts::FRAC_1_SQRT_2)
+            } else {
+                sample
+            };
+            on_sample(sample);
+            on_sample(sample);
+        }
+
+        if remaining == 0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+    let report = match input {
+        DecodeInput::Container(src) => {
+            decode_container(src, options.sample_rate.hz(), options.lenient, on_frame)
+        }
+        DecodeInput::Packets(packets) => decode_packets(packets, options, on_frame),
+    }?;
+    if exceeded {
+        return Err(SilkError::OutputLimitExceeded {
+            limit: options.max_output_bytes,
+        });
+    }
+    Ok(report)
+}
+
+/// Peak envelope of `src` in `buckets` bars of 0..=255, decoded frame by frame.
+/// Each bar covers an equal share of the 20 ms frames; messages with fewer frames
+/// than bars get one frame per bar and silent bars after the end.
+pub fn silk_waveform<R: AsRef<[u8]>>(
+    src: R,
+    sample_rate: i32,
+    buckets: usize,
+) -> Result<Vec<u8>, SilkError> {
+    let src = src.as_ref();
+    // counted up front so every frame goes straight into its bar
+    let frames = (silk_duration(src, sample_rate)?.as_millis() / 20) as usize;
+    let mut waveform = vec![0u8; buckets];
+    let mut frame = 0;
+    unsafe {
+        _decode_silk(src, sample_rate, |samples| {
+            let index = if frames <= buckets {
+                frame
+            } else {
+                ((frame + 1) * buckets - 1) / frames
+            };
+            frame += 1;
+            let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+            if let Some(bucket) = waveform.get_mut(index) {
+                *bucket = (*bucket).max(scale_peak(peak));
+            }
+            ControlFlow::Continue(())
+        })?;
+    }
+    Ok(waveform)
+}
+
+const FULL_SCALE: f32 = 32768.0;
+const SILENCE_DBFS: f32 = -60.0;
+
+// f32::round lives in std
+fn round_to_i16(sample: f32) -> i16 {
+    if sample >= 0.0 {
+        (sample + 0.5) as i16
+    } else {
+        (sample - 0.5) as i16
+    }
+}
+
+/// Full scale is -32768..=32767, so negative samples scale by one more step.
+pub(crate) fn quantize_f32(sample: f32) -> i16 {
+    if sample.is_nan() {
+        return 0;
+    }
+    let sample = sample.clamp(-1.0, 1.0);
+    if sample < 0.0 {
+        round_to_i16(sample * FULL_SCALE)
+    } else {
+        round_to_i16(sample * i16::MAX as f32)
+    }
+}
+
+fn scale_peak(peak: u16) -> u8 {
+    let peak = peak.min(i16::MAX as u16) as u32;
+    (peak * u8::MAX as u32 / i16::MAX as u32) as u8
+}
+
+/// Decoded frames of a container at `sample_rate`, as they come out of the SDK.
+#[cfg(feature = "std")]
+pub(crate) fn decode_frames<F>(src: &[u8], sample_rate: i32, on_frame: F) -> Result<(), SilkError>
+where
+    F: FnMut(&[i16]) -> ControlFlow<()>,
+{
+    unsafe { _decode_silk(src, sample_rate, on_frame) }
+}
+
+unsafe fn _decode_silk<F>(src: &[u8], sample_rate: i32, on_frame: F) -> Result<(), SilkError>
+where
+    F: FnMut(&[i16]) -> ControlFlow<()>,
+{
+    decode_container(src, sample_rate, None, on_frame)?;
+    Ok(())
+}
+
+fn decode_container<F>(
+    src: &[u8],
+    sample_rate: i32,
+    lenient: Option<CorruptPacketFill>,
+    on_frame: F,
+) -> Result<DecodeReport, SilkError>
+where
+    F: FnMut(&[i16]) -> ControlFlow<()>,
+{
+    let mut decoder = PacketDecoder::new(sample_rate)?;
+    decode_container_with(&mut decoder, src, lenient, on_frame)
+}
+
+fn decode_container_with<F>(
+    decoder: &mut PacketDecoder,
+    src: &[u8],
+    lenient: Option<CorruptPacketFill>,
+    mut on_frame: F,
+) -> Result<DecodeReport, SilkError>
+where
+    F: FnMut(&[i16]) -> ControlFlow<()>,
+{
+    if lenient.is_none() {
+        // a stream claiming more than it holds fails before anything is decoded
+        for frame in SilkFrameIter::new(src) {
+            frame?;
+        }
+    }
+    let mut report = DecodeReport::default();
+    for frame in SilkFrameIter::new(src) {
+        let frame = match (frame, lenient) {
+            (Err(SilkError::InvalidPacketLength { offset, .. }), Some(_)) => {
+                report.skipped.push(offset);
+                break;
+            }
+            (frame, _) => frame?,
+        };
+        let flow = match (decoder.decode(Some(frame.payload), &mut on_frame), lenient) {
+            (Err(_), Some(fill)) => {
+                report.skipped.push(frame.offset);
+                decoder.fill(fill, &mut on_frame)?
+            }
+            (flow, _) => flow.map_err(|error| SilkError::Frame {
+                index: frame.index,
+                offset: frame.offset,
+                source: Box::new(error),
+            })?,
+        };
+        if flow.is_break() {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+/// One length-prefixed packet of a container, see [`SilkFrameIter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilkFrame<'a> {
+    pub payload: &'a [u8],
+    /// Position of the length prefix in the container.
+    pub offset: usize,
+    pub index: usize,
+}
+
+impl SilkFrame<'_> {
+    pub fn len(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// Empty packets are DTX frames.
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+
+    /// Rate the encoder worked at internally for this packet (8000, 12000, 16000 or
+    /// 24000), from its table of contents. `None` for DTX and corrupt packets.
+    pub fn internal_sample_rate(&self) -> Option<i32> {
+        if self.is_empty() {
+            return None;
+        }
+        TocBuffer::new()
+            .parse(self.payload)
+            .map(|toc| toc.fs_kHz * 1000)
+    }
+}
+
+/// Walks the packets of a container without decoding them. A missing header is
+/// reported by the first item, the iteration ends at the terminator and after an error.
+#[derive(Debug, Clone)]
+pub struct SilkFrameIter<'a> {
+    src: &'a [u8],
+    offset: usize,
+    index: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a> SilkFrameIter<'a> {
+    pub fn new(src: &'a [u8]) -> Self {
+        Self {
+            src,
+            offset: 0,
+            index: 0,
+            started: false,
+            finished: false,
+        }
+    }
+}
+
+impl<'a> Iterator for SilkFrameIter<'a> {
+    type Item = Result<SilkFrame<'a>, SilkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            let prefix = usize::from(self.src.first() == Some(&TENCENT_PREFIX));
+            if !self.src[prefix..].starts_with(SILK_HEADER) {
+                self.finished = true;
+                return Some(Err(SilkError::Invalid));
+            }
+            self.offset = prefix + SILK_HEADER.len();
+        }
+
+        let rest = &self.src[self.offset..];
+        let len = match rest {
+            [low, high, ..] => i16::from_le_bytes([*low, *high]),
+            _ => -1,
+        };
+        // -1 terminates the stream, whatever follows is not audio
+        if len < 0 {
+            self.finished = true;
+            return None;
+        }
+        let size = len as usize;
+        if size > MAX_PACKET_BYTES || rest.len() < 2 + size {
+            self.finished = true;
+            return Some(Err(SilkError::InvalidPacketLength {
+                offset: self.offset,
+                len,
+            }));
+        }
+
+        let frame = SilkFrame {
+            payload: &rest[2..2 + size],
+            offset: self.offset,
+            index: self.index,
+        };
+        self.offset += 2 + size;
+        self.index += 1;
+        Some(Ok(frame))
+    }
+}
+
+/// Length of `src` from the packet headers, without decoding the audio. It matches the
+/// decoded length at any supported `sample_rate`.
+pub fn silk_duration<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Duration, SilkError> {
+    SampleRate::try_from(sample_rate)?;
+    let mut frames = 0;
+    // DTX packets are concealed for as many frames as the packet before them held
+    let mut frames_per_packet = 1;
+    let mut toc = TocBuffer::new();
+    for frame in SilkFrameIter::new(src.as_ref()) {
+        let frame = frame?;
+        if !frame.is_empty() {
+            frames_per_packet = frames_in_packet(&mut toc, &frame)?;
+        }
+        frames += frames_per_packet;
+    }
+    Ok(Duration::from_millis(frames * PACKET_DURATION_MS))
+}
+
+/// Container summary from [`probe_silk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilkInfo {
+    pub variant: SilkHeaderVariant,
+    pub packet_count: usize,
+    /// Packet bytes without the header and length prefixes.
+    pub payload_bytes: usize,
+    pub has_terminator: bool,
+}
+
+impl SilkInfo {
+    /// Packet count times 20 ms, exact for streams of 20 ms packets (what this crate
+    /// writes by default). The probe never parses payloads, so streams with 40-100 ms
+    /// packets come out short; [`silk_duration`] reads their frame counts. The
+    /// figure does not depend on the decoding rate.
+    pub fn estimated_duration(&self) -> Duration {
+        Duration::from_millis(self.packet_count as u64 * PACKET_DURATION_MS)
+    }
+
+    /// Bits per second over [`estimated_duration`](Self::estimated_duration), so it
+    /// overstates the rate of streams with longer packets. 0 for an empty stream.
+    pub fn average_bitrate(&self) -> u64 {
+        let duration_ms = self.estimated_duration().as_millis() as u64;
+        (self.payload_bytes as u64 * 8 * 1000)
+            .checked_div(duration_ms)
+            .unwrap_or(0)
+    }
+}
+
+/// Reads the layout of `src` from the header and the length prefixes alone, the SDK is
+/// not called, so this is safe to run on untrusted input.
+pub fn probe_silk(src: &[u8]) -> Result<SilkInfo, SilkError> {
+    let mut frames = SilkFrameIter::new(src);
+    let (mut packet_count, mut payload_bytes) = (0, 0);
+    for frame in frames.by_ref() {
+        let frame = frame?;
+        packet_count += 1;
+        payload_bytes += frame.len();
+    }
+
+    let tencent = src.starts_with(&[TENCENT_PREFIX]);
+    let has_terminator = src.len() >= frames.offset + 2
+        && i16::from_le_bytes([src[frames.offset], src[frames.offset + 1]]) < 0;
+    Ok(SilkInfo {
+        variant: SilkHeaderVariant::from_flags(tencent, has_terminator),
+        packet_count,
+        payload_bytes,
+        has_terminator,
+    })
+}
+
+// SKP_Silk_SDK_get_TOC stores the flags of every frame it parses before checking the
+// frame count, so a packet claiming more than MAX_FRAMES_PER_PACKET frames writes past
+// the struct. Each frame spends more than a bit on its termination code, which bounds
+// the excess by the packet's bit count.
+#[repr(C)]
+struct TocBuffer {
+    toc: sdk::SKP_Silk_TOC_struct,
+    overflow: [i32; MAX_PACKET_BYTES * 8],
+}
+
+impl TocBuffer {
+    // 32 KiB, allocated once per stream rather than per packet
+    fn new() -> Box<Self> {
+        Box::new(Self {
+            toc: sdk::SKP_Silk_TOC_struct {
+                framesInPacket: 0,
+                fs_kHz: 0,
+                inbandLBRR: 0,
+                corrupt: 0,
+                vadFlags: [0; MAX_FRAMES_PER_PACKET],
+                sigtypeFlags: [0; MAX_FRAMES_PER_PACKET],
+            },
+            overflow: [0; MAX_PACKET_BYTES * 8],
+        })
+    }
+
+    /// Table of contents of `payload`, `None` if it is corrupt.
+    fn parse(&mut self, payload: &[u8]) -> Option<&sdk::SKP_Silk_TOC_struct> {
+        unsafe {
+            sdk::SKP_Silk_SDK_get_TOC(payload.as_ptr(), payload.len() as i32, &mut self.toc);
+        }
+        (self.toc.corrupt == 0).then_some(&self.toc)
+    }
+
+    /// Frame count from the table of contents of `payload`, `None` if it is corrupt.
+    fn frames(&mut self, payload: &[u8]) -> Option<u64> {
+        self.parse(payload).map(|toc| toc.framesInPacket as u64)
+    }
+}
+
+fn frames_in_packet(toc: &mut TocBuffer, frame: &SilkFrame) -> Result<u64, SilkError> {
+    toc.frames(frame.payload).ok_or_else(|| SilkError::Frame {
+        index: frame.index,
+        offset: frame.offset,
+        source: Box::new(SilkError::DecPayloadError),
+    })
+}
+
+fn decode_packets<F>(
+    packets: &[Option<&[u8]>],
+    options: &SilkDecodeOptions,
+    mut on_frame: F,
+) -> Result<DecodeReport, SilkError>
+where
+    F: FnMut(&[i16]) -> ControlFlow<()>,
+{
+    let mut decoder = PacketDecoder::new(options.sample_rate.hz())?;
+    let mut report = DecodeReport::default();
+    let mut fec = [0u8; MAX_PACKET_BYTES];
+    for (index, packet) in packets.iter().enumerate() {
+        let packet = match packet {
+            None if options.in_band_fec => search_lbrr(&packets[index + 1..], &mut fec),
+            packet => *packet,
+        };
+        let flow = match (decoder.decode(packet, &mut on_frame), options.lenient) {
+            (Err(_), Some(fill)) => {
+                report.skipped.push(index);
+                decoder.fill(fill, &mut on_frame)?
+            }
+            (flow, _) => flow?,
+        };
+        if flow.is_break() {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+/// FEC copy of a lost packet from the packets following it, decoded like a regular one.
+fn search_lbrr<'a>(
+    following: &[Option<&[u8]>],
+    buf: &'a mut [u8; MAX_PACKET_BYTES],
+) -> Option<&'a [u8]> {
+    for (offset, packet) in following.iter().take(MAX_LBRR_DELAY).enumerate() {
+        let Some(packet) = packet.filter(|packet| !packet.is_empty()) else {
+            continue;
+        };
+        let mut len = 0i16;
+        unsafe {
+            sdk::SKP_Silk_SDK_search_for_LBRR(
+                packet.as_ptr(),
+                packet.len() as i32,
+                offset as i32 + 1,
+                buf.as_mut_ptr(),
+                &mut len,
+            );
+        }
+        if len > 0 {
+            return Some(&buf[..len as usize]);
+        }
+    }
+    None
+}
+
+/// Decoder keeping its state across packets that arrive one at a time, e.g. from a
+/// socket. Output is mono samples at the rate given to [`new`](Self::new).
+pub struct SilkDecoder {
+    decoder: PacketDecoder,
+    pending: Vec<u8>,
+    // container offset of the first pending byte
+    offset: usize,
+    header_read: bool,
+    finished: bool,
+}
+
+impl SilkDecoder {
+    pub fn new(sample_rate: i32) -> Result<Self, SilkError> {
+        Ok(Self {
+            decoder: PacketDecoder::new(sample_rate)?,
+            pending: vec![],
+            offset: 0,
+            header_read: false,
+            finished: false,
+        })
+    }
+
+    /// Decodes one bare packet, an empty one is a DTX frame.
+    pub fn decode_packet(&mut self, packet: &[u8]) -> Result<Vec<i16>, SilkError> {
+        let mut samples = vec![];
+        decode_into_samples(&mut self.decoder, Some(packet), &mut samples)?;
+        Ok(samples)
+    }
+
+    /// Conceals a packet that never arrived, from the ones decoded before it.
+    pub fn conceal(&mut self) -> Result<Vec<i16>, SilkError> {
+        let mut samples = vec![];
+        decode_into_samples(&mut self.decoder, None, &mut samples)?;
+        Ok(samples)
+    }
+
+    /// [`decode_silk_into`] at the rate of the decoder, reusing its SDK state. The state
+    /// is re-initialized first and the chunked input of
+    /// [`feed_container_bytes`](Self::feed_container_bytes) is dropped, so nothing
+    /// carries over from earlier calls.
+    pub fn decode_into(&mut self, src: &[u8], out: &mut Vec<u8>) -> Result<usize, SilkError> {
+        out.clear();
+        self.pending.clear();
+        self.offset = 0;
+        self.header_read = false;
+        self.finished = false;
+        self.decoder.reset()?;
+        decode_container_with(&mut self.decoder, src, None, |samples| {
+            for sample in samples {
+                out.extend_from_slice(&sample.to_le_bytes());
+            }
+            ControlFlow::Continue(())
+        })?;
+        Ok(out.len())
+    }
+
+    /// Takes a container in chunks of any size and returns the samples of the packets
+    /// completed by `bytes`. The header is stripped, nothing after the terminator is
+    /// decoded.
+    pub fn feed_container_bytes(&mut self, bytes: &[u8]) -> Result<Vec<i16>, SilkError> {
+        let mut samples = vec![];
+        if self.finished {
+            return Ok(samples);
+        }
+        self.pending.extend_from_slice(bytes);
+        if !self.header_read && !self.read_header()? {
+            return Ok(samples);
+        }
+
+        let Self {
+            decoder,
+            pending,
+            offset,
+            finished,
+            ..
+        } = self;
+        let mut rest = &pending[..];
+        while rest.len() >= 2 {
+            let len = i16::from_le_bytes([rest[0], rest[1]]);
+            if len < 0 {
+                *finished = true;
+                break;
+            }
+            let size = len as usize;
+            if size > MAX_PACKET_BYTES {
+                return Err(SilkError::InvalidPacketLength {
+                    offset: *offset + pending.len() - rest.len(),
+                    len,
+                });
+            }
+            if rest.len() < 2 + size {
+                break;
+            }
+            decode_into_samples(decoder, Some(&rest[2..2 + size]), &mut samples)?;
+            rest = &rest[2 + size..];
+        }
+        let consumed = pending.len() - rest.len();
+        pending.drain(..consumed);
+        *offset += consumed;
+        if *finished {
+            pending.clear();
+        }
+        Ok(samples)
+    }
+
+    fn read_header(&mut self) -> Result<bool, SilkError> {
+        let prefix = usize::from(self.pending.first() == Some(&TENCENT_PREFIX));
+        let received = &self.pending[prefix..];
+        if received.len() < SILK_HEADER.len() {
+            if !SILK_HEADER.starts_with(received) {
+                return Err(SilkError::Invalid);
+            }
+            return Ok(false);
+        }
+        if !received.starts_with(SILK_HEADER) {
+            return Err(SilkError::Invalid);
+        }
+        self.pending.drain(..prefix + SILK_HEADER.len());
+        self.offset = prefix + SILK_HEADER.len();
+        self.header_read = true;
+        Ok(true)
+    }
+}
+
+fn decode_into_samples(
+    decoder: &mut PacketDecoder,
+    packet: Option<&[u8]>,
+    samples: &mut Vec<i16>,
+) -> Result<(), SilkError> {
+    // the sink never stops, so the control flow is always Continue
+    let _ = decoder.decode(packet, &mut |frame: &[i16]| {
+        samples.extend_from_slice(frame);
+        ControlFlow::Continue(())
+    })?;
+    Ok(())
+}
+
+struct PacketDecoder {
+    state: CodecState,
+    control: sdk::SKP_SILK_SDK_DecControlStruct,
+    buf: Vec<i16>,
+    // the first SDK call after an init sets up the resampler and overwrites the error
+    // code of the frame with its own, so the first packet is checked up front
+    fresh: bool,
+}
+
+impl PacketDecoder {
+    fn new(sample_rate: i32) -> Result<Self, SilkError> {
+        let frame_samples = SampleRate::try_from(sample_rate)?.samples(PACKET_DURATION_MS as usize);
+
+        let mut decoder_size = 0;
+        fast_check!(sdk::SKP_Silk_SDK_Get_Decoder_Size(&mut decoder_size));
+
+        let mut decoder = Self {
+            state: CodecState::new(decoder_size as usize),
+            control: sdk::SKP_SILK_SDK_DecControlStruct {
+                API_sampleRate: sample_rate,
+                frameSize: 0,
+                framesPerPacket: 1,
+                moreInternalDecoderFrames: 0,
+                inBandFECOffset: 0,
+            },
+            // one call decodes a single 20 ms frame, longer packets take one call per
+            // frame, so this bounds the output of every call whatever the packet holds
+            buf: vec![0i16; frame_samples],
+            fresh: true,
+        };
+        decoder.reset()?;
+        Ok(decoder)
+    }
+
+    fn reset(&mut self) -> Result<(), SilkError> {
+        fast_check!(sdk::SKP_Silk_SDK_InitDecoder(self.state.as_mut_ptr()));
+        self.fresh = true;
+        Ok(())
+    }
+
+    /// Stands in for a packet that could not be decoded.
+    fn fill<F>(
+        &mut self,
+        fill: CorruptPacketFill,
+        on_frame: &mut F,
+    ) -> Result<ControlFlow<()>, SilkError>
+    where
+        F: FnMut(&[i16]) -> ControlFlow<()>,
+    {
+        match fill {
+            CorruptPacketFill::Drop => Ok(ControlFlow::Continue(())),
+            CorruptPacketFill::Silence => {
+                self.buf.fill(0);
+                Ok(on_frame(&self.buf))
+            }
+            CorruptPacketFill::Conceal => self.decode(None, on_frame),
+        }
+    }
+
+    /// Decodes one packet, `None` conceals a lost one.
+    fn decode<F>(
+        &mut self,
+        packet: Option<&[u8]>,
+        on_frame: &mut F,
+    ) -> Result<ControlFlow<()>, SilkError>
+    where
+        F: FnMut(&[i16]) -> ControlFlow<()>,
+    {
+        let input = packet.unwrap_or_default();
+        if input.len() > MAX_PACKET_BYTES {
+            return Err(SilkError::DecPayloadTooLarge);
+        }
+
+        // empty packets are DTX frames, decoded as a lost packet (comfort noise)
+        let lost_flag = input.is_empty() as i32;
+        if self.fresh && lost_flag == 0 && TocBuffer::new().frames(input).is_none() {
+            return Err(SilkError::DecPayloadError);
+        }
+        self.fresh = false;
+        // concealment also runs one frame per call, as many as the last packet held
+        let mut lost_frames = self.control.framesPerPacket.max(1);
+
+        // packets longer than 20 ms hold several frames, decoded one call each
+        loop {
+            let mut output_size = 0i16;
+            fast_check!(sdk::SKP_Silk_SDK_Decode(
+                self.state.as_mut_ptr(),
+                &mut self.control,
+                lost_flag,
+                input.as_ptr(),
+                input.len() as i32,
+                self.buf.as_mut_ptr(),
+                &mut output_size,
+            ));
+
+            debug_assert!(output_size as usize <= self.buf.len());
+            if on_frame(&self.buf[0..output_size as usize]).is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+            let more_frames = if lost_flag == 1 {
+                lost_frames -= 1;
+                lost_frames > 0
+            } else {
+                self.control.moreInternalDecoderFrames != 0
+            };
+            if !more_frames {
+                return Ok(ControlFlow::Continue(()));
+            }
+        }
+    }
+}
+
+/// Level [`SilkEncodeOptions::normalize`] brings the input to, in dBFS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalizeMode {
+    Peak(f32),
+    Rms(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilkEncodeOptions {
+    pub(crate) sample_rate: SampleRate,
+    pub(crate) bit_rate: i32,
+    pub(crate) tencent: bool,
+    pub(crate) prime_ms: u16,
+    pub(crate) channels: u8,
+    pub(crate) fit_to_size: Option<u64>,
+    pub(crate) in_band_fec: bool,
+    pub(crate) dtx: bool,
+    pub(crate) packet_loss_percentage: Option<u8>,
+    pub(crate) complexity: u8,
+    pub(crate) max_internal_sample_rate: Option<i32>,
+    pub(crate) packet_duration_ms: u16,
+    pub(crate) pad_final_frame: bool,
+    pub(crate) terminator: Option<bool>,
+    pub(crate) max_duration: Option<Duration>,
+    pub(crate) strict_max_duration: bool,
+    pub(crate) normalize: Option<NormalizeMode>,
+    pub(crate) reject_silence: bool,
+    pub(crate) analyze_input: bool,
+}
+
+impl Default for SilkEncodeOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: SampleRate::Hz24000,
+            bit_rate: 24000,
+            tencent: false,
+            prime_ms: 0,
+            channels: 1,
+            fit_to_size: None,
+            in_band_fec: false,
+            dtx: false,
+            packet_loss_percentage: None,
+            complexity: 2,
+            max_internal_sample_rate: None,
+            packet_duration_ms: PACKET_DURATION_MS as u16,
+            pad_final_frame: true,
+            terminator: None,
+            max_duration: None,
+            strict_max_duration: false,
+            normalize: None,
+            reject_silence: false,
+            analyze_input: false,
+        }
+    }
+}
+
+impl SilkEncodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rate of the input PCM, 24 kHz by default. Functions taking the rate in Hz
+    /// convert it with `SampleRate::try_from` and fail with
+    /// [`SilkError::UnsupportedSampleRate`] before encoding anything.
+    pub fn sample_rate(mut self, sample_rate: SampleRate) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Target bit rate in bps, 5000..=100000. The encoder clamps it to what the
+    /// internal rate can use: about 6-20 kbps at 8 kHz, 7-25 kbps at 12 kHz, 8-30 kbps
+    /// at 16 kHz and 12-40 kbps at 24 kHz.
+    pub fn bit_rate(mut self, bit_rate: i32) -> Self {
+        self.bit_rate = bit_rate;
+        self
+    }
+
+    pub fn tencent(mut self, tencent: bool) -> Self {
+        self.tencent = tencent;
+        self
+    }
+
+    /// Puts the first `prime_ms` of the input, 10 dB down, in front of the stream, so
+    /// the encoder and the decoder both start the first real frame warmed up. Rounded
+    /// up to whole packets, input shorter than that is primed padded with silence.
+    /// The primer packets are part of the stream; decode it with
+    /// [`SilkDecodeOptions::skip_leading_ms`] set to the rounded primer length to get
+    /// the input length back. Encode stats and progress count only the real frames.
+    pub fn prime_ms(mut self, prime_ms: u16) -> Self {
+        self.prime_ms = prime_ms;
+        self
+    }
+
+    /// Channel count of the interleaved input PCM, stereo input is mixed down to mono
+    /// before framing.
+    pub fn channels(mut self, channels: u8) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Replaces the bit rate with the highest one whose estimated output stays within
+    /// `max_bytes` for the given input, see [`max_bitrate_for_size`]. The encoders that
+    /// return the whole stream encode again at a lower rate when the SDK overshoots the
+    /// estimate; the callback, progress and cancellable ones only go by the estimate.
+    pub fn fit_to_size(mut self, max_bytes: u64) -> Self {
+        self.fit_to_size = Some(max_bytes);
+        self
+    }
+
+    /// Embeds in-band FEC so a decoder can recover lost packets from the next one,
+    /// the encoder then expects 10% packet loss unless `packet_loss_percentage` is set.
+    pub fn in_band_fec(mut self, in_band_fec: bool) -> Self {
+        self.in_band_fec = in_band_fec;
+        self
+    }
+
+    /// Discontinuous transmission: silent frames are sent as empty packets, which the
+    /// decoder fills with comfort noise of the same length.
+    pub fn dtx(mut self, dtx: bool) -> Self {
+        self.dtx = dtx;
+        self
+    }
+
+    /// Expected packet loss (0..=100), higher values trade bit rate for robustness.
+    pub fn packet_loss_percentage(mut self, packet_loss_percentage: u8) -> Self {
+        self.packet_loss_percentage = Some(packet_loss_percentage);
+        self
+    }
+
+    /// Encoder complexity 0 (fastest) to 2 (best quality, the default).
+    pub fn complexity(mut self, complexity: u8) -> Self {
+        self.complexity = complexity;
+        self
+    }
+
+    /// Caps the rate the encoder works at internally (8000, 12000, 16000 or 24000),
+    /// e.g. 8000 for narrowband streams. Defaults to the input rate, at most 24000.
+    pub fn max_internal_sample_rate(mut self, max_internal_sample_rate: i32) -> Self {
+        self.max_internal_sample_rate = Some(max_internal_sample_rate);
+        self
+    }
+
+    /// Audio per packet, 20 (the default), 40, 60, 80 or 100 ms. Longer packets cut
+    /// the per-packet container overhead of stored messages.
+    pub fn packet_duration_ms(mut self, packet_duration_ms: u16) -> Self {
+        self.packet_duration_ms = packet_duration_ms;
+        self
+    }
+
+    /// Zero-pads the trailing partial frame of the input and encodes it (the default),
+    /// otherwise up to one packet of audio at the end is dropped.
+    pub fn pad_final_frame(mut self, pad_final_frame: bool) -> Self {
+        self.pad_final_frame = pad_final_frame;
+        self
+    }
+
+    /// Ends the stream with the `-1` length block. Unset, plain streams get it and
+    /// tencent streams (which QQ expects without it) do not.
+    pub fn terminator(mut self, terminator: bool) -> Self {
+        self.terminator = Some(terminator);
+        self
+    }
+
+    /// Sets [`tencent`](Self::tencent) and [`terminator`](Self::terminator) together.
+    pub fn header(self, variant: SilkHeaderVariant) -> Self {
+        self.tencent(variant.tencent())
+            .terminator(variant.terminated())
+    }
+
+    /// Encodes at most `max_duration` of the input and drops the rest, e.g. for the
+    /// 60 s limit of QQ voice messages.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Fails longer input with [`SilkError::InputTooLong`] instead of truncating it.
+    pub fn strict_max_duration(mut self, strict_max_duration: bool) -> Self {
+        self.strict_max_duration = strict_max_duration;
+        self
+    }
+
+    /// Scales the input to the `mode` level before encoding, quiet recordings suffer
+    /// most at low bit rates. The gain never takes the peak past full scale, so an RMS
+    /// target can end up lower than asked instead of clipping.
+    pub fn normalize(mut self, mode: NormalizeMode) -> Self {
+        self.normalize = Some(mode);
+        self
+    }
+
+    /// Fails input that is silent throughout (below -60 dBFS, e.g. the zeros of a
+    /// broken capture path) with [`SilkError::SilentInput`].
+    pub fn reject_silence(mut self, reject_silence: bool) -> Self {
+        self.reject_silence = reject_silence;
+        self
+    }
+
+    /// Measures the levels of the input for [`EncodeStats::input`], an extra pass over
+    /// the whole input. Off by default.
+    pub fn analyze_input(mut self, analyze_input: bool) -> Self {
+        self.analyze_input = analyze_input;
+        self
+    }
+
+    /// The defaults at `sample_rate` Hz, for the functions taking the rate as a number.
+    pub(crate) fn at(sample_rate: i32) -> Result<Self, SilkError> {
+        Ok(Self::new().sample_rate(SampleRate::try_from(sample_rate)?))
+    }
+
+    fn writes_terminator(&self) -> bool {
+        self.terminator.unwrap_or(!self.tencent)
+    }
+
+    fn enc_control(&self) -> sdk::SKP_SILK_SDK_EncControlStruct {
+        sdk::SKP_SILK_SDK_EncControlStruct {
+            API_sampleRate: self.sample_rate.hz(),
+            maxInternalSampleRate: self
+                .max_internal_sample_rate
+                .unwrap_or(self.sample_rate.hz().min(24000)),
+            packetSize: (self.packet_duration_ms as i32 * self.sample_rate.hz()) / 1000,
+            bitRate: self.bit_rate,
+            packetLossPercentage: match self.packet_loss_percentage {
+                Some(packet_loss_percentage) => packet_loss_percentage as i32,
+                None if self.in_band_fec => FEC_PACKET_LOSS_PERCENTAGE,
+                None => 0,
+            },
+            complexity: self.complexity as i32,
+            useInBandFEC: self.in_band_fec as i32,
+            useDTX: self.dtx as i32,
+        }
+    }
+}
+
+/// Container framing around the packets of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SilkHeaderVariant {
+    /// `#!SILK_V3`, packets, `-1` terminator.
+    Standard,
+    /// `#!SILK_V3`, packets.
+    StandardUnterminated,
+    /// `0x02 #!SILK_V3`, packets, as sent by QQ and WeChat.
+    Tencent,
+    /// `0x02 #!SILK_V3`, packets, `-1` terminator.
+    TencentTerminated,
+}
+
+impl SilkHeaderVariant {
+    /// Reads the variant of `src`, whose packets have to be complete up to the
+    /// terminator or the end.
+    pub fn detect<R: AsRef<[u8]>>(src: R) -> Result<Self, SilkError> {
+        let src = src.as_ref();
+        let tencent = src.starts_with(&[TENCENT_PREFIX]);
+        let packets = src[tencent as usize..]
+            .strip_prefix(SILK_HEADER)
+            .ok_or(SilkError::Invalid)?;
+        let terminated = packets_len(packets)? < packets.len();
+        Ok(Self::from_flags(tencent, terminated))
+    }
+
+    fn from_flags(tencent: bool, terminated: bool) -> Self {
+        match (tencent, terminated) {
+            (false, true) => Self::Standard,
+            (false, false) => Self::StandardUnterminated,
+            (true, false) => Self::Tencent,
+            (true, true) => Self::TencentTerminated,
+        }
+    }
+
+    pub fn tencent(self) -> bool {
+        matches!(self, Self::Tencent | Self::TencentTerminated)
+    }
+
+    pub fn terminated(self) -> bool {
+        matches!(self, Self::Standard | Self::TencentTerminated)
+    }
+}
+
+/// Common encode targets, all with 20 ms packets and mono input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SilkPreset {
+    /// 24 kHz, 24 kbps, tencent prefix.
+    QqVoice,
+    /// 24 kHz, 30 kbps, tencent prefix.
+    WeChatVoice,
+    /// 24 kHz, 40 kbps (the most the 24 kHz internal rate uses), plain header.
+    HighQuality,
+    /// 8 kHz, 8 kbps, plain header.
+    LowBandwidth,
+}
+
+impl SilkPreset {
+    pub fn options(self) -> SilkEncodeOptions {
+        let (sample_rate, bit_rate, tencent) = match self {
+            Self::QqVoice => (SampleRate::Hz24000, 24000, true),
+            Self::WeChatVoice => (SampleRate::Hz24000, 30000, true),
+            Self::HighQuality => (SampleRate::Hz24000, 40000, false),
+            Self::LowBandwidth => (SampleRate::Hz8000, 8000, false),
+        };
+        SilkEncodeOptions::new()
+            .sample_rate(sample_rate)
+            .bit_rate(bit_rate)
+            .tencent(tencent)
+    }
+}
+
+impl From<SilkPreset> for SilkEncodeOptions {
+    fn from(preset: SilkPreset) -> Self {
+        preset.options()
+    }
+}
+
+/// Settings the SDK encoder is running with, as reported by the SDK after the last
+/// encoded frame. The bit rate is the requested one limited to 5000..=100000; at a
+/// low internal rate the stream stays well below it, see [`SilkEncodeOptions::bit_rate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderStatus {
+    pub sample_rate: i32,
+    pub max_internal_sample_rate: i32,
+    /// Samples per packet at `sample_rate`.
+    pub packet_size: i32,
+    pub bit_rate: i32,
+    pub packet_loss_percentage: i32,
+    pub complexity: i32,
+    pub in_band_fec: bool,
+    pub dtx: bool,
+}
+
+impl From<sdk::SKP_SILK_SDK_EncControlStruct> for EncoderStatus {
+    fn from(status: sdk::SKP_SILK_SDK_EncControlStruct) -> Self {
+        Self {
+            sample_rate: status.API_sampleRate,
+            max_internal_sample_rate: status.maxInternalSampleRate,
+            packet_size: status.packetSize,
+            bit_rate: status.bitRate,
+            packet_loss_percentage: status.packetLossPercentage,
+            complexity: status.complexity,
+            in_band_fec: status.useInBandFEC != 0,
+            dtx: status.useDTX != 0,
+        }
+    }
+}
+
+/// Rejects settings the SDK would refuse (or silently ignore) before any FFI call.
+fn validate_enc_control(control: &sdk::SKP_SILK_SDK_EncControlStruct) -> Result<(), SilkError> {
+    if !(MIN_BIT_RATE..=MAX_BIT_RATE).contains(&control.bitRate) {
+        return Err(SilkError::InvalidBitRate {
+            given: control.bitRate,
+            min: MIN_BIT_RATE,
+            max: MAX_BIT_RATE,
+        });
+    }
+    if !INTERNAL_SAMPLE_RATES.contains(&control.maxInternalSampleRate) {
+        return Err(SilkError::EncFsNotSupported);
+    }
+    if !(0..=100).contains(&control.packetLossPercentage) {
+        return Err(SilkError::EncInvalidLossRate);
+    }
+    if !(0..=2).contains(&control.complexity) {
+        return Err(SilkError::EncInvalidComplexitySetting);
+    }
+    if control.useInBandFEC != 0 && control.packetLossPercentage == 0 {
+        return Err(SilkError::EncInvalidInbandFecSetting);
+    }
+    Ok(())
+}
+
+#[deprecated(note = "use `encode_silk_with_options` with `SilkEncodeOptions::header`")]
+pub fn encode_silk<R: AsRef<[u8]>>(
+    src: R,
+    sample_rate: i32,
+    bit_rate: i32,
+    tencent: bool,
+) -> Result<Vec<u8>, SilkError> {
+    let options = SilkEncodeOptions::at(sample_rate)?
+        .bit_rate(bit_rate)
+        .tencent(tencent);
+    encode_silk_with_options(src, &options)
+}
+
+pub fn encode_silk_with_options<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkEncodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    encode_pcm(src.as_ref(), options)
+}
+
+pub fn encode_silk_preset<R: AsRef<[u8]>>(
+    src: R,
+    preset: SilkPreset,
+) -> Result<Vec<u8>, SilkError> {
+    encode_silk_with_options(src, &preset.options())
+}
+
+/// Same as [`encode_silk_with_options`] for native `i16` samples, without going
+/// through bytes.
+pub fn encode_silk_samples_with_options(
+    samples: &[i16],
+    options: &SilkEncodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    encode_pcm(samples, options)
+}
+
+/// Same as [`encode_silk_with_options`] for normalized `f32` samples, clamped to
+/// -1..=1 with NaN encoded as silence.
+pub fn encode_silk_f32_with_options(
+    samples: &[f32],
+    options: &SilkEncodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    let samples = samples
+        .iter()
+        .map(|&sample| quantize_f32(sample))
+        .collect::<Vec<_>>();
+    encode_pcm(&samples, options)
+}
+
+/// Figures of one [`encode_silk_with_stats`] run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EncodeStats {
+    pub frames: usize,
+    /// Mono samples of input, after stereo is mixed down.
+    pub input_samples: usize,
+    /// Zeros appended to complete the last frame.
+    pub padded_samples: usize,
+    /// Trailing partial frame left out without `pad_final_frame`.
+    pub dropped_samples: usize,
+    pub output_bytes: usize,
+    pub duration: Duration,
+    /// Input past `max_duration` was cut off.
+    pub truncated: bool,
+    /// Levels of the mono input as it went into the encoder, only measured with
+    /// [`SilkEncodeOptions::analyze_input`].
+    pub input: Option<InputReport>,
+    /// Average over the whole stream in bps, container overhead included.
+    pub bit_rate: u64,
+    /// Bit rate the encoder was set to, the one `fit_to_size` chose when it is set.
+    pub target_bit_rate: i32,
+}
+
+impl EncodeStats {
+    fn new(
+        input_samples: usize,
+        frame_samples: usize,
+        output_bytes: usize,
+        truncated: bool,
+        input: Option<InputReport>,
+        options: &SilkEncodeOptions,
+    ) -> Self {
+        let remainder = input_samples % frame_samples;
+        let padded = options.pad_final_frame && remainder != 0;
+        let frames = input_samples / frame_samples + padded as usize;
+        let duration_ms = frames as u64 * options.packet_duration_ms as u64;
+        Self {
+            frames,
+            input_samples,
+            padded_samples: if padded { frame_samples - remainder } else { 0 },
+            dropped_samples: if options.pad_final_frame {
+                0
+            } else {
+                remainder
+            },
+            output_bytes,
+            duration: Duration::from_millis(duration_ms),
+            truncated,
+            input,
+            bit_rate: (output_bytes as u64 * 8 * 1000)
+                .checked_div(duration_ms)
+                .unwrap_or(0),
+            target_bit_rate: options.bit_rate,
+        }
+    }
+}
+
+/// [`encode_silk_with_options`] that also reports what was encoded.
+pub fn encode_silk_with_stats<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkEncodeOptions,
+) -> Result<(Vec<u8>, EncodeStats), SilkError> {
+    let src = src.as_ref();
+    let (mono, mut options) = prepare_pcm(src, options)?;
+    let mut encoder = PacketEncoder::new(&options)?;
+    let result = unsafe { _encode_silk_fitted(&mut encoder, &mono, &mut options)? };
+    let truncated = mono.len() < src.len() / options.channels as usize;
+    let stats = EncodeStats::new(
+        mono.len() / 2,
+        encoder.frame_size / 2,
+        result.len(),
+        truncated,
+        options
+            .analyze_input
+            .then(|| analyze(&mono, options.sample_rate.hz())),
+        &options,
+    );
+    Ok((result, stats))
+}
+
+/// PCM the encoder takes directly, s16le bytes or samples.
+trait PcmInput: Copy + Default {
+    /// Elements per mono sample.
+    const PER_SAMPLE: usize;
+
+    fn downmix_stereo(src: &[Self]) -> Vec<Self>;
+
+    fn samples(src: &[Self]) -> impl Iterator<Item = i16> + '_;
+
+    fn from_samples(samples: impl Iterator<Item = i16>) -> Vec<Self>;
+
+    fn encode<'a>(encoder: &'a mut PacketEncoder, frame: &[Self]) -> Result<&'a [u8], SilkError>;
+}
+
+impl PcmInput for u8 {
+    const PER_SAMPLE: usize = 2;
+
+    fn downmix_stereo(src: &[u8]) -> Vec<u8> {
+        downmix_stereo_pcm(src)
+    }
+
+    fn samples(src: &[u8]) -> impl Iterator<Item = i16> + '_ {
+        src.chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+    }
+
+    fn from_samples(samples: impl Iterator<Item = i16>) -> Vec<u8> {
+        samples.flat_map(i16::to_le_bytes).collect()
+    }
+
+    fn encode<'a>(encoder: &'a mut PacketEncoder, frame: &[u8]) -> Result<&'a [u8], SilkError> {
+        encoder.encode(frame)
+    }
+}
+
+impl PcmInput for i16 {
+    const PER_SAMPLE: usize = 1;
+
+    fn downmix_stereo(src: &[i16]) -> Vec<i16> {
+        src.chunks_exact(2)
+            .map(|frame| ((frame[0] as i32 + frame[1] as i32) / 2) as i16)
+            .collect()
+    }
+
+    fn samples(src: &[i16]) -> impl Iterator<Item = i16> + '_ {
+        src.iter().copied()
+    }
+
+    fn from_samples(samples: impl Iterator<Item = i16>) -> Vec<i16> {
+        samples.collect()
+    }
+
+    fn encode<'a>(encoder: &'a mut PacketEncoder, frame: &[i16]) -> Result<&'a [u8], SilkError> {
+        encoder.encode_samples(frame)
+    }
+}
+
+fn encode_pcm<T: PcmInput>(src: &[T], options: &SilkEncodeOptions) -> Result<Vec<u8>, SilkError> {
+    let (mono, mut options) = prepare_pcm(src, options)?;
+    let mut encoder = PacketEncoder::new(&options)?;
+    unsafe { _encode_silk_fitted(&mut encoder, &mono, &mut options) }
+}
+
+/// Validates `src` and mixes it down to mono, `fit_to_size` is resolved to a bit rate.
+fn prepare_pcm<'a, T: PcmInput>(
+    src: &'a [T],
+    options: &SilkEncodeOptions,
+) -> Result<(Cow<'a, [T]>, SilkEncodeOptions), SilkError> {
+    let pcm_frame_len = match options.channels {
+        1 => T::PER_SAMPLE,
+        2 => T::PER_SAMPLE * 2,
+        channels => return Err(SilkError::UnsupportedChannels(channels)),
+    };
+    if src.is_empty() || !src.len().is_multiple_of(pcm_frame_len) {
+        return Err(SilkError::InvalidPcmLength(src.len()));
+    }
+    let mut src = src;
+    if let Some(limit) = options.max_duration {
+        let max_len = max_input_len(limit, options.sample_rate.hz(), pcm_frame_len);
+        if src.len() > max_len {
+            if options.strict_max_duration {
+                return Err(SilkError::InputTooLong {
+                    limit,
+                    actual: pcm_duration(src.len() / pcm_frame_len, options.sample_rate.hz()),
+                });
+            }
+            src = &src[..max_len];
+        }
+    }
+    let mono = if options.channels == 2 {
+        Cow::Owned(T::downmix_stereo(src))
+    } else {
+        Cow::Borrowed(src)
+    };
+    if options.reject_silence && analyze(&mono, options.sample_rate.hz()).is_silent() {
+        return Err(SilkError::SilentInput);
+    }
+    let mono = match options.normalize {
+        Some(mode) => Cow::Owned(normalize_pcm(&mono, mode)),
+        None => mono,
+    };
+
+    let mut options = *options;
+    if let Some(max_bytes) = options.fit_to_size {
+        let duration = pcm_duration(mono.len() / T::PER_SAMPLE, options.sample_rate.hz());
+        options.bit_rate = max_bitrate_for_size(duration, max_bytes, &options)
+            .ok_or(SilkError::SizeBudgetTooSmall { max_bytes })?;
+    }
+    Ok((mono, options))
+}
+
+/// Levels of a piece of input, see [`analyze_pcm`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct InputReport {
+    /// `-inf` for all-zero input.
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    /// Samples at full scale.
+    pub clipped_samples: usize,
+    /// Below -60 dBFS at the start, the whole input when it is silent throughout.
+    pub leading_silence_ms: u64,
+    pub trailing_silence_ms: u64,
+}
+
+impl InputReport {
+    pub fn is_silent(&self) -> bool {
+        self.peak_dbfs < SILENCE_DBFS
+    }
+}
+
+/// Peak and RMS level, clipping and silence at the edges of mono s16le `pcm`.
+pub fn analyze_pcm<R: AsRef<[u8]>>(pcm: R, sample_rate: i32) -> InputReport {
+    analyze(pcm.as_ref(), sample_rate)
+}
+
+fn analyze<T: PcmInput>(src: &[T], sample_rate: i32) -> InputReport {
+    let threshold = (db_to_gain(SILENCE_DBFS) * FULL_SCALE) as i32;
+    let (mut peak, mut square_sum, mut samples, mut clipped_samples) = (0i32, 0f64, 0usize, 0);
+    let (mut first_sound, mut last_sound) = (None, 0);
+    for (index, sample) in T::samples(src).enumerate() {
+        let magnitude = (sample as i32).abs();
+        peak = peak.max(magnitude);
+        square_sum += (sample as f64) * (sample as f64);
+        samples += 1;
+        if magnitude >= i16::MAX as i32 {
+            clipped_samples += 1;
+        }
+        if magnitude >= threshold {
+            first_sound.get_or_insert(index);
+            last_sound = index + 1;
+        }
+    }
+
+    let to_ms = |samples: usize| samples as u64 * 1000 / sample_rate.max(1) as u64;
+    let rms = libm::sqrtf((square_sum / samples.max(1) as f64) as f32);
+    InputReport {
+        peak_dbfs: gain_to_db(peak as f32 / FULL_SCALE),
+        rms_dbfs: gain_to_db(rms / FULL_SCALE),
+        clipped_samples,
+        leading_silence_ms: to_ms(first_sound.unwrap_or(samples)),
+        trailing_silence_ms: to_ms(if first_sound.is_some() {
+            samples - last_sound
+        } else {
+            0
+        }),
+    }
+}
+
+fn normalize_pcm<T: PcmInput>(src: &[T], mode: NormalizeMode) -> Vec<T> {
+    let (mut peak, mut square_sum, mut samples) = (0i32, 0f64, 0usize);
+    for sample in T::samples(src) {
+        peak = peak.max((sample as i32).abs());
+        square_sum += (sample as f64) * (sample as f64);
+        samples += 1;
+    }
+    if peak == 0 {
+        return src.to_vec();
+    }
 
-unsafe fn _decode_silk(mut src: &[u8], sample_rate: i32) -> Result<Vec<u8>, SilkError> {
-    // skip tencent flag
-    if src.starts_with(&[0x02]) {
-        src.advance(1);
+    let (level, target_db) = match mode {
+        NormalizeMode::Peak(target_db) => (peak as f32, target_db),
+        NormalizeMode::Rms(target_db) => {
+            (libm::sqrtf((square_sum / samples as f64) as f32), target_db)
+        }
     };
+    let gain = (db_to_gain(target_db) * FULL_SCALE / level).min(i16::MAX as f32 / peak as f32);
+    T::from_samples(T::samples(src).map(|sample| round_to_i16(sample as f32 * gain)))
+}
 
-    const SILK_HEADER: &[u8] = b"#!SILK_V3";
-    if src.starts_with(SILK_HEADER) {
-        src.advance(SILK_HEADER.len());
-    } else {
-        return Err(SilkError::Invalid);
-    };
+/// Linear gain of `db` decibels, `libm` as the `f32` methods live in std.
+pub(crate) fn db_to_gain(db: f32) -> f32 {
+    libm::powf(10.0, db / 20.0)
+}
 
-    let mut dec_control = sdk::SKP_SILK_SDK_DecControlStruct {
-        API_sampleRate: sample_rate,
-        frameSize: 0,
-        framesPerPacket: 1,
-        moreInternalDecoderFrames: 0,
-        inBandFECOffset: 0,
-    };
+/// `-inf` for a gain of 0.
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * libm::log10f(gain)
+}
 
-    let mut decoder_size = 0;
+/// Input elements in `limit` of audio, `frame_len` per sample of every channel.
+fn max_input_len(limit: Duration, sample_rate: i32, frame_len: usize) -> usize {
+    (limit.as_micros() * sample_rate as u128 / 1_000_000) as usize * frame_len
+}
 
-    fast_check!(sdk::SKP_Silk_SDK_Get_Decoder_Size(&mut decoder_size));
+fn pcm_duration(samples: usize, sample_rate: i32) -> Duration {
+    Duration::from_micros(samples as u64 * 1_000_000 / sample_rate.max(1) as u64)
+}
 
-    let mut decoder = vec![0u8; decoder_size as usize];
+fn downmix_stereo_pcm(src: &[u8]) -> Vec<u8> {
+    let mut mono = Vec::with_capacity(src.len() / 2);
+    for frame in src.chunks_exact(4) {
+        let left = i16::from_le_bytes([frame[0], frame[1]]) as i32;
+        let right = i16::from_le_bytes([frame[2], frame[3]]) as i32;
+        mono.put_i16_le(((left + right) / 2) as i16);
+    }
+    mono
+}
 
-    fast_check!(sdk::SKP_Silk_SDK_InitDecoder(
-        decoder.as_mut_ptr() as *mut c_void
-    ));
+unsafe fn _encode_silk<T: PcmInput>(
+    encoder: &mut PacketEncoder,
+    src: &[T],
+    options: &SilkEncodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    unsafe { _encode_silk_observed(encoder, src, options, |_| ControlFlow::Continue(())) }
+}
+
+/// [`_encode_silk`] that honours `fit_to_size` when the SDK overshoots the estimated
+/// bit rate, which it does on hard input: the stream is encoded again at a rate scaled
+/// down by the excess until it fits. `options` ends up with the bit rate used.
+unsafe fn _encode_silk_fitted<T: PcmInput>(
+    encoder: &mut PacketEncoder,
+    src: &[T],
+    options: &mut SilkEncodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    const STEP: i32 = 1000;
 
-    let mut result = vec![];
-    let frame_size = sample_rate as usize / 1000 * 40;
-    let mut buf = vec![0u8; frame_size];
     loop {
-        if src.remaining() < 2 {
-            break;
-        }
-        let input_size = src.get_i16_le();
-        if input_size > frame_size as i16 {
-            return Err(SilkError::Invalid);
+        let encoded = unsafe { _encode_silk(encoder, src, options)? };
+        let Some(max_bytes) = options.fit_to_size else {
+            return Ok(encoded);
+        };
+        let len = encoded.len() as u64;
+        if len <= max_bytes {
+            return Ok(encoded);
         }
-        if src.remaining() < input_size as usize {
-            return Err(SilkError::Invalid);
+        if options.bit_rate <= MIN_BIT_RATE {
+            return Err(SilkError::SizeBudgetTooSmall { max_bytes });
         }
+        let scaled = (options.bit_rate as u64 * max_bytes / len) as i32;
+        options.bit_rate = scaled.min(options.bit_rate - STEP).max(MIN_BIT_RATE);
+        encoder.reset(options)?;
+    }
+}
 
-        let input;
-        (input, src) = src.split_at(input_size as usize);
+/// [`_encode_silk`] calling `on_packet` after each emitted packet, with `None` for the
+/// primer packets, `Break` stops with [`SilkError::Cancelled`].
+unsafe fn _encode_silk_observed<T: PcmInput, F>(
+    encoder: &mut PacketEncoder,
+    src: &[T],
+    options: &SilkEncodeOptions,
+    mut on_packet: F,
+) -> Result<Vec<u8>, SilkError>
+where
+    F: FnMut(Option<EncodeProgress>) -> ControlFlow<()>,
+{
+    let mut result = container_start(
+        options.tencent,
+        estimated_capacity(src.len() / T::PER_SAMPLE * 2, options),
+    );
+    let frame_len = encoder.frame_size / 2 * T::PER_SAMPLE;
+    let total_frames = src.len() / frame_len
+        + usize::from(options.pad_final_frame && !src.len().is_multiple_of(frame_len));
+    encode_packets(encoder, src, options, |frame_index, packet| {
+        put_packet(&mut result, packet);
+        on_packet(frame_index.map(|frame_index| EncodeProgress {
+            frames_done: frame_index + 1,
+            total_frames,
+            bytes_written: result.len(),
+        }))
+    })?;
+    finish_container(&mut result, options.writes_terminator());
+    Ok(result)
+}
 
-        let mut output_size = 0i16;
+/// Hands the primer packets to `on_packet` with `None` and then every packet of `src`
+/// with its index, `Break` stops with [`SilkError::Cancelled`].
+fn encode_packets<T: PcmInput, F>(
+    encoder: &mut PacketEncoder,
+    src: &[T],
+    options: &SilkEncodeOptions,
+    mut on_packet: F,
+) -> Result<(), SilkError>
+where
+    F: FnMut(Option<usize>, &[u8]) -> ControlFlow<()>,
+{
+    let frame_len = encoder.frame_size / 2 * T::PER_SAMPLE;
 
-        fast_check!(sdk::SKP_Silk_SDK_Decode(
-            decoder.as_mut_ptr() as *mut c_void,
-            &mut dec_control,
-            0,
-            input.as_ptr(),
-            input_size as i32,
-            buf.as_mut_ptr() as *mut i16,
-            &mut output_size,
-        ));
+    let prime_len = primer_len(options, T::PER_SAMPLE, frame_len).min(src.len());
+    let primer = primer_pcm(&src[..prime_len]);
+    for chunk in pcm_frames(&primer, frame_len, true) {
+        if on_packet(None, T::encode(encoder, &chunk)?).is_break() {
+            return Err(SilkError::Cancelled);
+        }
+    }
 
-        result.extend_from_slice(&buf[0..output_size as usize * 2])
+    for (frame_index, frame) in pcm_frames(src, frame_len, options.pad_final_frame).enumerate() {
+        if on_packet(Some(frame_index), T::encode(encoder, &frame)?).is_break() {
+            return Err(SilkError::Cancelled);
+        }
     }
-    Ok(result)
+    Ok(())
 }
 
-pub fn encode_silk<R: AsRef<[u8]>>(
+/// Input elements of the primer, `sample_len` per sample of every channel, rounded up
+/// to whole packets of `frame_len`.
+fn primer_len(options: &SilkEncodeOptions, sample_len: usize, frame_len: usize) -> usize {
+    let len = options.sample_rate.samples(options.prime_ms as usize) * sample_len;
+    len.div_ceil(frame_len) * frame_len
+}
+
+/// The primer played back ahead of the input: quiet enough not to stand out, loud
+/// enough to leave the gains and the predictors close to where the input starts.
+fn primer_pcm<T: PcmInput>(src: &[T]) -> Vec<T> {
+    const PRIMER_GAIN: f32 = 0.316; // -10 dB
+
+    T::from_samples(T::samples(src).map(|sample| round_to_i16(sample as f32 * PRIMER_GAIN)))
+}
+
+/// Encodes `src` at the highest bit rate whose output fits in `max_bytes`, searched in
+/// 1 kbps steps by actually encoding. Returns the stream and the bit rate used.
+pub fn encode_silk_to_size<R: AsRef<[u8]>>(
     src: R,
     sample_rate: i32,
-    bit_rate: i32,
+    max_bytes: u64,
     tencent: bool,
+) -> Result<(Vec<u8>, i32), SilkError> {
+    const STEP: i32 = 1000;
+
+    let src = src.as_ref();
+    let options = SilkEncodeOptions::at(sample_rate)?.tencent(tencent);
+    let mut handle = SilkEncoderHandle::new(&options)?;
+    let mut encode = |bit_rate: i32| {
+        handle.options = options.bit_rate(bit_rate);
+        handle
+            .encode(src)
+            .map(|encoded| (encoded.len() as u64 <= max_bytes).then_some(encoded))
+    };
+
+    if let Some(encoded) = encode(MAX_BIT_RATE)? {
+        return Ok((encoded, MAX_BIT_RATE));
+    }
+    let mut best = encode(MIN_BIT_RATE)?
+        .map(|encoded| (encoded, MIN_BIT_RATE))
+        .ok_or(SilkError::SizeBudgetTooSmall { max_bytes })?;
+
+    // `low` fits and `high` does not
+    let (mut low, mut high) = (MIN_BIT_RATE, MAX_BIT_RATE);
+    while high - low > STEP {
+        let mid = (low + (high - low) / 2) / STEP * STEP;
+        let mid = if mid <= low { low + STEP } else { mid };
+        match encode(mid)? {
+            Some(encoded) => {
+                best = (encoded, mid);
+                low = mid;
+            }
+            None => high = mid,
+        }
+    }
+    Ok(best)
+}
+
+/// Encodes `src` into bare SDK packets, without the container header and length
+/// prefixes. [`assemble_silk`] turns them back into a stream.
+pub fn encode_silk_packets<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkEncodeOptions,
+) -> Result<Vec<Vec<u8>>, SilkError> {
+    let mut packets = vec![];
+    encode_silk_with_callback(src, options, |_, packet| packets.push(packet.to_vec()))?;
+    Ok(packets)
+}
+
+/// Frames bare packets as a stream of `variant`, the inverse of
+/// [`encode_silk_packets`].
+pub fn assemble_silk<I>(packets: I, variant: SilkHeaderVariant) -> Result<Vec<u8>, SilkError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut result = container_start(variant.tencent(), 0);
+    for packet in packets {
+        let packet = packet.as_ref();
+        if packet.len() > i16::MAX as usize {
+            return Err(SilkError::Invalid);
+        }
+        put_packet(&mut result, packet);
+    }
+    finish_container(&mut result, variant.terminated());
+    Ok(result)
+}
+
+/// [`encode_silk_with_options`] handing every packet to `on_packet` as soon as it is
+/// encoded, with its index in the stream and without the length prefix, instead of
+/// building the container. The index counts the `prime_ms` packets too.
+pub fn encode_silk_with_callback<R, F>(
+    src: R,
+    options: &SilkEncodeOptions,
+    mut on_packet: F,
+) -> Result<(), SilkError>
+where
+    R: AsRef<[u8]>,
+    F: FnMut(usize, &[u8]),
+{
+    let (mono, options) = prepare_pcm(src.as_ref(), options)?;
+    let mut encoder = PacketEncoder::new(&options)?;
+    let mut index = 0;
+    encode_packets(&mut encoder, &mono, &options, |_, packet| {
+        on_packet(index, packet);
+        index += 1;
+        ControlFlow::Continue(())
+    })
+}
+
+/// Where an [`encode_silk_with_progress`] run is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeProgress {
+    pub frames_done: usize,
+    /// Known up front from the input length.
+    pub total_frames: usize,
+    pub bytes_written: usize,
+}
+
+/// [`encode_silk_with_options`] reporting progress every `interval` frames and after
+/// the last one. A panic in `on_progress` unwinds out of the call and drops the
+/// partial output together with the encoder state, nothing is left half-written.
+pub fn encode_silk_with_progress<R, F>(
+    src: R,
+    options: &SilkEncodeOptions,
+    interval: usize,
+    mut on_progress: F,
+) -> Result<Vec<u8>, SilkError>
+where
+    R: AsRef<[u8]>,
+    F: FnMut(EncodeProgress),
+{
+    let (mono, options) = prepare_pcm(src.as_ref(), options)?;
+    let mut encoder = PacketEncoder::new(&options)?;
+    let interval = interval.max(1);
+    unsafe {
+        _encode_silk_observed(&mut encoder, &mono, &options, |progress| {
+            let Some(progress) = progress else {
+                return ControlFlow::Continue(());
+            };
+            if progress.frames_done.is_multiple_of(interval)
+                || progress.frames_done == progress.total_frames
+            {
+                on_progress(progress);
+            }
+            ControlFlow::Continue(())
+        })
+    }
+}
+
+/// [`encode_silk_with_options`] that stops with [`SilkError::Cancelled`] once `cancel`
+/// is set, it is checked before the first frame and after every packet, the
+/// [`prime_ms`](SilkEncodeOptions::prime_ms) ones included. The packets encoded so
+/// far are dropped with the encoder; to keep them, feed a [`SilkEncoder`] and `finish`
+/// it when cancelled.
+pub fn encode_silk_cancellable<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkEncodeOptions,
+    cancel: &AtomicBool,
 ) -> Result<Vec<u8>, SilkError> {
-    unsafe { _encode_silk(src.as_ref(), sample_rate, bit_rate, tencent) }
+    let (mono, options) = prepare_pcm(src.as_ref(), options)?;
+    if cancel.load(Ordering::Relaxed) {
+        return Err(SilkError::Cancelled);
+    }
+    let mut encoder = PacketEncoder::new(&options)?;
+    unsafe {
+        _encode_silk_observed(&mut encoder, &mono, &options, |_| {
+            if cancel.load(Ordering::Relaxed) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+    }
 }
 
-unsafe fn _encode_silk(
-    src: &[u8],
+/// Encoder for many independent clips. The SDK state is allocated once and
+/// re-initialized before every clip, so nothing carries over from the previous one.
+/// The handle is `Send` and can be kept per worker thread.
+pub struct SilkEncoderHandle {
+    encoder: PacketEncoder,
+    options: SilkEncodeOptions,
+}
+
+impl SilkEncoderHandle {
+    pub fn new(options: &SilkEncodeOptions) -> Result<Self, SilkError> {
+        Ok(Self {
+            encoder: PacketEncoder::new(options)?,
+            options: *options,
+        })
+    }
+
+    /// Same output as [`encode_silk_with_options`] with the options of the handle.
+    pub fn encode<R: AsRef<[u8]>>(&mut self, pcm: R) -> Result<Vec<u8>, SilkError> {
+        let (mono, mut options) = prepare_pcm(pcm.as_ref(), &self.options)?;
+        self.encoder.reset(&options)?;
+        unsafe { _encode_silk_fitted(&mut self.encoder, &mono, &mut options) }
+    }
+
+    /// Status after the last clip.
+    pub fn status(&self) -> Result<EncoderStatus, SilkError> {
+        self.encoder.status()
+    }
+}
+
+/// Encodes `src` once per entry of `bit_rates` in a single pass over the input, with
+/// one encoder per bit rate. A bit rate the encoder rejects only fails its own slot.
+pub fn encode_silk_multi<R: AsRef<[u8]>>(
+    src: R,
     sample_rate: i32,
-    bit_rate: i32,
+    bit_rates: &[i32],
     tencent: bool,
-) -> Result<Vec<u8>, SilkError> {
-    let enc_control = sdk::SKP_SILK_SDK_EncControlStruct {
-        API_sampleRate: sample_rate,
-        maxInternalSampleRate: 24000,
-        packetSize: (20 * sample_rate) / 1000,
-        bitRate: bit_rate,
-        packetLossPercentage: 0,
-        complexity: 2,
-        useInBandFEC: 0,
-        useDTX: 0,
+) -> Vec<Result<Vec<u8>, SilkError>> {
+    let src = src.as_ref();
+    if src.is_empty() || !src.len().is_multiple_of(2) {
+        return bit_rates
+            .iter()
+            .map(|_| Err(SilkError::InvalidPcmLength(src.len())))
+            .collect();
+    }
+
+    let Ok(options) = SilkEncodeOptions::at(sample_rate) else {
+        return bit_rates
+            .iter()
+            .map(|_| Err(SilkError::UnsupportedSampleRate(sample_rate)))
+            .collect();
     };
+    let options = options.tencent(tencent);
+    let mut outputs = bit_rates
+        .iter()
+        .map(|&bit_rate| {
+            let options = options.bit_rate(bit_rate);
+            let capacity = estimated_capacity(src.len(), &options);
+            PacketEncoder::new(&options)
+                .map(|encoder| (encoder, container_start(tencent, capacity)))
+        })
+        .collect::<Vec<_>>();
+
+    let frame_size = packet_frame_size(options.sample_rate, PACKET_DURATION_MS as u16);
+    for frame in pcm_frames(src, frame_size, options.pad_final_frame) {
+        for output in outputs.iter_mut() {
+            let Ok((encoder, result)) = output else {
+                continue;
+            };
+            match encoder.encode(&frame) {
+                Ok(packet) => put_packet(result, packet),
+                Err(err) => *output = Err(err),
+            }
+        }
+    }
+
+    outputs
+        .into_iter()
+        .map(|output| {
+            output.map(|(_, mut result)| {
+                finish_container(&mut result, options.writes_terminator());
+                result
+            })
+        })
+        .collect()
+}
+
+/// Incremental [`encode_silk_with_options`] for PCM that arrives in chunks. Whole
+/// frames are encoded as soon as they are buffered and the header goes out with the
+/// first output, so feeding the same audio in any chunk sizes gives the same bytes.
+/// `max_duration` drops or, with `strict_max_duration`, fails the input past it and
+/// `reject_silence` is checked by [`finish`](Self::finish), after the packets went out.
+/// `fit_to_size` and `normalize` need the whole input up front and are refused with
+/// [`SilkError::UnsupportedOption`].
+pub struct SilkEncoder {
+    encoder: PacketEncoder,
+    options: SilkEncodeOptions,
+    channels: usize,
+    pending: Vec<u8>,
+    input_len: usize,
+    max_input_len: usize,
+    peak: i32,
+    packets: usize,
+    primed: bool,
+    started: bool,
+}
+
+impl SilkEncoder {
+    pub fn new(options: &SilkEncodeOptions) -> Result<Self, SilkError> {
+        let channels = match options.channels {
+            channels @ (1 | 2) => channels as usize,
+            channels => return Err(SilkError::UnsupportedChannels(channels)),
+        };
+        if options.fit_to_size.is_some() {
+            return Err(SilkError::UnsupportedOption("fit_to_size"));
+        }
+        if options.normalize.is_some() {
+            return Err(SilkError::UnsupportedOption("normalize"));
+        }
+        let encoder = PacketEncoder::new(options)?;
+        Ok(Self {
+            pending: Vec::with_capacity(encoder.frame_size * channels),
+            encoder,
+            options: *options,
+            channels,
+            input_len: 0,
+            max_input_len: options.max_duration.map_or(usize::MAX, |limit| {
+                max_input_len(limit, options.sample_rate.hz(), 2 * channels)
+            }),
+            peak: 0,
+            packets: 0,
+            primed: false,
+            started: false,
+        })
+    }
+
+    /// Buffers interleaved s16le `pcm` and returns the packets completed by it.
+    pub fn feed(&mut self, pcm: &[u8]) -> Result<Vec<u8>, SilkError> {
+        let mut output = self.header();
+        self.feed_with(pcm, |_, packet| put_packet(&mut output, packet))?;
+        // only now, a failed feed leaves the header for the next output
+        self.started = true;
+        Ok(output)
+    }
+
+    /// [`feed`](Self::feed) handing each completed packet to `on_packet` with its
+    /// index, without the length prefix and the container header.
+    pub fn feed_with<F>(&mut self, pcm: &[u8], mut on_packet: F) -> Result<(), SilkError>
+    where
+        F: FnMut(usize, &[u8]),
+    {
+        let accepted = pcm.len().min(self.max_input_len - self.input_len);
+        if accepted < pcm.len()
+            && let (Some(limit), true) =
+                (self.options.max_duration, self.options.strict_max_duration)
+        {
+            return Err(SilkError::InputTooLong {
+                limit,
+                actual: pcm_duration(
+                    (self.input_len + pcm.len()) / (2 * self.channels),
+                    self.options.sample_rate.hz(),
+                ),
+            });
+        }
+        let pcm = &pcm[..accepted];
+        self.pending.extend_from_slice(pcm);
+        self.input_len += pcm.len();
+
+        // the primer audio is encoded again afterwards, so it has to be held back
+        if !self.primed {
+            if self.pending.len() < self.prime_len() {
+                return Ok(());
+            }
+            self.prime(&mut on_packet)?;
+        }
+        self.encode_pending(&mut on_packet)
+    }
+
+    /// [`feed`](Self::feed) for native `i16` samples.
+    pub fn feed_samples(&mut self, samples: &[i16]) -> Result<Vec<u8>, SilkError> {
+        let pcm = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.feed(&pcm)
+    }
+
+    /// Encodes the buffered tail, zero-padded with `pad_final_frame` and dropped
+    /// otherwise, and terminates the stream.
+    pub fn finish(self) -> Result<Vec<u8>, SilkError> {
+        let mut output = self.header();
+        let terminator = self.options.writes_terminator();
+        self.finish_with(|_, packet| put_packet(&mut output, packet))?;
+        finish_container(&mut output, terminator);
+        Ok(output)
+    }
+
+    /// [`finish`](Self::finish) handing the remaining packets to `on_packet`.
+    pub fn finish_with<F>(mut self, mut on_packet: F) -> Result<(), SilkError>
+    where
+        F: FnMut(usize, &[u8]),
+    {
+        if self.input_len == 0 || !self.input_len.is_multiple_of(2 * self.channels) {
+            return Err(SilkError::InvalidPcmLength(self.input_len));
+        }
+        if !self.primed {
+            self.prime(&mut on_packet)?;
+        }
+        self.encode_pending(&mut on_packet)?;
+        if self.options.reject_silence {
+            // a dropped tail still counts, as in encode_silk_with_options
+            let tail = pcm_peak(&mono_pcm(&self.pending, self.channels));
+            self.peak = self.peak.max(tail);
+        }
+        if self.options.pad_final_frame && !self.pending.is_empty() {
+            self.pending.resize(self.input_frame_len(), 0);
+            self.encode_pending(&mut on_packet)?;
+        }
+        if self.options.reject_silence && gain_to_db(self.peak as f32 / FULL_SCALE) < SILENCE_DBFS {
+            return Err(SilkError::SilentInput);
+        }
+        Ok(())
+    }
+
+    /// Switches the bit rate for the frames encoded from now on, input still buffered
+    /// included, without resetting the encoder. Rates outside 5000..=100000 are
+    /// rejected with [`SilkError::InvalidBitRate`] as in [`SilkEncodeOptions::bit_rate`],
+    /// not clamped, and the encoder keeps its rate. [`status`](Self::status) reports
+    /// the new rate once a frame has been encoded with it.
+    pub fn set_bit_rate(&mut self, bit_rate: i32) -> Result<(), SilkError> {
+        self.update_options(self.options.bit_rate(bit_rate))
+    }
+
+    /// [`set_bit_rate`](Self::set_bit_rate) for the expected packet loss.
+    pub fn set_packet_loss(&mut self, packet_loss_percentage: u8) -> Result<(), SilkError> {
+        self.update_options(self.options.packet_loss_percentage(packet_loss_percentage))
+    }
+
+    /// [`set_bit_rate`](Self::set_bit_rate) for in-band FEC.
+    pub fn set_fec(&mut self, in_band_fec: bool) -> Result<(), SilkError> {
+        self.update_options(self.options.in_band_fec(in_band_fec))
+    }
+
+    fn update_options(&mut self, options: SilkEncodeOptions) -> Result<(), SilkError> {
+        self.encoder.set_control(&options)?;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Status after the last encoded frame.
+    pub fn status(&self) -> Result<EncoderStatus, SilkError> {
+        self.encoder.status()
+    }
+
+    fn header(&self) -> Vec<u8> {
+        if self.started {
+            vec![]
+        } else {
+            container_start(self.options.tencent, 0)
+        }
+    }
+
+    fn input_frame_len(&self) -> usize {
+        self.encoder.frame_size * self.channels
+    }
+
+    fn prime_len(&self) -> usize {
+        primer_len(&self.options, 2 * self.channels, self.input_frame_len())
+    }
+
+    fn prime<F>(&mut self, on_packet: &mut F) -> Result<(), SilkError>
+    where
+        F: FnMut(usize, &[u8]),
+    {
+        let prime_len = self.prime_len().min(self.pending.len());
+        let primer = primer_pcm(&mono_pcm(&self.pending[..prime_len], self.channels));
+        for chunk in pcm_frames(&primer, self.encoder.frame_size, true) {
+            on_packet(self.packets, self.encoder.encode(&chunk)?);
+            self.packets += 1;
+        }
+        self.primed = true;
+        Ok(())
+    }
+
+    fn encode_pending<F>(&mut self, on_packet: &mut F) -> Result<(), SilkError>
+    where
+        F: FnMut(usize, &[u8]),
+    {
+        let frame_len = self.input_frame_len();
+        let encoded = self.pending.len() / frame_len * frame_len;
+        for frame in self.pending[..encoded].chunks_exact(frame_len) {
+            let frame = mono_pcm(frame, self.channels);
+            if self.options.reject_silence {
+                self.peak = self.peak.max(pcm_peak(&frame));
+            }
+            on_packet(self.packets, self.encoder.encode(&frame)?);
+            self.packets += 1;
+        }
+        self.pending.drain(..encoded);
+        Ok(())
+    }
+}
+
+fn pcm_peak(pcm: &[u8]) -> i32 {
+    u8::samples(pcm)
+        .map(|sample| (sample as i32).abs())
+        .max()
+        .unwrap_or(0)
+}
+
+fn mono_pcm(pcm: &[u8], channels: usize) -> Cow<'_, [u8]> {
+    if channels == 2 {
+        Cow::Owned(downmix_stereo_pcm(pcm))
+    } else {
+        Cow::Borrowed(pcm)
+    }
+}
+
+/// Encodes interleaved `i16` chunks of any size as if they were one buffer, without
+/// concatenating them first. Runs on a [`SilkEncoder`], so the options it refuses fail
+/// here too.
+pub fn encode_silk_from_iter<I>(
+    chunks: I,
+    options: &SilkEncodeOptions,
+) -> Result<Vec<u8>, SilkError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[i16]>,
+{
+    let mut encoder = SilkEncoder::new(options)?;
+    let mut result = vec![];
+    for chunk in chunks {
+        result.extend_from_slice(&encoder.feed_samples(chunk.as_ref())?);
+    }
+    result.extend_from_slice(&encoder.finish()?);
+    Ok(result)
+}
+
+/// s16le bytes of one packet of input.
+fn packet_frame_size(sample_rate: SampleRate, packet_duration_ms: u16) -> usize {
+    sample_rate.samples(packet_duration_ms as usize) * 2
+}
+
+/// Splits `src` into encoder frames, a trailing partial frame is zero-padded with
+/// `pad_final_frame` and dropped otherwise.
+fn pcm_frames<T: Clone + Default>(
+    src: &[T],
+    frame_len: usize,
+    pad_final_frame: bool,
+) -> impl Iterator<Item = Cow<'_, [T]>> {
+    let chunks = src.chunks_exact(frame_len);
+    let remainder = chunks.remainder();
+    let padded = (pad_final_frame && !remainder.is_empty()).then(|| {
+        let mut frame = remainder.to_vec();
+        frame.resize(frame_len, T::default());
+        Cow::Owned(frame)
+    });
+    chunks.map(Cow::Borrowed).chain(padded)
+}
+
+fn container_start(tencent: bool, capacity: usize) -> Vec<u8> {
+    let mut result = Vec::with_capacity(capacity);
+    if tencent {
+        result.put_u8(TENCENT_PREFIX);
+    }
+    result.extend_from_slice(SILK_HEADER);
+    result
+}
+
+fn put_packet(result: &mut Vec<u8>, packet: &[u8]) {
+    result.put_i16_le(packet.len() as i16);
+    result.extend_from_slice(packet);
+}
+
+fn finish_container(result: &mut Vec<u8>, terminator: bool) {
+    if terminator {
+        result.put_i16_le(-1);
+    }
+}
+
+/// One SDK encoder instance fed with whole packets of `packet_duration_ms` (20-100 ms).
+struct PacketEncoder {
+    state: CodecState,
+    control: sdk::SKP_SILK_SDK_EncControlStruct,
+    frame_size: usize,
+    buf: Vec<u8>,
+    samples: Vec<i16>,
+}
+
+impl PacketEncoder {
+    fn new(options: &SilkEncodeOptions) -> Result<Self, SilkError> {
+        if !PACKET_DURATIONS_MS.contains(&options.packet_duration_ms) {
+            return Err(SilkError::EncPacketSizeNotSupported);
+        }
+        let frame_size = packet_frame_size(options.sample_rate, options.packet_duration_ms);
+
+        let mut encoder_size = 0;
+        fast_check!(sdk::SKP_Silk_SDK_Get_Encoder_Size(&mut encoder_size));
+
+        let mut encoder = Self {
+            state: CodecState::new(encoder_size as usize),
+            control: options.enc_control(),
+            frame_size,
+            buf: vec![0u8; MAX_PACKET_BYTES],
+            samples: Vec::with_capacity(frame_size / 2),
+        };
+        encoder.reset(options)?;
+        Ok(encoder)
+    }
+
+    /// Re-initializes the SDK state in place for `options`, which must keep the sample
+    /// rate and packet duration the encoder was created with.
+    fn reset(&mut self, options: &SilkEncodeOptions) -> Result<(), SilkError> {
+        self.set_control(options)?;
+        let mut status = empty_enc_control();
+        fast_check!(sdk::SKP_Silk_SDK_InitEncoder(
+            self.state.as_mut_ptr(),
+            &mut status,
+        ));
+        Ok(())
+    }
+
+    /// Settings for the following frames, the SDK applies them on the next call.
+    fn set_control(&mut self, options: &SilkEncodeOptions) -> Result<(), SilkError> {
+        let control = options.enc_control();
+        validate_enc_control(&control)?;
+        self.control = control;
+        Ok(())
+    }
+
+    /// Encodes one frame of s16le bytes. They are copied out first, the slice is not
+    /// necessarily aligned for i16 and the target not necessarily little-endian.
+    fn encode(&mut self, frame: &[u8]) -> Result<&[u8], SilkError> {
+        self.samples.clear();
+        self.samples.extend(
+            frame
+                .chunks_exact(2)
+                .map(|sample| i16::from_le_bytes([sample[0], sample[1]])),
+        );
+        let Self {
+            state,
+            control,
+            buf,
+            samples,
+            ..
+        } = self;
+        encode_frame(state, control, samples, buf)
+    }
+
+    fn status(&self) -> Result<EncoderStatus, SilkError> {
+        let mut status = empty_enc_control();
+        fast_check!(sdk::SKP_Silk_SDK_QueryEncoder(
+            self.state.as_ptr(),
+            &mut status,
+        ));
+        Ok(status.into())
+    }
+
+    fn encode_samples(&mut self, samples: &[i16]) -> Result<&[u8], SilkError> {
+        let Self {
+            state,
+            control,
+            buf,
+            ..
+        } = self;
+        encode_frame(state, control, samples, buf)
+    }
+}
 
-    let mut enc_status = sdk::SKP_SILK_SDK_EncControlStruct {
+fn empty_enc_control() -> sdk::SKP_SILK_SDK_EncControlStruct {
+    sdk::SKP_SILK_SDK_EncControlStruct {
         API_sampleRate: 0,
         maxInternalSampleRate: 0,
         packetSize: 0,
-        bitRate: bit_rate,
+        bitRate: 0,
         packetLossPercentage: 0,
         complexity: 0,
         useInBandFEC: 0,
         useDTX: 0,
-    };
+    }
+}
 
-    let mut encoder_size = 0;
-    fast_check!(sdk::SKP_Silk_SDK_Get_Encoder_Size(&mut encoder_size));
+fn encode_frame<'a>(
+    state: &mut CodecState,
+    control: &sdk::SKP_SILK_SDK_EncControlStruct,
+    samples: &[i16],
+    buf: &'a mut [u8],
+) -> Result<&'a [u8], SilkError> {
+    let mut output_size = buf.len() as i16;
+    fast_check!(sdk::SKP_Silk_SDK_Encode(
+        state.as_mut_ptr(),
+        control,
+        samples.as_ptr(),
+        samples.len() as i32,
+        buf.as_mut_ptr(),
+        &mut output_size,
+    ));
+    Ok(&buf[..output_size as usize])
+}
 
-    let mut encoder = vec![0u8; encoder_size as usize];
+/// Re-encodes `src` at `target_bit_rate`. Decoding and encoding both run at
+/// `sample_rate`, one frame at a time without materializing the PCM. The header
+/// variant of `src`, terminator included, is kept; `tencent` overrides its prefix.
+pub fn transcode_silk<R: AsRef<[u8]>>(
+    src: R,
+    sample_rate: i32,
+    target_bit_rate: i32,
+    tencent: Option<bool>,
+) -> Result<Vec<u8>, SilkError> {
+    let options = SilkEncodeOptions::at(sample_rate)?.bit_rate(target_bit_rate);
+    reencode_silk(src.as_ref(), sample_rate, options, tencent)
+}
 
-    fast_check!(sdk::SKP_Silk_SDK_InitEncoder(
-        encoder.as_mut_ptr() as *mut c_void,
-        &mut enc_status,
-    ));
+/// Decodes `src` at `sample_rate` and feeds the PCM frame by frame to an encoder
+/// built from `options`, which gets the header variant of `src`.
+fn reencode_silk(
+    src: &[u8],
+    sample_rate: i32,
+    options: SilkEncodeOptions,
+    tencent: Option<bool>,
+) -> Result<Vec<u8>, SilkError> {
+    let variant = SilkHeaderVariant::detect(src)?;
+    let options = options.header(variant);
+    let tencent = tencent.unwrap_or(options.tencent);
+    let options = options.tencent(tencent);
+    let mut encoder = PacketEncoder::new(&options)?;
+    let frame_samples = encoder.frame_size / 2;
 
-    let mut result = vec![];
+    let mut result = container_start(tencent, 0);
+    let mut pending = Vec::with_capacity(frame_samples);
+    let mut encode_error = None;
+    unsafe {
+        _decode_silk(src, sample_rate, |samples| {
+            for &sample in samples {
+                pending.push(sample);
+                if pending.len() < frame_samples {
+                    continue;
+                }
+                match encoder.encode_samples(&pending) {
+                    Ok(packet) => put_packet(&mut result, packet),
+                    Err(err) => {
+                        encode_error = Some(err);
+                        return ControlFlow::Break(());
+                    }
+                }
+                pending.clear();
+            }
+            ControlFlow::Continue(())
+        })?;
+    }
+    if let Some(err) = encode_error {
+        return Err(err);
+    }
+
+    if !pending.is_empty() {
+        pending.resize(frame_samples, 0);
+        put_packet(&mut result, encoder.encode_samples(&pending)?);
+    }
+    finish_container(&mut result, options.writes_terminator());
+    Ok(result)
+}
+
+/// Re-encodes `src`, a `src_rate` stream, as a `dst_rate` one. The decoder resamples
+/// to `dst_rate` and the encoder runs at that rate internally, so no converter is
+/// needed and 8 kHz sources are upsampled the same way. Above 24 kHz the stream
+/// stays at 24 kHz internally, the highest rate SILK codes.
+pub fn resample_silk<R: AsRef<[u8]>>(
+    src: R,
+    src_rate: i32,
+    dst_rate: i32,
+    bit_rate: i32,
+    tencent: Option<bool>,
+) -> Result<Vec<u8>, SilkError> {
+    SampleRate::try_from(src_rate)?;
+    let options = SilkEncodeOptions::at(dst_rate)?
+        .max_internal_sample_rate(dst_rate.min(24000))
+        .bit_rate(bit_rate);
+    reencode_silk(src.as_ref(), dst_rate, options, tencent)
+}
+
+/// Splices `leading` and `trailing` silence around the packets of `src`; only the
+/// silence is encoded (with `sample_rate` and `bit_rate`), the original packets are
+/// copied unchanged. Padding is rounded up to whole 20 ms packets.
+pub fn pad_silk<R: AsRef<[u8]>>(
+    src: R,
+    leading: Duration,
+    trailing: Duration,
+    sample_rate: i32,
+    bit_rate: i32,
+) -> Result<Vec<u8>, SilkError> {
+    let src = src.as_ref();
+    let tencent = src.starts_with(&[TENCENT_PREFIX]);
+    let packets = src
+        .get(tencent as usize..)
+        .and_then(|src| src.strip_prefix(SILK_HEADER))
+        .ok_or(SilkError::Invalid)?;
+    let packets_len = packets_len(packets)?;
+
+    // tencent streams carry no terminator, which leaves just the packets after the header
+    let options = SilkEncodeOptions::at(sample_rate)?
+        .bit_rate(bit_rate)
+        .tencent(true);
+    let leading = encode_silence(leading, &options)?;
+    let trailing = encode_silence(trailing, &options)?;
+
+    let mut result = Vec::with_capacity(src.len() + leading.len() + trailing.len());
     if tencent {
-        result.put_u8(0x02);
+        result.put_u8(TENCENT_PREFIX);
+    }
+    result.extend_from_slice(SILK_HEADER);
+    result.extend_from_slice(&leading);
+    result.extend_from_slice(&packets[..packets_len]);
+    result.extend_from_slice(&trailing);
+    if packets_len < packets.len() {
+        result.put_i16_le(-1);
     }
-    result.extend_from_slice(b"#!SILK_V3");
+    Ok(result)
+}
 
-    let frame_size = sample_rate as usize / 1000 * 40;
-    let mut output_size = 1250i16;
-    let mut buf = vec![0u8; output_size as usize];
-    for chunk in src.chunks(frame_size) {
-        output_size = 1250;
-        if chunk.len() < frame_size {
+/// Length of the packets up to the terminator or the end of the stream.
+fn packets_len(packets: &[u8]) -> Result<usize, SilkError> {
+    let mut src = packets;
+    while src.remaining() >= 2 {
+        let packet_size = i16::from_le_bytes([src[0], src[1]]);
+        if packet_size < 0 {
             break;
         }
-        fast_check!(sdk::SKP_Silk_SDK_Encode(
-            encoder.as_mut_ptr() as *mut c_void,
-            &enc_control,
-            chunk.as_ptr() as *const i16,
-            chunk.len() as i32 / 2,
-            buf.as_mut_ptr(),
-            &mut output_size,
-        ));
-        result.put_i16_le(output_size);
-        result.extend_from_slice(&buf[0..output_size as usize]);
+        if src.remaining() < 2 + packet_size as usize {
+            return Err(SilkError::Invalid);
+        }
+        src.advance(2 + packet_size as usize);
     }
-    if !tencent {
-        result.put_i16_le(-1);
+    Ok(packets.len() - src.len())
+}
+
+fn encode_silence(duration: Duration, options: &SilkEncodeOptions) -> Result<Vec<u8>, SilkError> {
+    let packets = duration
+        .as_micros()
+        .div_ceil(PACKET_DURATION_MS as u128 * 1000) as usize;
+    let packet_samples = options.sample_rate.samples(PACKET_DURATION_MS as usize);
+    let silence = vec![0u8; packets * packet_samples * 2];
+    let mut encoder = PacketEncoder::new(options)?;
+    let encoded = unsafe { _encode_silk(&mut encoder, &silence, options)? };
+    Ok(encoded[1 + SILK_HEADER.len()..].to_vec())
+}
+
+/// Upper estimate of the container size for `duration` of audio. The SDK treats the
+/// bit rate as an average, so a stream can exceed the estimate by up to 10% at a rate
+/// its internal sample rate uses (see [`SilkEncodeOptions::bit_rate`]); below that
+/// range the encoder spends more than asked and the estimate does not hold.
+pub fn estimate_silk_size(duration: Duration, bit_rate: i32, options: &SilkEncodeOptions) -> u64 {
+    let packet_duration_ms = options.packet_duration_ms.max(1) as u64;
+    let packets = duration.as_millis().div_ceil(packet_duration_ms as u128) as u64;
+    let packet_bytes =
+        (bit_rate.clamp(MIN_BIT_RATE, MAX_BIT_RATE) as u64 * packet_duration_ms).div_ceil(8000);
+    container_overhead(options) + packets * (2 + packet_bytes)
+}
+
+/// Highest bit rate whose [`estimate_silk_size`] fits in `max_bytes`, at most 100000.
+/// `None` when not even 5000 bps does.
+pub fn max_bitrate_for_size(
+    duration: Duration,
+    max_bytes: u64,
+    options: &SilkEncodeOptions,
+) -> Option<i32> {
+    let packet_duration_ms = options.packet_duration_ms.max(1) as u64;
+    let packets = duration.as_millis().div_ceil(packet_duration_ms as u128) as u64;
+    let available = max_bytes.checked_sub(container_overhead(options) + packets * 2)?;
+    if packets == 0 {
+        return Some(MAX_BIT_RATE);
     }
-    Ok(result)
+
+    let bit_rate = (available / packets * 8000 / packet_duration_ms).min(MAX_BIT_RATE as u64);
+    (bit_rate >= MIN_BIT_RATE as u64).then_some(bit_rate as i32)
+}
+
+/// Worst-case size of the stream [`encode_silk`] produces for `pcm_len` bytes of mono
+/// s16le input, for encoding into fixed buffers. The SDK overshoots its target rate on
+/// hard input, so every packet is assumed to fill the encoder buffer; the bound holds
+/// at any `bit_rate` and is far above [`estimate_silk_size`].
+pub fn estimate_encoded_size(pcm_len: usize, sample_rate: i32, bit_rate: i32) -> usize {
+    let Ok(options) = SilkEncodeOptions::at(sample_rate) else {
+        // nothing is encoded at an unsupported rate, only the container is left
+        return max_encoded_size(0, &SilkEncodeOptions::new());
+    };
+    estimate_encoded_size_with_options(pcm_len, &options.bit_rate(bit_rate))
+}
+
+/// [`estimate_encoded_size`] for the stream [`encode_silk_with_options`] produces.
+pub fn estimate_encoded_size_with_options(pcm_len: usize, options: &SilkEncodeOptions) -> usize {
+    max_encoded_size(pcm_len / options.channels.max(1) as usize, options)
+}
+
+fn max_encoded_size(mono_len: usize, options: &SilkEncodeOptions) -> usize {
+    let overhead = 1 + SILK_HEADER.len() + 2;
+    let frame_size = packet_frame_size(options.sample_rate, options.packet_duration_ms);
+    if frame_size == 0 {
+        return overhead;
+    }
+    overhead + mono_len.div_ceil(frame_size) * (2 + MAX_PACKET_BYTES)
+}
+
+/// Starting capacity of the stream encoded from `mono_len` bytes, the
+/// [`estimate_silk_size`] plus its 10% overshoot; hard input still grows the buffer.
+fn estimated_capacity(mono_len: usize, options: &SilkEncodeOptions) -> usize {
+    let samples = (mono_len / 2) as u64;
+    let duration =
+        Duration::from_micros(samples.saturating_mul(1_000_000) / options.sample_rate.hz() as u64);
+    let estimate = estimate_silk_size(duration, options.bit_rate, options);
+    (estimate + estimate / 10) as usize
+}
+
+fn container_overhead(options: &SilkEncodeOptions) -> u64 {
+    let mut overhead = SILK_HEADER.len() as u64;
+    if options.tencent {
+        overhead += 1;
+    }
+    if options.writes_terminator() {
+        overhead += 2;
+    }
+    overhead
 }
 
 #[derive(Error, Debug)]
@@ -199,6 +2833,42 @@ pub enum SilkError {
     DecPayloadTooLarge,
     #[error("DecPayloadError")]
     DecPayloadError,
+    #[error("SampleRateMismatch {signal} != {target}")]
+    SampleRateMismatch { signal: i32, target: i32 },
+    #[error("UnsupportedChannels {0}")]
+    UnsupportedChannels(u8),
+    #[error("InvalidBitRate {given}, expected {min}..={max}")]
+    InvalidBitRate { given: i32, min: i32, max: i32 },
+    #[error("InvalidPcmLength {0}")]
+    InvalidPcmLength(usize),
+    #[error("UnsupportedSampleRate {0}")]
+    UnsupportedSampleRate(i32),
+    #[error("SizeBudgetTooSmall {max_bytes}")]
+    SizeBudgetTooSmall { max_bytes: u64 },
+    #[error("InputTooLong {actual:?} > {limit:?}")]
+    InputTooLong { limit: Duration, actual: Duration },
+    #[error("InvalidPacketLength {len} at {offset}")]
+    InvalidPacketLength { offset: usize, len: i16 },
+    /// A packet of a container failed to decode, at `offset` of its length prefix.
+    #[error("Frame {index} at {offset}: {source}")]
+    Frame {
+        index: usize,
+        offset: usize,
+        source: Box<SilkError>,
+    },
+    #[error("OutputLimitExceeded {limit}")]
+    OutputLimitExceeded { limit: u64 },
+    #[error("SilentInput")]
+    SilentInput,
+    /// The option needs the whole input up front, which a streaming encoder never has.
+    #[error("UnsupportedOption {0}")]
+    UnsupportedOption(&'static str),
+    #[error("Cancelled")]
+    Cancelled,
+    /// Writing the output failed.
+    #[cfg(feature = "std")]
+    #[error("Io {0}")]
+    Io(#[from] std::io::Error),
     #[error("OTHER {0}")]
     Other(i32),
 }
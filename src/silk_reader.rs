@@ -0,0 +1,107 @@
+use crate::silk::{SILK_HEADER, SilkDecoder, SilkError, TENCENT_PREFIX};
+use std::io::{self, Read};
+
+/// Decodes a silk container from `inner` lazily, reads yield s16le PCM. One packet is
+/// read and decoded at a time, reads of any size are served from it.
+/// Unlike [`decode_silk`](crate::decode_silk) there is no output cap, memory stays
+/// at one packet however long the stream claims to be.
+pub struct SilkReader<R: Read> {
+    inner: R,
+    decoder: SilkDecoder,
+    packet: Vec<u8>,
+    pcm: Vec<u8>,
+    position: usize,
+    header_read: bool,
+    finished: bool,
+}
+
+impl<R: Read> SilkReader<R> {
+    pub fn new(inner: R, sample_rate: i32) -> Result<Self, SilkError> {
+        Ok(Self {
+            inner,
+            decoder: SilkDecoder::new(sample_rate)?,
+            packet: vec![],
+            pcm: vec![],
+            position: 0,
+            header_read: false,
+            finished: false,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; SILK_HEADER.len()];
+        self.inner.read_exact(&mut header[..1])?;
+        if header[0] == TENCENT_PREFIX {
+            self.inner.read_exact(&mut header)?;
+        } else {
+            self.inner.read_exact(&mut header[1..])?;
+        }
+        if header != SILK_HEADER {
+            return Err(io::Error::other(SilkError::Invalid));
+        }
+        self.header_read = true;
+        Ok(())
+    }
+
+    /// Decodes the next packet into `pcm`, false at the end of the stream.
+    fn next_packet(&mut self) -> io::Result<bool> {
+        if !self.header_read {
+            self.read_header()?;
+        }
+
+        // like decode_silk, a missing or partial length prefix ends the stream
+        let mut len = [0u8; 2];
+        let len = match read_full(&mut self.inner, &mut len)? {
+            2 => i16::from_le_bytes(len),
+            _ => -1,
+        };
+        if len < 0 {
+            self.finished = true;
+            return Ok(false);
+        }
+
+        self.packet.resize(len as usize, 0);
+        self.inner.read_exact(&mut self.packet)?;
+        let samples = self
+            .decoder
+            .decode_packet(&self.packet)
+            .map_err(io::Error::other)?;
+        self.pcm.clear();
+        self.pcm
+            .extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for SilkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pcm.len() {
+            if self.finished || !self.next_packet()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.pcm.len() - self.position);
+        buf[..len].copy_from_slice(&self.pcm[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Fills `buf` unless the reader ends first, returns how much was read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
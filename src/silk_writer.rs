@@ -0,0 +1,94 @@
+use crate::silk::{
+    DEFAULT_MAX_OUTPUT_BYTES, SilkEncodeOptions, SilkEncoder, SilkError, decode_frames,
+};
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+
+/// Encodes s16le PCM from `reader` into `writer` with memory bounded to a read buffer
+/// plus one frame, with the options a [`SilkEncoder`] accepts. Returns the number of
+/// PCM bytes read, input past `max_duration` included.
+pub fn encode_silk_stream<R: Read, W: Write>(
+    mut reader: R,
+    writer: W,
+    options: &SilkEncodeOptions,
+) -> io::Result<u64> {
+    let mut writer = SilkWriter::new(writer, options).map_err(io::Error::other)?;
+    let read = io::copy(&mut reader, &mut writer)?;
+    writer.finish()?;
+    Ok(read)
+}
+
+/// Decodes `src` into `writer` as s16le PCM one frame at a time, the output is the
+/// same as for [`decode_silk`]. Returns the number of bytes written, a failed write
+/// stops the decode with [`SilkError::Io`].
+///
+/// The output is capped like [`decode_silk`]'s: past 115.2 MB, 10 minutes of 48 kHz
+/// stereo, the decode stops with [`SilkError::OutputLimitExceeded`] after writing up
+/// to the limit.
+///
+/// [`decode_silk`]: crate::decode_silk
+pub fn decode_silk_to_writer<R: AsRef<[u8]>, W: Write>(
+    src: R,
+    sample_rate: i32,
+    mut writer: W,
+) -> Result<u64, SilkError> {
+    let mut bytes = vec![];
+    let mut written = 0;
+    let mut result = Ok(());
+    decode_frames(src.as_ref(), sample_rate, |samples| {
+        bytes.clear();
+        bytes.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        if written + bytes.len() as u64 > DEFAULT_MAX_OUTPUT_BYTES {
+            result = Err(SilkError::OutputLimitExceeded {
+                limit: DEFAULT_MAX_OUTPUT_BYTES,
+            });
+            return ControlFlow::Break(());
+        }
+        if let Err(err) = writer.write_all(&bytes) {
+            result = Err(SilkError::Io(err));
+            return ControlFlow::Break(());
+        }
+        written += bytes.len() as u64;
+        ControlFlow::Continue(())
+    })?;
+    result?;
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Encodes the s16le PCM written to it into a silk stream on `inner`, writes of any
+/// size are accepted, partial samples included. The tail frame and the terminator
+/// are only written by [`finish`](Self::finish); dropping the writer loses them. Options
+/// are handled as by [`SilkEncoder`].
+pub struct SilkWriter<W: Write> {
+    inner: W,
+    encoder: SilkEncoder,
+}
+
+impl<W: Write> SilkWriter<W> {
+    pub fn new(inner: W, options: &SilkEncodeOptions) -> Result<Self, SilkError> {
+        Ok(Self {
+            inner,
+            encoder: SilkEncoder::new(options)?,
+        })
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let tail = self.encoder.finish().map_err(io::Error::other)?;
+        self.inner.write_all(&tail)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SilkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let packets = self.encoder.feed(buf).map_err(io::Error::other)?;
+        self.inner.write_all(&packets)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
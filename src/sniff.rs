@@ -0,0 +1,343 @@
+use crate::silk::{MAX_PACKET_BYTES, SILK_HEADER, SilkHeaderVariant, TENCENT_PREFIX, probe_silk};
+
+const ID3_HEADER_LEN: usize = 10;
+// tags followed before giving up, crafted input can chain empty tags indefinitely
+const MAX_ID3_TAGS: usize = 8;
+const MP3_AFTER_ID3: DetectedFormat = DetectedFormat::new("mp3", "mp3", 0.7);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectedFormat {
+    pub name: &'static str,
+    pub extension: &'static str,
+    /// 1.0 for unambiguous signatures, lower for frame syncs and containers
+    /// whose payload could not be identified from the prefix.
+    pub confidence: f32,
+}
+
+impl DetectedFormat {
+    const fn new(name: &'static str, extension: &'static str, confidence: f32) -> Self {
+        Self {
+            name,
+            extension,
+            confidence,
+        }
+    }
+}
+
+/// Classifies a blob from its leading bytes only, a few dozen bytes are enough
+/// unless a large ID3 tag has to be skipped. With the `ffmpeg` feature, blobs without
+/// a known signature go through ffmpeg's demuxer probes over their first 4 KB.
+pub fn detect_audio_format(mut bytes: &[u8]) -> Option<DetectedFormat> {
+    let mut tagged = false;
+    for _ in 0..MAX_ID3_TAGS {
+        if !bytes.starts_with(b"ID3") {
+            // a tagged file whose audio is not recognized is most likely mp3
+            return detect_signature(bytes).or(tagged.then_some(MP3_AFTER_ID3));
+        }
+        match skip_id3(bytes) {
+            Some(rest) if !rest.is_empty() => bytes = rest,
+            _ => return Some(MP3_AFTER_ID3),
+        }
+        tagged = true;
+    }
+    Some(MP3_AFTER_ID3)
+}
+
+fn detect_signature(bytes: &[u8]) -> Option<DetectedFormat> {
+    if bytes.starts_with(SILK_HEADER)
+        || (bytes.first() == Some(&TENCENT_PREFIX) && bytes[1..].starts_with(SILK_HEADER))
+    {
+        return Some(DetectedFormat::new("silk", "silk", 1.0));
+    }
+    if bytes.starts_with(b"#!AMR") {
+        return Some(DetectedFormat::new("amr", "amr", 1.0));
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE") {
+        return Some(DetectedFormat::new("wav", "wav", 1.0));
+    }
+    if bytes.starts_with(b"FORM") && matches!(bytes.get(8..12), Some(b"AIFF" | b"AIFC")) {
+        return Some(DetectedFormat::new("aiff", "aiff", 1.0));
+    }
+    if bytes.starts_with(b"fLaC") {
+        return Some(DetectedFormat::new("flac", "flac", 1.0));
+    }
+    if bytes.starts_with(b"caff") {
+        return Some(DetectedFormat::new("caf", "caf", 1.0));
+    }
+    if bytes.starts_with(b"OggS") {
+        return Some(detect_ogg(bytes));
+    }
+    if bytes.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
+        return Some(DetectedFormat::new("matroska", "mka", 0.9));
+    }
+    if bytes.get(4..8) == Some(b"ftyp") {
+        return Some(match bytes.get(8..12) {
+            Some(b"M4A " | b"M4B " | b"M4P ") => DetectedFormat::new("m4a", "m4a", 1.0),
+            _ => DetectedFormat::new("mp4", "mp4", 0.8),
+        });
+    }
+    detect_frame_sync(bytes).or_else(|| detect_container(bytes))
+}
+
+/// Asks the demuxer probes of ffmpeg about the formats without a signature here.
+#[cfg(feature = "ffmpeg")]
+fn detect_container(bytes: &[u8]) -> Option<DetectedFormat> {
+    let (name, extension, score) = crate::ffmpeg_utils::probe_container_format(bytes)?;
+    // even a certain probe ranks below the exact signatures above
+    Some(DetectedFormat::new(
+        name,
+        extension,
+        0.9 * score as f32 / 100.0,
+    ))
+}
+
+/// Without ffmpeg only the signatures above are known.
+#[cfg(not(feature = "ffmpeg"))]
+fn detect_container(_bytes: &[u8]) -> Option<DetectedFormat> {
+    None
+}
+
+/// Whether `data` is a silk container: the optional tencent prefix, the header, and a
+/// first length prefix that is a terminator or a packet of a possible size that fits
+/// in `data`. Any negative prefix terminates, as for [`SilkFrameIter`] and
+/// `SilkReader`. A bare header is an empty stream and passes.
+///
+/// [`SilkFrameIter`]: crate::SilkFrameIter
+pub fn is_silk(data: &[u8]) -> bool {
+    let prefix = usize::from(data.first() == Some(&TENCENT_PREFIX));
+    let Some(packets) = data[prefix..].strip_prefix(SILK_HEADER) else {
+        return false;
+    };
+    match *packets {
+        [] => true,
+        [low, high, ..] => match i16::from_le_bytes([low, high]) {
+            len @ 0.. => len as usize <= MAX_PACKET_BYTES && packets.len() >= 2 + len as usize,
+            _ => true,
+        },
+        _ => false,
+    }
+}
+
+/// Header variant of `data`, `None` unless every length prefix checks out.
+pub fn detect_silk_variant(data: &[u8]) -> Option<SilkHeaderVariant> {
+    probe_silk(data).ok().map(|info| info.variant)
+}
+
+fn detect_ogg(bytes: &[u8]) -> DetectedFormat {
+    // the first page carries one packet starting right after the segment table
+    let payload = bytes
+        .get(26)
+        .map(|&segments| 27 + segments as usize)
+        .and_then(|start| bytes.get(start..));
+    match payload {
+        Some(payload) if payload.starts_with(b"OpusHead") => {
+            DetectedFormat::new("opus", "opus", 1.0)
+        }
+        Some(payload) if payload.starts_with(b"\x01vorbis") => {
+            DetectedFormat::new("vorbis", "ogg", 1.0)
+        }
+        Some(payload) if payload.starts_with(b"\x7fFLAC") => {
+            DetectedFormat::new("flac", "oga", 1.0)
+        }
+        _ => DetectedFormat::new("ogg", "ogg", 0.8),
+    }
+}
+
+/// The bytes after the ID3 tag at the start of `bytes`, `None` if it is cut off.
+fn skip_id3(bytes: &[u8]) -> Option<&[u8]> {
+    // the tag size is a 28-bit syncsafe integer excluding the header
+    let tag_len = bytes
+        .get(6..ID3_HEADER_LEN)?
+        .iter()
+        .fold(0usize, |len, &byte| (len << 7) | (byte & 0x7f) as usize);
+    bytes.get(ID3_HEADER_LEN + tag_len..)
+}
+
+fn detect_frame_sync(bytes: &[u8]) -> Option<DetectedFormat> {
+    let [0xff, second, ..] = *bytes else {
+        return None;
+    };
+    if second & 0xf6 == 0xf0 {
+        return Some(DetectedFormat::new("aac", "aac", 0.6));
+    }
+    // mpeg audio sync with a valid layer
+    if second & 0xe0 == 0xe0 && second & 0x06 != 0 {
+        let layer_iii = second & 0x06 == 0x02;
+        return Some(if layer_iii {
+            DetectedFormat::new("mp3", "mp3", 0.6)
+        } else {
+            DetectedFormat::new("mp2", "mp2", 0.5)
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(bytes: &[u8]) -> Option<&'static str> {
+        detect_audio_format(bytes).map(|format| format.name)
+    }
+
+    /// An empty ID3v2.4 tag of `tag_len` bytes after the header, below 256 MB.
+    fn id3(tag_len: usize) -> Vec<u8> {
+        let mut tag = b"ID3\x04\x00\x00".to_vec();
+        // syncsafe, 7 bits per byte
+        tag.extend(
+            (0..4)
+                .rev()
+                .map(|byte| (tag_len >> (7 * byte)) as u8 & 0x7f),
+        );
+        tag.resize(ID3_HEADER_LEN + tag_len, 0);
+        tag
+    }
+
+    #[test]
+    fn detects_magic_bytes() {
+        assert_eq!(name(b"#!SILK_V3\x0a\x00"), Some("silk"));
+        assert_eq!(name(b"#!AMR\n"), Some("amr"));
+        assert_eq!(name(b"RIFF\x24\x00\x00\x00WAVEfmt "), Some("wav"));
+        assert_eq!(name(b"FORM\x00\x00\x00\x00AIFF"), Some("aiff"));
+        assert_eq!(name(b"FORM\x00\x00\x00\x00AIFC"), Some("aiff"));
+        assert_eq!(name(b"fLaC\x00\x00\x00\x22"), Some("flac"));
+        assert_eq!(name(b"caff\x00\x01"), Some("caf"));
+        assert_eq!(name(b"\x1a\x45\xdf\xa3"), Some("matroska"));
+        assert_eq!(name(b"\x00\x00\x00\x20ftypM4A "), Some("m4a"));
+        assert_eq!(name(b"\x00\x00\x00\x20ftypisom"), Some("mp4"));
+        assert_eq!(name(&[0xff, 0xf1, 0x50]), Some("aac"));
+        assert_eq!(name(&[0xff, 0xfb, 0x90]), Some("mp3"));
+        assert_eq!(name(&[0xff, 0xfd, 0x90]), Some("mp2"));
+    }
+
+    #[test]
+    fn detects_ogg_payloads() {
+        let page = |payload: &[u8]| {
+            let mut page = b"OggS".to_vec();
+            page.resize(26, 0);
+            page.push(1);
+            page.push(payload.len() as u8);
+            page.extend_from_slice(payload);
+            page
+        };
+        assert_eq!(name(&page(b"OpusHead")), Some("opus"));
+        assert_eq!(name(&page(b"\x01vorbis")), Some("vorbis"));
+        assert_eq!(name(&page(b"\x7fFLAC")), Some("flac"));
+        assert_eq!(name(&page(b"Speex   ")), Some("ogg"));
+    }
+
+    #[test]
+    fn detects_tencent_silk() {
+        let format = detect_audio_format(b"\x02#!SILK_V3").unwrap();
+        assert_eq!(format.name, "silk");
+        assert_eq!(format.confidence, 1.0);
+    }
+
+    #[test]
+    fn skips_id3_tags() {
+        let mut tagged = id3(4);
+        tagged.extend_from_slice(b"fLaC");
+        assert_eq!(name(&tagged), Some("flac"));
+
+        let mut twice = id3(0);
+        twice.extend_from_slice(&tagged);
+        assert_eq!(name(&twice), Some("flac"));
+
+        let mut unknown = id3(0);
+        unknown.extend_from_slice(b"????");
+        assert_eq!(name(&unknown), Some("mp3"));
+
+        // cover art makes tags of hundreds of KB
+        let mut large = id3(300_000);
+        large.extend_from_slice(&[0xff, 0xfb, 0x90]);
+        assert_eq!(name(&large), Some("mp3"));
+        let mut large = id3(5000);
+        large.extend_from_slice(b"fLaC");
+        assert_eq!(name(&large), Some("flac"));
+        assert_eq!(name(&large[..4000]), Some("mp3"));
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    #[test]
+    fn probes_other_containers_with_ffmpeg() {
+        // Sun AU header: data offset, unknown size, 16-bit PCM, 24 kHz, mono
+        let au =
+            b".snd\x00\x00\x00\x18\xff\xff\xff\xff\x00\x00\x00\x03\x00\x00\x5d\xc0\x00\x00\x00\x01";
+        let format = detect_audio_format(au).unwrap();
+        assert_eq!((format.name, format.extension), ("au", "au"));
+        assert!(format.confidence > 0.5 && format.confidence < 1.0);
+
+        let voc = b"Creative Voice File\x1a\x1a\x00\x0a\x01\x29\x11";
+        assert_eq!(name(voc), Some("voc"));
+        // the exact signatures still win
+        assert_eq!(
+            detect_audio_format(b"fLaC\x00\x00\x00\x22")
+                .unwrap()
+                .confidence,
+            1.0
+        );
+    }
+
+    #[test]
+    fn bounds_chained_id3_tags() {
+        let chain = id3(0).repeat(1 << 20);
+        assert_eq!(detect_audio_format(&chain), Some(MP3_AFTER_ID3));
+    }
+
+    #[test]
+    fn handles_truncated_prefixes() {
+        assert_eq!(name(b""), None);
+        assert_eq!(name(b"#!SIL"), None);
+        assert_eq!(name(b"\x02"), None);
+        assert_eq!(name(b"RIFF\x00\x00"), None);
+        assert_eq!(name(&[0xff]), None);
+        assert_eq!(name(b"ID3\x04"), Some("mp3"));
+        assert_eq!(name(&id3(20)[..15]), Some("mp3"));
+    }
+
+    #[test]
+    fn is_silk_checks_the_first_packet() {
+        assert!(!is_silk(b""));
+        assert!(!is_silk(b"\x02"));
+        assert!(!is_silk(b"#!SILK_V"));
+        assert!(is_silk(b"#!SILK_V3"));
+        assert!(is_silk(b"\x02#!SILK_V3"));
+        assert!(is_silk(b"#!SILK_V3\xff\xff"));
+        // any negative prefix ends the stream for the decoders as well
+        assert!(is_silk(b"#!SILK_V3\xfe\xff"));
+        assert!(is_silk(b"#!SILK_V3\x00\x80"));
+        assert!(is_silk(b"\x02#!SILK_V3\x03\x00abc"));
+        assert!(is_silk(b"#!SILK_V3\x00\x00"));
+
+        // the magic followed by garbage
+        assert!(!is_silk(b"#!SILK_V3\x03"));
+        assert!(!is_silk(b"#!SILK_V3\x03\x00ab"));
+        assert!(!is_silk(b"#!SILK_V3\x01\x04"));
+        assert!(!is_silk(b"#!SILK_V3\x00\x7fwhatever"));
+        assert!(!is_silk(b"\x02\x02#!SILK_V3"));
+    }
+
+    #[test]
+    fn detects_silk_variants() {
+        let variant = |data: &[u8]| detect_silk_variant(data);
+        assert_eq!(
+            variant(b"#!SILK_V3"),
+            Some(SilkHeaderVariant::StandardUnterminated)
+        );
+        assert_eq!(
+            variant(b"#!SILK_V3\x01\x00a\xff\xff"),
+            Some(SilkHeaderVariant::Standard)
+        );
+        assert_eq!(
+            variant(b"\x02#!SILK_V3\x01\x00a"),
+            Some(SilkHeaderVariant::Tencent)
+        );
+        assert_eq!(
+            variant(b"\x02#!SILK_V3\xff\xff"),
+            Some(SilkHeaderVariant::TencentTerminated)
+        );
+        // unlike is_silk, every packet is checked
+        assert_eq!(variant(b"#!SILK_V3\x01\x00a\x09\x00"), None);
+        assert_eq!(variant(b""), None);
+    }
+}
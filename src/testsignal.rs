@@ -0,0 +1,204 @@
+use crate::wav::WavWriter;
+use std::f32::consts::TAU;
+use std::io::{self, Seek, Write};
+use std::time::Duration;
+
+const DEFAULT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+// syllable rate of the speech-like envelope
+const SPEECH_SYLLABLE_HZ: f32 = 4.0;
+const SPEECH_HARMONICS: usize = 4;
+// Paul Kellet's pink filter peaks around 6x the white input, the rare peaks above
+// that are clipped to keep `amplitude` a bound
+const PINK_NOISE_GAIN: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Silence,
+    Sine {
+        frequency: f32,
+    },
+    /// Linear sweep from `start` to `end` Hz over the signal duration.
+    Sweep {
+        start: f32,
+        end: f32,
+    },
+    WhiteNoise,
+    PinkNoise,
+    /// Harmonic tone at `pitch` Hz with a syllable-rate amplitude envelope.
+    SpeechLike {
+        pitch: f32,
+    },
+}
+
+/// Deterministic synthetic audio for tests and benchmarks, noise is reproducible for
+/// a given `seed`. Samples are interleaved, tones are identical on every channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TestSignal {
+    waveform: Waveform,
+    sample_rate: u32,
+    channels: u16,
+    duration: Duration,
+    amplitude: f32,
+    seed: u64,
+}
+
+impl TestSignal {
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            sample_rate: 24000,
+            channels: 1,
+            duration: Duration::from_secs(1),
+            amplitude: 0.5,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    pub fn silence() -> Self {
+        Self::new(Waveform::Silence)
+    }
+
+    pub fn sine(frequency: f32) -> Self {
+        Self::new(Waveform::Sine { frequency })
+    }
+
+    pub fn sweep(start: f32, end: f32) -> Self {
+        Self::new(Waveform::Sweep { start, end })
+    }
+
+    pub fn white_noise() -> Self {
+        Self::new(Waveform::WhiteNoise)
+    }
+
+    pub fn pink_noise() -> Self {
+        Self::new(Waveform::PinkNoise)
+    }
+
+    pub fn speech_like(pitch: f32) -> Self {
+        Self::new(Waveform::SpeechLike { pitch })
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate.max(1);
+        self
+    }
+
+    pub fn channels(mut self, channels: u16) -> Self {
+        self.channels = channels.max(1);
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Peak level relative to full scale, clamped to 0..=1.
+    pub fn amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn frames(&self) -> usize {
+        (self.duration.as_micros() * self.sample_rate as u128 / 1_000_000) as usize
+    }
+
+    pub fn samples_f32(&self) -> Vec<f32> {
+        let frames = self.frames();
+        let channels = self.channels as usize;
+        let mut rng = XorShift::new(self.seed);
+        let mut pink = vec![PinkFilter::default(); channels];
+        let mut samples = Vec::with_capacity(frames * channels);
+        for frame in 0..frames {
+            let t = frame as f32 / self.sample_rate as f32;
+            for pink in pink.iter_mut() {
+                let sample = match self.waveform {
+                    Waveform::Silence => 0.0,
+                    Waveform::Sine { frequency } => (TAU * frequency * t).sin(),
+                    Waveform::Sweep { start, end } => {
+                        let length = self.duration.as_secs_f32().max(f32::EPSILON);
+                        (TAU * (start * t + (end - start) * t * t / (2.0 * length))).sin()
+                    }
+                    Waveform::WhiteNoise => rng.next_f32(),
+                    Waveform::PinkNoise => {
+                        (pink.next(rng.next_f32()) * PINK_NOISE_GAIN).clamp(-1.0, 1.0)
+                    }
+                    Waveform::SpeechLike { pitch } => speech_like_sample(pitch, t),
+                };
+                samples.push((sample * self.amplitude).clamp(-1.0, 1.0));
+            }
+        }
+        samples
+    }
+
+    pub fn samples_i16(&self) -> Vec<i16> {
+        self.samples_f32()
+            .into_iter()
+            .map(|sample| (sample * i16::MAX as f32).round() as i16)
+            .collect()
+    }
+
+    /// s16le bytes, the input format of [`encode_silk`](crate::encode_silk).
+    pub fn pcm_bytes(&self) -> Vec<u8> {
+        self.samples_i16()
+            .into_iter()
+            .flat_map(i16::to_le_bytes)
+            .collect()
+    }
+
+    pub fn write_wav<W: Write + Seek>(&self, output: W) -> io::Result<W> {
+        let mut writer = WavWriter::new(output, self.sample_rate, self.channels, None)?;
+        writer.write_all(&self.pcm_bytes())?;
+        writer.finish()
+    }
+}
+
+fn speech_like_sample(pitch: f32, t: f32) -> f32 {
+    let envelope = 0.5 - 0.5 * (TAU * SPEECH_SYLLABLE_HZ * t).cos();
+    let tone = (1..=SPEECH_HARMONICS)
+        .map(|harmonic| (TAU * pitch * harmonic as f32 * t).sin() / harmonic as f32)
+        .sum::<f32>();
+    // the harmonic series sums to roughly 2 at its peaks
+    envelope * tone / 2.0
+}
+
+#[derive(Debug, Clone)]
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // an all-zero state never leaves zero
+        Self(if seed == 0 { DEFAULT_SEED } else { seed })
+    }
+
+    /// Uniform in -1..1.
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PinkFilter([f32; 7]);
+
+impl PinkFilter {
+    fn next(&mut self, white: f32) -> f32 {
+        let b = &mut self.0;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.016898;
+        let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+}
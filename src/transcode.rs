@@ -0,0 +1,298 @@
+#[cfg(feature = "ffmpeg")]
+use crate::pcm::{AudioConverter, PcmError};
+use crate::silk::{
+    SampleRate, SilkDecodeOptions, SilkEncodeOptions, SilkError, decode_silk,
+    decode_silk_with_options, encode_silk_with_options,
+};
+use crate::wav::{WavError, wav_bytes, wav_header};
+#[cfg(not(feature = "ffmpeg"))]
+use crate::wav::{encode_wav_pcm, parse_wav};
+use std::fs;
+#[cfg(feature = "ffmpeg")]
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(feature = "ffmpeg")]
+const SUPPORTED_CONVERSIONS: &str = "silk -> wav/pcm/silk, pcm -> silk, audio (wav, mp3, flac, ogg, opus, m4a, aac) -> silk/wav/pcm";
+#[cfg(not(feature = "ffmpeg"))]
+const SUPPORTED_CONVERSIONS: &str =
+    "silk -> wav/pcm/silk, pcm/wav -> silk (other audio inputs need the `ffmpeg` feature)";
+// the ffmpeg converter always produces 24 kHz mono
+#[cfg(feature = "ffmpeg")]
+const CONVERTED_SAMPLE_RATE: SampleRate = SampleRate::Hz24000;
+
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("silk error: {0}")]
+    Silk(#[from] SilkError),
+    #[error("wav error: {0}")]
+    Wav(#[from] WavError),
+    #[cfg(feature = "ffmpeg")]
+    #[error("pcm error: {0}")]
+    Pcm(#[from] PcmError),
+    #[error(
+        "unsupported conversion from `{input}` to `{output}`; supported: {SUPPORTED_CONVERSIONS}"
+    )]
+    UnsupportedConversion { input: String, output: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranscodeOptions {
+    pub(crate) bit_rate: i32,
+    pub(crate) sample_rate: SampleRate,
+    pub(crate) tencent: bool,
+}
+
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        Self {
+            bit_rate: 24000,
+            sample_rate: SampleRate::Hz24000,
+            tencent: false,
+        }
+    }
+}
+
+impl TranscodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bit_rate(mut self, bit_rate: i32) -> Self {
+        self.bit_rate = bit_rate;
+        self
+    }
+
+    /// Rate of raw PCM inputs and of decoded silk, converted audio inputs are always
+    /// encoded from 24 kHz.
+    pub fn sample_rate(mut self, sample_rate: SampleRate) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn tencent(mut self, tencent: bool) -> Self {
+        self.tencent = tencent;
+        self
+    }
+
+    fn encode_options(&self, sample_rate: SampleRate) -> SilkEncodeOptions {
+        SilkEncodeOptions::new()
+            .sample_rate(sample_rate)
+            .bit_rate(self.bit_rate)
+            .tencent(self.tencent)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StereoMergeOptions {
+    pub(crate) left: SilkDecodeOptions,
+    pub(crate) right: SilkDecodeOptions,
+    pub(crate) left_offset: Duration,
+    pub(crate) right_offset: Duration,
+}
+
+impl StereoMergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the decode rate of both streams.
+    pub fn sample_rate(mut self, sample_rate: SampleRate) -> Self {
+        self.left = self.left.sample_rate(sample_rate);
+        self.right = self.right.sample_rate(sample_rate);
+        self
+    }
+
+    /// Decode options of the left stream, it has to decode to mono at the same rate
+    /// as the right one.
+    pub fn left(mut self, left: SilkDecodeOptions) -> Self {
+        self.left = left;
+        self
+    }
+
+    pub fn right(mut self, right: SilkDecodeOptions) -> Self {
+        self.right = right;
+        self
+    }
+
+    /// Silence inserted before the left stream to line it up with the right one. It
+    /// counts towards the `max_output_bytes` of the left decode options.
+    pub fn left_offset(mut self, left_offset: Duration) -> Self {
+        self.left_offset = left_offset;
+        self
+    }
+
+    pub fn right_offset(mut self, right_offset: Duration) -> Self {
+        self.right_offset = right_offset;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Silk,
+    Pcm,
+    Wav,
+    #[cfg(feature = "ffmpeg")]
+    Audio,
+}
+
+fn file_kind(path: &Path) -> Option<FileKind> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "silk" | "slk" | "sil" => Some(FileKind::Silk),
+        "pcm" | "raw" => Some(FileKind::Pcm),
+        "wav" => Some(FileKind::Wav),
+        #[cfg(feature = "ffmpeg")]
+        "mp3" | "flac" | "ogg" | "oga" | "opus" | "m4a" | "aac" | "amr" | "aiff" | "aif"
+        | "webm" | "mka" | "mp4" => Some(FileKind::Audio),
+        _ => None,
+    }
+}
+
+/// Picks the conversion from the file extensions: silk inputs are decoded or
+/// re-encoded, PCM and audio inputs are encoded to silk. Without `ffmpeg` the only
+/// audio input is 16-bit WAV, encoded at its own sample rate.
+pub fn transcode<P, Q>(
+    input_path: P,
+    output_path: Q,
+    options: &TranscodeOptions,
+) -> Result<(), TranscodeError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (input_path, output_path) = (input_path.as_ref(), output_path.as_ref());
+    let unsupported = || TranscodeError::UnsupportedConversion {
+        input: input_path.display().to_string(),
+        output: output_path.display().to_string(),
+    };
+    let input_kind = file_kind(input_path).ok_or_else(unsupported)?;
+    let output_kind = file_kind(output_path).ok_or_else(unsupported)?;
+
+    let output = match (input_kind, output_kind) {
+        (FileKind::Silk, FileKind::Pcm) => {
+            decode_silk(fs::read(input_path)?, options.sample_rate.hz())?
+        }
+        (FileKind::Silk, FileKind::Wav) => {
+            let pcm = decode_silk(fs::read(input_path)?, options.sample_rate.hz())?;
+            wav_bytes(&pcm, options.sample_rate.hz(), 1)
+        }
+        (FileKind::Silk, FileKind::Silk) => {
+            let pcm = decode_silk(fs::read(input_path)?, options.sample_rate.hz())?;
+            encode_silk_with_options(pcm, &options.encode_options(options.sample_rate))?
+        }
+        (FileKind::Pcm, FileKind::Silk) => encode_silk_with_options(
+            fs::read(input_path)?,
+            &options.encode_options(options.sample_rate),
+        )?,
+        #[cfg(not(feature = "ffmpeg"))]
+        (FileKind::Wav, FileKind::Silk) => {
+            let wav = parse_wav(&fs::read(input_path)?)?;
+            encode_wav_pcm(wav, options.encode_options(options.sample_rate))?
+        }
+        #[cfg(feature = "ffmpeg")]
+        (FileKind::Wav | FileKind::Audio, FileKind::Silk) => {
+            let pcm = convert_to_memory(input_path)?;
+            encode_silk_with_options(pcm, &options.encode_options(CONVERTED_SAMPLE_RATE))?
+        }
+        #[cfg(feature = "ffmpeg")]
+        (FileKind::Wav | FileKind::Audio, FileKind::Pcm) => convert_to_memory(input_path)?,
+        #[cfg(feature = "ffmpeg")]
+        (FileKind::Wav | FileKind::Audio, FileKind::Wav) => wav_bytes(
+            &convert_to_memory(input_path)?,
+            CONVERTED_SAMPLE_RATE.hz(),
+            1,
+        ),
+        _ => return Err(unsupported()),
+    };
+    fs::write(output_path, output)?;
+    Ok(())
+}
+
+#[cfg(feature = "ffmpeg")]
+fn convert_to_memory(input_path: &Path) -> Result<Vec<u8>, TranscodeError> {
+    let mut pcm = vec![];
+    AudioConverter::new()?
+        .reader(input_path)?
+        .read_to_end(&mut pcm)?;
+    Ok(pcm)
+}
+
+/// Decodes two mono silk streams (e.g. both sides of a call) into one stereo WAV
+/// with `left` and `right` on their own channel, the shorter one padded with silence.
+pub fn merge_silk_to_stereo_wav<L, R, W>(
+    left: L,
+    right: R,
+    sample_rate: i32,
+    output: W,
+) -> Result<(), TranscodeError>
+where
+    L: AsRef<[u8]>,
+    R: AsRef<[u8]>,
+    W: Write,
+{
+    let options = StereoMergeOptions::new().sample_rate(SampleRate::try_from(sample_rate)?);
+    merge_silk_to_stereo_wav_with_options(left, right, &options, output)
+}
+
+pub fn merge_silk_to_stereo_wav_with_options<L, R, W>(
+    left: L,
+    right: R,
+    options: &StereoMergeOptions,
+    mut output: W,
+) -> Result<(), TranscodeError>
+where
+    L: AsRef<[u8]>,
+    R: AsRef<[u8]>,
+    W: Write,
+{
+    let sample_rate = options.left.sample_rate;
+    if options.right.sample_rate != sample_rate {
+        return Err(SilkError::SampleRateMismatch {
+            signal: options.right.sample_rate.hz(),
+            target: sample_rate.hz(),
+        }
+        .into());
+    }
+    let left = decode_track(left.as_ref(), &options.left, options.left_offset)?;
+    let right = decode_track(right.as_ref(), &options.right, options.right_offset)?;
+
+    let len = left.len().max(right.len());
+    let mut data = Vec::with_capacity(len * 2);
+    for index in (0..len).step_by(2) {
+        data.extend_from_slice(left.get(index..index + 2).unwrap_or(&[0; 2]));
+        data.extend_from_slice(right.get(index..index + 2).unwrap_or(&[0; 2]));
+    }
+
+    let data_len = u32::try_from(data.len()).unwrap_or(u32::MAX);
+    output.write_all(&wav_header(sample_rate.hz() as u32, 2, data_len, None))?;
+    output.write_all(&data)?;
+    Ok(())
+}
+
+fn decode_track(
+    src: &[u8],
+    options: &SilkDecodeOptions,
+    offset: Duration,
+) -> Result<Vec<u8>, SilkError> {
+    if options.output_channels != 1 {
+        return Err(SilkError::UnsupportedChannels(options.output_channels));
+    }
+    // the leading silence counts towards the output limit of the track
+    let limit = options.max_output_bytes;
+    let offset_bytes =
+        usize::try_from(offset.as_micros() * options.sample_rate.hz() as u128 / 1_000_000 * 2)
+            .ok()
+            .filter(|&len| len as u64 <= limit)
+            .ok_or(SilkError::OutputLimitExceeded { limit })?;
+    let mut pcm = vec![0; offset_bytes];
+    let options = options.max_output_bytes(limit - offset_bytes as u64);
+    pcm.extend_from_slice(&decode_silk_with_options(src, &options)?);
+    Ok(pcm)
+}
@@ -0,0 +1,221 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeechDetectionOptions {
+    frame_ms: u32,
+    energy_threshold_dbfs: f32,
+    max_zero_crossing_rate: f32,
+    hangover_ms: u32,
+    min_segment: Duration,
+    min_gap: Duration,
+}
+
+impl Default for SpeechDetectionOptions {
+    fn default() -> Self {
+        Self {
+            frame_ms: 20,
+            energy_threshold_dbfs: -40.0,
+            max_zero_crossing_rate: 0.35,
+            hangover_ms: 200,
+            min_segment: Duration::from_millis(250),
+            min_gap: Duration::from_millis(300),
+        }
+    }
+}
+
+impl SpeechDetectionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn frame_ms(mut self, frame_ms: u32) -> Self {
+        self.frame_ms = frame_ms.max(1);
+        self
+    }
+
+    /// Frames quieter than this RMS level are treated as silence.
+    pub fn energy_threshold_dbfs(mut self, energy_threshold_dbfs: f32) -> Self {
+        self.energy_threshold_dbfs = energy_threshold_dbfs;
+        self
+    }
+
+    /// Frames crossing zero more often than this (per sample) are treated as noise.
+    pub fn max_zero_crossing_rate(mut self, max_zero_crossing_rate: f32) -> Self {
+        self.max_zero_crossing_rate = max_zero_crossing_rate;
+        self
+    }
+
+    /// How long a segment stays open after the last speech frame.
+    pub fn hangover_ms(mut self, hangover_ms: u32) -> Self {
+        self.hangover_ms = hangover_ms;
+        self
+    }
+
+    pub fn min_segment(mut self, min_segment: Duration) -> Self {
+        self.min_segment = min_segment;
+        self
+    }
+
+    /// Segments separated by a shorter gap are merged.
+    pub fn min_gap(mut self, min_gap: Duration) -> Self {
+        self.min_gap = min_gap;
+        self
+    }
+}
+
+/// Streaming energy/zero-crossing speech detector, fed with samples or s16le bytes.
+#[derive(Debug, Clone)]
+pub struct SpeechDetector {
+    options: SpeechDetectionOptions,
+    sample_rate: u32,
+    frame_len: usize,
+    hangover_frames: usize,
+    frame: Vec<i16>,
+    pending_byte: Option<u8>,
+    frame_index: usize,
+    hangover_left: usize,
+    segment_start: Option<usize>,
+    last_speech_frame: usize,
+    segments: Vec<(usize, usize)>,
+}
+
+impl SpeechDetector {
+    pub fn new(sample_rate: u32, options: SpeechDetectionOptions) -> Self {
+        let frame_len = (sample_rate as usize * options.frame_ms as usize / 1000).max(1);
+        Self {
+            options,
+            sample_rate,
+            frame_len,
+            hangover_frames: options.hangover_ms.div_ceil(options.frame_ms) as usize,
+            frame: Vec::with_capacity(frame_len),
+            pending_byte: None,
+            frame_index: 0,
+            hangover_left: 0,
+            segment_start: None,
+            last_speech_frame: 0,
+            segments: vec![],
+        }
+    }
+
+    pub fn push_samples(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            self.frame.push(sample);
+            if self.frame.len() == self.frame_len {
+                self.process_frame();
+                self.frame.clear();
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<(Duration, Duration)> {
+        if !self.frame.is_empty() {
+            self.process_frame();
+        }
+        if let Some(start) = self.segment_start.take() {
+            self.segments.push((start, self.last_speech_frame + 1));
+        }
+
+        let min_gap = self.duration_to_frames(self.options.min_gap);
+        let min_segment = self.duration_to_frames(self.options.min_segment);
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (start, end) in self.segments.iter().copied() {
+            match merged.last_mut() {
+                Some(last) if start - last.1 < min_gap => last.1 = end,
+                _ => merged.push((start, end)),
+            }
+        }
+
+        merged
+            .into_iter()
+            .filter(|(start, end)| end - start >= min_segment)
+            .map(|(start, end)| (self.frames_to_duration(start), self.frames_to_duration(end)))
+            .collect()
+    }
+
+    fn process_frame(&mut self) {
+        let is_speech = self.is_speech_frame();
+        if is_speech {
+            if self.segment_start.is_none() {
+                self.segment_start = Some(self.frame_index);
+            }
+            self.last_speech_frame = self.frame_index;
+            self.hangover_left = self.hangover_frames;
+        } else if self.segment_start.is_some() {
+            if self.hangover_left == 0 {
+                let start = self.segment_start.take().unwrap_or(self.frame_index);
+                self.segments.push((start, self.last_speech_frame + 1));
+            } else {
+                self.hangover_left -= 1;
+            }
+        }
+        self.frame_index += 1;
+    }
+
+    fn is_speech_frame(&self) -> bool {
+        let energy = self
+            .frame
+            .iter()
+            .map(|&sample| (sample as f64 / 32768.0).powi(2))
+            .sum::<f64>();
+        let rms = (energy / self.frame.len() as f64).sqrt();
+        let rms_dbfs = 20.0 * rms.max(1e-10).log10();
+        let crossings = self
+            .frame
+            .windows(2)
+            .filter(|pair| (pair[0] >= 0) != (pair[1] >= 0))
+            .count();
+        let zero_crossing_rate = crossings as f32 / self.frame.len() as f32;
+
+        rms_dbfs as f32 > self.options.energy_threshold_dbfs
+            && zero_crossing_rate <= self.options.max_zero_crossing_rate
+    }
+
+    fn duration_to_frames(&self, duration: Duration) -> usize {
+        (duration.as_millis() as usize).div_ceil(self.options.frame_ms as usize)
+    }
+
+    fn frames_to_duration(&self, frames: usize) -> Duration {
+        let samples = frames as u64 * self.frame_len as u64;
+        Duration::from_micros(samples * 1_000_000 / self.sample_rate.max(1) as u64)
+    }
+}
+
+impl Write for SpeechDetector {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = buf;
+        if let Some(low) = self.pending_byte.take() {
+            match bytes.split_first() {
+                Some((&high, rest)) => {
+                    self.push_samples(&[i16::from_le_bytes([low, high])]);
+                    bytes = rest;
+                }
+                None => self.pending_byte = Some(low),
+            }
+        }
+
+        let samples = bytes
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>();
+        self.push_samples(&samples);
+        if bytes.len() % 2 == 1 {
+            self.pending_byte = bytes.last().copied();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn detect_speech_segments_pcm(
+    pcm: &[i16],
+    sample_rate: u32,
+    options: &SpeechDetectionOptions,
+) -> Vec<(Duration, Duration)> {
+    let mut detector = SpeechDetector::new(sample_rate, *options);
+    detector.push_samples(pcm);
+    detector.finish()
+}
@@ -1,4 +1,6 @@
-use crate::ffmpeg_utils::ensure_ffmpeg_initialized;
+use crate::ffmpeg_utils::{
+    ensure_ffmpeg_initialized, format_duration_to_duration, stream_duration_to_duration,
+};
 use ffmpeg_next as ffmpeg;
 use std::fs::File;
 use std::io::{BufWriter, Write};
@@ -6,8 +8,6 @@ use std::path::Path;
 use std::time::Duration;
 use thiserror::Error;
 
-const FALLBACK_FORMAT_TIME_BASE_MICROS: i64 = 1_000_000;
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VideoMetadata {
     pub width: u32,
@@ -97,42 +97,6 @@ where
     Ok(())
 }
 
-fn stream_duration_to_duration(stream: &ffmpeg::Stream<'_>) -> Option<Duration> {
-    let raw_duration = stream.duration();
-    if raw_duration <= 0 {
-        return None;
-    }
-
-    rational_units_to_duration(raw_duration, stream.time_base())
-}
-
-fn format_duration_to_duration(raw_duration: i64) -> Option<Duration> {
-    if raw_duration <= 0 {
-        return None;
-    }
-
-    let seconds = raw_duration as f64 / FALLBACK_FORMAT_TIME_BASE_MICROS as f64;
-    duration_from_seconds(seconds)
-}
-
-fn rational_units_to_duration(value: i64, time_base: ffmpeg::Rational) -> Option<Duration> {
-    let denominator = time_base.denominator();
-    if denominator <= 0 {
-        return None;
-    }
-
-    let seconds = value as f64 * f64::from(time_base);
-    duration_from_seconds(seconds)
-}
-
-fn duration_from_seconds(seconds: f64) -> Option<Duration> {
-    if !seconds.is_finite() || seconds <= 0.0 {
-        return None;
-    }
-
-    Some(Duration::from_secs_f64(seconds))
-}
-
 fn decode_first_video_frame(
     format_context: &mut ffmpeg::format::context::Input,
     decoder: &mut ffmpeg::codec::decoder::Video,
@@ -0,0 +1,286 @@
+use crate::silk::{
+    SampleRate, SilkDecodeOptions, SilkEncodeOptions, SilkError, decode_silk_with_options,
+    encode_silk_with_options,
+};
+use bytes::{Buf, BufMut};
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use thiserror::Error;
+
+const PCM_FORMAT_TAG: u16 = 1;
+const EXTENSIBLE_FORMAT_TAG: u16 = 0xfffe;
+const BITS_PER_SAMPLE: u16 = 16;
+const FMT_CHUNK_MIN_LEN: usize = 16;
+// cbSize, valid bits and channel mask come before the sub format GUID
+const EXTENSIBLE_SUB_FORMAT_OFFSET: usize = 24;
+
+#[derive(Error, Debug)]
+pub enum WavError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("silk error: {0}")]
+    Silk(#[from] SilkError),
+    #[error("not a RIFF/WAVE file")]
+    NotWav,
+    #[error("missing `{0}` chunk")]
+    MissingChunk(&'static str),
+    #[error("unsupported format tag {0:#06x}, only integer PCM is supported")]
+    UnsupportedFormat(u16),
+    #[error("unsupported {0}-bit samples, only 16-bit is supported")]
+    UnsupportedBitsPerSample(u16),
+    #[error("unsupported {0} channels, only mono and stereo are supported")]
+    UnsupportedChannels(u16),
+}
+
+/// s16le samples of a WAV file as parsed by [`parse_wav`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavPcm {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Interleaved s16le.
+    pub data: Vec<u8>,
+}
+
+/// Parses a 16-bit integer PCM WAV, chunks other than `fmt ` and `data` are skipped.
+/// A `data` chunk longer than the file (as written by some streaming tools) is cut
+/// to what is there.
+pub fn parse_wav(src: &[u8]) -> Result<WavPcm, WavError> {
+    if src.len() < 12 || &src[..4] != b"RIFF" || &src[8..12] != b"WAVE" {
+        return Err(WavError::NotWav);
+    }
+
+    let mut chunks = &src[12..];
+    let mut format = None;
+    while chunks.remaining() >= 8 {
+        let id = [chunks[0], chunks[1], chunks[2], chunks[3]];
+        chunks.advance(4);
+        let len = (chunks.get_u32_le() as usize).min(chunks.len());
+        let body = &chunks[..len];
+        match &id {
+            b"fmt " => format = Some(parse_fmt_chunk(body)?),
+            b"data" => {
+                let (sample_rate, channels) = format.ok_or(WavError::MissingChunk("fmt "))?;
+                let block_align = channels as usize * 2;
+                return Ok(WavPcm {
+                    sample_rate,
+                    channels,
+                    data: body[..len - len % block_align.max(1)].to_vec(),
+                });
+            }
+            _ => {}
+        }
+        chunks.advance((len + len % 2).min(chunks.len()));
+    }
+    Err(WavError::MissingChunk(if format.is_some() {
+        "data"
+    } else {
+        "fmt "
+    }))
+}
+
+fn parse_fmt_chunk(mut body: &[u8]) -> Result<(u32, u16), WavError> {
+    if body.len() < FMT_CHUNK_MIN_LEN {
+        return Err(WavError::MissingChunk("fmt "));
+    }
+    let extensible = body;
+    let mut format_tag = body.get_u16_le();
+    let channels = body.get_u16_le();
+    let sample_rate = body.get_u32_le();
+    body.advance(6);
+    let bits_per_sample = body.get_u16_le();
+
+    if format_tag == EXTENSIBLE_FORMAT_TAG {
+        // the sub format GUID starts with the plain format tag
+        let sub_format = extensible
+            .get(EXTENSIBLE_SUB_FORMAT_OFFSET..EXTENSIBLE_SUB_FORMAT_OFFSET + 2)
+            .ok_or(WavError::UnsupportedFormat(format_tag))?;
+        format_tag = u16::from_le_bytes([sub_format[0], sub_format[1]]);
+    }
+    if format_tag != PCM_FORMAT_TAG {
+        return Err(WavError::UnsupportedFormat(format_tag));
+    }
+    if bits_per_sample != BITS_PER_SAMPLE {
+        return Err(WavError::UnsupportedBitsPerSample(bits_per_sample));
+    }
+    Ok((sample_rate, channels))
+}
+
+/// Encodes a 16-bit PCM WAV at the sample rate it was recorded with, stereo is mixed
+/// down to mono. Rates the encoder does not take are rejected, not resampled.
+pub fn encode_wav_to_silk<P: AsRef<Path>>(
+    path: P,
+    bit_rate: i32,
+    tencent: bool,
+) -> Result<Vec<u8>, WavError> {
+    let options = SilkEncodeOptions::new().bit_rate(bit_rate).tencent(tencent);
+    encode_wav_pcm(parse_wav(&fs::read(path)?)?, options)
+}
+
+/// Encodes `wav` with `options` at the sample rate and channel count of the file.
+pub(crate) fn encode_wav_pcm(wav: WavPcm, options: SilkEncodeOptions) -> Result<Vec<u8>, WavError> {
+    let channels = match wav.channels {
+        channels @ (1 | 2) => channels as u8,
+        channels => return Err(WavError::UnsupportedChannels(channels)),
+    };
+    let options = options
+        .sample_rate(SampleRate::try_from(
+            i32::try_from(wav.sample_rate).unwrap_or(i32::MAX),
+        )?)
+        .channels(channels);
+    Ok(encode_silk_with_options(wav.data, &options)?)
+}
+
+/// Decodes `src` into a mono 16-bit WAV at `sample_rate`, ready to play.
+pub fn decode_silk_to_wav<R: AsRef<[u8]>>(src: R, sample_rate: i32) -> Result<Vec<u8>, SilkError> {
+    decode_silk_to_wav_with_options(src, &SilkDecodeOptions::at(sample_rate)?)
+}
+
+/// [`decode_silk_to_wav`] with `options`, the header carries their rate and channels.
+pub fn decode_silk_to_wav_with_options<R: AsRef<[u8]>>(
+    src: R,
+    options: &SilkDecodeOptions,
+) -> Result<Vec<u8>, SilkError> {
+    let pcm = decode_silk_with_options(src, options)?;
+    Ok(wav_bytes(
+        &pcm,
+        options.sample_rate.hz(),
+        options.output_channels as u16,
+    ))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WavInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl WavInfo {
+    fn entries(&self) -> impl Iterator<Item = (&'static [u8; 4], &str)> {
+        [
+            (b"INAM", &self.title),
+            (b"IART", &self.artist),
+            (b"IPRD", &self.album),
+            (b"ICMT", &self.comment),
+        ]
+        .into_iter()
+        .filter_map(|(id, value)| value.as_deref().map(|value| (id, value)))
+    }
+}
+
+/// Streams s16le PCM into a WAV container, the chunk sizes are patched in `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    header_len: u64,
+    data_len: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(
+        mut inner: W,
+        sample_rate: u32,
+        channels: u16,
+        info: Option<&WavInfo>,
+    ) -> io::Result<Self> {
+        let header = wav_header(sample_rate, channels, 0, info);
+        inner.write_all(&header)?;
+        Ok(Self {
+            inner,
+            header_len: header.len() as u64,
+            data_len: 0,
+        })
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut riff_len = self.header_len - 8 + self.data_len;
+        if self.data_len % 2 == 1 {
+            self.inner.write_all(&[0])?;
+            riff_len += 1;
+        }
+
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&chunk_len(riff_len).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(self.header_len - 4))?;
+        self.inner
+            .write_all(&chunk_len(self.data_len).to_le_bytes())?;
+        self.inner.seek(SeekFrom::End(0))?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Seek> Write for WavWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.data_len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub(crate) fn wav_header(
+    sample_rate: u32,
+    channels: u16,
+    data_len: u32,
+    info: Option<&WavInfo>,
+) -> Vec<u8> {
+    let block_align = channels * BITS_PER_SAMPLE / 8;
+    let list_chunk = info.map(info_list_chunk).unwrap_or_default();
+
+    let mut header = Vec::with_capacity(44 + list_chunk.len());
+    header.put_slice(b"RIFF");
+    header.put_u32_le((36 + list_chunk.len() as u32).saturating_add(data_len + data_len % 2));
+    header.put_slice(b"WAVE");
+    header.put_slice(b"fmt ");
+    header.put_u32_le(16);
+    header.put_u16_le(PCM_FORMAT_TAG);
+    header.put_u16_le(channels);
+    header.put_u32_le(sample_rate);
+    header.put_u32_le(sample_rate * block_align as u32);
+    header.put_u16_le(block_align);
+    header.put_u16_le(BITS_PER_SAMPLE);
+    header.put_slice(&list_chunk);
+    header.put_slice(b"data");
+    header.put_u32_le(data_len);
+    header
+}
+
+/// Interleaved s16le `pcm` with a 44-byte header.
+pub(crate) fn wav_bytes(pcm: &[u8], sample_rate: i32, channels: u16) -> Vec<u8> {
+    let data_len = u32::try_from(pcm.len()).unwrap_or(u32::MAX);
+    let mut wav = wav_header(sample_rate.max(0) as u32, channels, data_len, None);
+    wav.extend_from_slice(pcm);
+    wav
+}
+
+fn info_list_chunk(info: &WavInfo) -> Vec<u8> {
+    let mut entries = vec![];
+    for (id, value) in info.entries() {
+        let len = value.len() + 1;
+        entries.put_slice(id);
+        entries.put_u32_le(len as u32);
+        entries.put_slice(value.as_bytes());
+        entries.put_u8(0);
+        if len % 2 == 1 {
+            entries.put_u8(0);
+        }
+    }
+    if entries.is_empty() {
+        return entries;
+    }
+
+    let mut chunk = Vec::with_capacity(12 + entries.len());
+    chunk.put_slice(b"LIST");
+    chunk.put_u32_le(4 + entries.len() as u32);
+    chunk.put_slice(b"INFO");
+    chunk.put_slice(&entries);
+    chunk
+}
+
+fn chunk_len(len: u64) -> u32 {
+    u32::try_from(len).unwrap_or(u32::MAX)
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "rayon")]
+
+mod common;
+
+use common::speech_pcm;
+use silk_codec::{SilkEncodeOptions, SilkError, encode_silk_batch, encode_silk_with_options};
+
+#[test]
+fn keeps_input_order_and_per_clip_errors() {
+    // clips of different lengths, so the workers finish them out of order
+    let mut inputs = (1..=12)
+        .map(|index| speech_pcm(24000, 100 * index))
+        .collect::<Vec<_>>();
+    inputs[3] = vec![];
+    inputs[7] = vec![0; 481];
+    inputs[11].pop();
+
+    let options = SilkEncodeOptions::new().tencent(true);
+    let results = encode_silk_batch(&inputs, &options);
+    assert_eq!(results.len(), inputs.len());
+    for (index, (input, result)) in inputs.iter().zip(&results).enumerate() {
+        match index {
+            3 | 7 | 11 => assert!(
+                matches!(result, Err(SilkError::InvalidPcmLength(len)) if *len == input.len()),
+                "clip {index}: {result:?}"
+            ),
+            _ => assert_eq!(
+                result.as_ref().unwrap(),
+                &encode_silk_with_options(input, &options).unwrap(),
+                "clip {index}"
+            ),
+        }
+    }
+}
+
+#[test]
+fn invalid_options_fail_every_clip() {
+    let inputs = [speech_pcm(24000, 100), speech_pcm(24000, 200)];
+    let options = SilkEncodeOptions::new().bit_rate(1000);
+    let results = encode_silk_batch(&inputs, &options);
+    assert_eq!(results.len(), 2);
+    assert!(
+        results
+            .iter()
+            .all(|result| matches!(result, Err(SilkError::InvalidBitRate { given: 1000, .. })))
+    );
+}
@@ -0,0 +1,54 @@
+//! Fixtures shared by the integration tests, all generated with [`TestSignal`] so
+//! none of them is a binary blob.
+#![allow(dead_code)]
+
+use silk_codec::{SampleRate, SilkEncodeOptions, TestSignal, encode_silk_with_options};
+use std::time::Duration;
+
+pub const PITCH: f32 = 180.0;
+
+/// Speech-like mono tone, the kind of input the encoder is tuned for.
+pub fn speech(sample_rate: u32, duration_ms: u64) -> TestSignal {
+    TestSignal::speech_like(PITCH)
+        .sample_rate(sample_rate)
+        .duration(Duration::from_millis(duration_ms))
+}
+
+pub fn speech_pcm(sample_rate: u32, duration_ms: u64) -> Vec<u8> {
+    speech(sample_rate, duration_ms).pcm_bytes()
+}
+
+/// [`speech_pcm`] encoded with `options`, which have to be at `sample_rate`.
+pub fn encoded_speech(sample_rate: u32, duration_ms: u64, options: &SilkEncodeOptions) -> Vec<u8> {
+    encode_silk_with_options(speech_pcm(sample_rate, duration_ms), options).unwrap()
+}
+
+/// A standard stream of 20 ms packets at `sample_rate`.
+pub fn silk_stream(sample_rate: u32, duration_ms: u64) -> Vec<u8> {
+    let options =
+        SilkEncodeOptions::new().sample_rate(SampleRate::try_from(sample_rate as i32).unwrap());
+    encoded_speech(sample_rate, duration_ms, &options)
+}
+
+/// The packet payloads of a container, without the length prefixes.
+pub fn packets(src: &[u8]) -> Vec<Vec<u8>> {
+    silk_codec::SilkFrameIter::new(src)
+        .map(|frame| frame.unwrap().payload.to_vec())
+        .collect()
+}
+
+pub fn samples(pcm: &[u8]) -> Vec<i16> {
+    pcm.chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect()
+}
+
+/// Playing time of mono s16le `pcm`.
+pub fn pcm_duration(pcm: &[u8], sample_rate: u32) -> Duration {
+    Duration::from_micros(pcm.len() as u64 / 2 * 1_000_000 / sample_rate as u64)
+}
+
+/// A path in the temp directory unique to this test process.
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("silk-codec-{}-{name}", std::process::id()))
+}
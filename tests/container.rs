@@ -0,0 +1,274 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{packets, pcm_duration, silk_stream, speech_pcm};
+use silk_codec::{
+    SampleRate, SilkEncodeOptions, SilkEncoder, SilkError, SilkFrameIter, SilkHeaderVariant,
+    TestSignal, assemble_silk, decode_silk, encode_silk_packets, encode_silk_with_options,
+    probe_silk, silk_duration, silk_waveform,
+};
+use std::time::Duration;
+
+/// 400 ms of silence followed by 400 ms of a loud tone, 20 packets each.
+fn silence_then_tone() -> Vec<u8> {
+    let mut pcm = TestSignal::silence()
+        .duration(Duration::from_millis(400))
+        .pcm_bytes();
+    pcm.extend(
+        TestSignal::sine(440.0)
+            .amplitude(0.8)
+            .duration(Duration::from_millis(400))
+            .pcm_bytes(),
+    );
+    encode_silk_with_options(pcm, &SilkEncodeOptions::new()).unwrap()
+}
+
+#[test]
+fn waveform_follows_the_envelope() {
+    let waveform = silk_waveform(silence_then_tone(), 24000, 8).unwrap();
+    assert_eq!(waveform.len(), 8);
+    assert!(waveform[..3].iter().all(|&bar| bar < 10), "{waveform:?}");
+    assert!(waveform[5..].iter().all(|&bar| bar > 150), "{waveform:?}");
+}
+
+#[test]
+fn short_messages_leave_the_last_bars_silent() {
+    // 5 packets for 8 bars
+    let waveform = silk_waveform(silk_stream(24000, 100), 24000, 8).unwrap();
+    assert!(waveform[..5].iter().any(|&bar| bar > 0));
+    assert_eq!(waveform[5..], [0, 0, 0]);
+}
+
+#[test]
+fn waveform_rejects_invalid_input() {
+    assert!(silk_waveform(b"not silk", 24000, 8).is_err());
+    assert!(matches!(
+        silk_waveform(silk_stream(24000, 100), 22050, 8),
+        Err(SilkError::UnsupportedSampleRate(22050))
+    ));
+    assert!(
+        silk_waveform(silk_stream(24000, 100), 24000, 0)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn bare_packets_assemble_into_the_encoded_stream() {
+    let pcm = speech_pcm(16000, 470);
+    for variant in [
+        SilkHeaderVariant::Standard,
+        SilkHeaderVariant::StandardUnterminated,
+        SilkHeaderVariant::Tencent,
+        SilkHeaderVariant::TencentTerminated,
+    ] {
+        let options = SilkEncodeOptions::new()
+            .sample_rate(SampleRate::Hz16000)
+            .header(variant);
+        let encoded = encode_silk_with_options(&pcm, &options).unwrap();
+        let bare = encode_silk_packets(&pcm, &options).unwrap();
+        assert_eq!(bare, packets(&encoded), "{variant:?}");
+        assert!(!bare[0].starts_with(b"#!SILK"));
+        assert_eq!(
+            assemble_silk(&bare, variant).unwrap(),
+            encoded,
+            "{variant:?}"
+        );
+    }
+    // a length the i16 prefix cannot hold
+    let oversized = [vec![0; i16::MAX as usize + 1]];
+    assert!(matches!(
+        assemble_silk(oversized, SilkHeaderVariant::Standard),
+        Err(SilkError::Invalid)
+    ));
+}
+
+#[test]
+fn frame_iter_walks_the_packets() {
+    let payloads: [&[u8]; 3] = [b"abc", b"", b"defgh"];
+    let stream = assemble_silk(payloads, SilkHeaderVariant::TencentTerminated).unwrap();
+    let frames = SilkFrameIter::new(&stream)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let layout = frames
+        .iter()
+        .map(|frame| (frame.index, frame.offset, frame.len(), frame.payload))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        layout,
+        [
+            (0, 10, 3, &b"abc"[..]),
+            (1, 15, 0, b""),
+            (2, 17, 5, b"defgh")
+        ]
+    );
+    assert!(frames[1].is_empty());
+
+    // cut inside the last payload
+    let mut frames = SilkFrameIter::new(&stream[..22]);
+    assert_eq!(frames.by_ref().filter(Result::is_ok).count(), 2);
+    let mut frames = SilkFrameIter::new(&stream[..22]).skip(2);
+    assert!(matches!(
+        frames.next(),
+        Some(Err(SilkError::InvalidPacketLength { offset: 17, len: 5 }))
+    ));
+    assert!(frames.next().is_none());
+
+    // a prefix longer than any packet the SDK writes
+    let mut bad = stream.clone();
+    bad[15..17].copy_from_slice(&2000i16.to_le_bytes());
+    let mut frames = SilkFrameIter::new(&bad);
+    assert!(frames.next().unwrap().is_ok());
+    assert!(matches!(
+        frames.next(),
+        Some(Err(SilkError::InvalidPacketLength {
+            offset: 15,
+            len: 2000
+        }))
+    ));
+    assert!(frames.next().is_none());
+
+    let mut frames = SilkFrameIter::new(b"RIFF....WAVE");
+    assert!(matches!(frames.next(), Some(Err(SilkError::Invalid))));
+    assert!(frames.next().is_none());
+}
+
+#[test]
+fn terminator_option_overrides_the_variant_default() {
+    let pcm = speech_pcm(24000, 300);
+    for tencent in [false, true] {
+        let bare = encode_silk_with_options(
+            &pcm,
+            &SilkEncodeOptions::new().tencent(tencent).terminator(false),
+        )
+        .unwrap();
+        assert!(!bare.ends_with(&[0xff, 0xff]));
+        for terminator in [None, Some(false), Some(true)] {
+            let mut options = SilkEncodeOptions::new().tencent(tencent);
+            if let Some(terminator) = terminator {
+                options = options.terminator(terminator);
+            }
+            let encoded = encode_silk_with_options(&pcm, &options).unwrap();
+            let mut encoder = SilkEncoder::new(&options).unwrap();
+            let mut streamed = encoder.feed(&pcm).unwrap();
+            streamed.extend(encoder.finish().unwrap());
+            assert_eq!(streamed, encoded);
+
+            // the marker is the only difference, and the decoder reads past neither
+            let expected = [&bare[..], &[0xff, 0xff]].concat();
+            if terminator.unwrap_or(!tencent) {
+                assert_eq!(encoded, expected, "{tencent} {terminator:?}");
+            } else {
+                assert_eq!(encoded, bare, "{tencent} {terminator:?}");
+            }
+            assert_eq!(
+                decode_silk(&encoded, 24000).unwrap(),
+                decode_silk(&bare, 24000).unwrap()
+            );
+            assert_eq!(SilkFrameIter::new(&encoded).count(), 15);
+        }
+    }
+}
+
+#[test]
+fn silk_duration_agrees_with_the_decoder() {
+    let speech = speech_pcm(16000, 600);
+    let with_silence = [speech.clone(), vec![0; 32000], speech.clone()].concat();
+    let options = SilkEncodeOptions::new().sample_rate(SampleRate::Hz16000);
+    for (pcm, options) in [
+        (&speech, options),
+        (&speech, options.tencent(true)),
+        (&speech, options.packet_duration_ms(60)),
+        (&speech, options.packet_duration_ms(100).terminator(false)),
+        // DTX packets last as long as the packet before them
+        (&with_silence, options.dtx(true)),
+        (&with_silence, options.dtx(true).packet_duration_ms(40)),
+    ] {
+        let encoded = encode_silk_with_options(pcm, &options).unwrap();
+        for rate in [8000, 16000, 44100] {
+            let decoded = decode_silk(&encoded, rate).unwrap();
+            let expected = pcm_duration(&decoded, rate as u32);
+            assert_eq!(
+                silk_duration(&encoded, rate).unwrap(),
+                expected,
+                "{options:?} at {rate}"
+            );
+        }
+    }
+
+    assert_eq!(silk_duration(b"#!SILK_V3", 24000).unwrap(), Duration::ZERO);
+    assert!(matches!(
+        silk_duration(silk_stream(24000, 100), 22050),
+        Err(SilkError::UnsupportedSampleRate(22050))
+    ));
+    assert!(matches!(silk_duration(b"", 24000), Err(SilkError::Invalid)));
+}
+
+#[test]
+fn probe_summarizes_the_container() {
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::Hz16000)
+        .bit_rate(16000);
+    let encoded = encode_silk_with_options(speech_pcm(16000, 1000), &options).unwrap();
+    let info = probe_silk(&encoded).unwrap();
+    assert_eq!(info.variant, SilkHeaderVariant::Standard);
+    assert!(info.has_terminator);
+    assert_eq!(info.packet_count, 50);
+    let payload_bytes = packets(&encoded).iter().map(Vec::len).sum::<usize>();
+    assert_eq!(info.payload_bytes, payload_bytes);
+    assert_eq!(encoded.len(), 9 + 50 * 2 + payload_bytes + 2);
+    assert_eq!(info.estimated_duration(), Duration::from_secs(1));
+    assert_eq!(info.average_bitrate(), payload_bytes as u64 * 8);
+
+    // 60 ms packets count as 20 ms each, silk_duration has the real length
+    let long = encode_silk_with_options(
+        speech_pcm(16000, 1200),
+        &options.packet_duration_ms(60).tencent(true),
+    )
+    .unwrap();
+    let info = probe_silk(&long).unwrap();
+    assert_eq!(
+        (info.variant, info.has_terminator),
+        (SilkHeaderVariant::Tencent, false)
+    );
+    assert_eq!(info.estimated_duration(), Duration::from_millis(400));
+    assert_eq!(
+        silk_duration(&long, 16000).unwrap(),
+        Duration::from_millis(1200)
+    );
+
+    let empty = probe_silk(b"#!SILK_V3\xff\xff").unwrap();
+    assert_eq!((empty.packet_count, empty.payload_bytes), (0, 0));
+    assert_eq!(empty.average_bitrate(), 0);
+}
+
+#[test]
+fn probe_rejects_malformed_containers() {
+    // hostile length prefixes never reach the SDK: past the end, and past the largest
+    // packet it can decode
+    let truncated = b"#!SILK_V3\x03\x00abc\x10\x00short";
+    assert!(matches!(
+        probe_silk(truncated),
+        Err(SilkError::InvalidPacketLength {
+            offset: 14,
+            len: 16
+        })
+    ));
+    let mut oversized = b"\x02#!SILK_V3".to_vec();
+    oversized.extend_from_slice(&2048i16.to_le_bytes());
+    oversized.extend_from_slice(&[0xff; 2048]);
+    assert!(matches!(
+        probe_silk(&oversized),
+        Err(SilkError::InvalidPacketLength {
+            offset: 10,
+            len: 2048
+        })
+    ));
+    for garbage in [&b""[..], b"#!SILK", b"\x02\x02#!SILK_V3", b"#!AMR\n"] {
+        assert!(
+            matches!(probe_silk(garbage), Err(SilkError::Invalid)),
+            "{garbage:?}"
+        );
+    }
+}
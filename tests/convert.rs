@@ -0,0 +1,1030 @@
+#![cfg(feature = "ffmpeg")]
+
+mod common;
+
+use common::{samples, speech, temp_path};
+use ffmpeg_next as ffmpeg;
+use silk_codec::{
+    AudioConverter, ConversionStats, FormatChangePolicy, PcmError, PcmSegment, ReplayGainMode,
+    SilkEncodeOptions, SilkWriter, SpeechDetectionOptions, TestSignal, WavInfo, WavWriter,
+    analyze_pcm, decode_silk, detect_speech_segments, encode_silk_with_options, parse_wav,
+};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::process::Command;
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::thread;
+use std::time::Duration;
+
+fn wav_fixture(name: &str, signal: &TestSignal) -> PathBuf {
+    let path = temp_path(name);
+    signal.write_wav(File::create(&path).unwrap()).unwrap();
+    path
+}
+
+fn convert(converter: &AudioConverter, input: &Path, name: &str) -> (ConversionStats, u64) {
+    let output = temp_path(name);
+    let stats = converter.convert_to_pcm(input, &output).unwrap();
+    let len = fs::metadata(&output).unwrap().len();
+    fs::remove_file(output).unwrap();
+    (stats, len)
+}
+
+/// 24 kHz mono s16le `pcm` muxed into Matroska with container `tags` and `chapters`
+/// given as (start ms, end ms, title), the metadata WAV can't carry.
+fn mka_fixture(
+    name: &str,
+    pcm: &[u8],
+    tags: &[(&str, &str)],
+    chapters: &[(i64, i64, &str)],
+) -> PathBuf {
+    ffmpeg::init().unwrap();
+    let path = temp_path(name);
+    let mut output = ffmpeg::format::output(&path).unwrap();
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::PCM_S16LE).unwrap();
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .audio()
+        .unwrap();
+    encoder.set_rate(24000);
+    encoder.set_channel_layout(ffmpeg::ChannelLayout::MONO);
+    encoder.set_format(ffmpeg::format::Sample::I16(
+        ffmpeg::format::sample::Type::Packed,
+    ));
+    encoder.set_time_base((1, 24000));
+    let encoder = encoder.open_as(codec).unwrap();
+    output.add_stream(codec).unwrap().set_parameters(&encoder);
+
+    let mut metadata = ffmpeg::Dictionary::new();
+    for (key, value) in tags {
+        metadata.set(key, value);
+    }
+    output.set_metadata(metadata);
+    for (id, &(start, end, title)) in chapters.iter().enumerate() {
+        output
+            .add_chapter(id as i64 + 1, (1, 1000), start, end, title)
+            .unwrap();
+    }
+    output.write_header().unwrap();
+
+    // the PCM packets are the raw samples, no encoding needed
+    let time_base = output.stream(0).unwrap().time_base();
+    for (index, chunk) in pcm.chunks(2048).enumerate() {
+        let mut packet = ffmpeg::Packet::copy(chunk);
+        packet.set_stream(0);
+        packet.set_pts(Some(index as i64 * 1024));
+        packet.set_dts(Some(index as i64 * 1024));
+        packet.set_duration(chunk.len() as i64 / 2);
+        packet.rescale_ts((1, 24000), time_base);
+        packet.write_interleaved(&mut output).unwrap();
+    }
+    output.write_trailer().unwrap();
+    path
+}
+
+/// `signal` as raw MP2 frames. Every MPEG audio frame carries its own spec, so two of
+/// these back to back make a source whose spec changes mid-file.
+fn mp2_stream(signal: &TestSignal, sample_rate: u32, channels: u16, bit_rate: usize) -> Vec<u8> {
+    const FRAME_SAMPLES: usize = 1152;
+    ffmpeg::init().unwrap();
+    let layout = ffmpeg::ChannelLayout::default(channels as i32);
+    let format = ffmpeg::format::Sample::I16(ffmpeg::format::sample::Type::Packed);
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::MP2).unwrap();
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .audio()
+        .unwrap();
+    encoder.set_rate(sample_rate as i32);
+    encoder.set_channel_layout(layout);
+    encoder.set_format(format);
+    encoder.set_bit_rate(bit_rate);
+    encoder.set_time_base((1, sample_rate as i32));
+    let mut encoder = encoder.open_as(codec).unwrap();
+
+    let mut stream = vec![];
+    let mut packet = ffmpeg::Packet::empty();
+    let mut drain = |encoder: &mut ffmpeg::encoder::audio::Encoder, stream: &mut Vec<u8>| {
+        while encoder.receive_packet(&mut packet).is_ok() {
+            stream.extend_from_slice(packet.data().unwrap());
+        }
+    };
+    let pcm = signal.pcm_bytes();
+    for (index, chunk) in pcm
+        .chunks(FRAME_SAMPLES * channels as usize * 2)
+        .enumerate()
+    {
+        let mut frame = ffmpeg::util::frame::Audio::new(format, FRAME_SAMPLES, layout);
+        frame.set_rate(sample_rate);
+        frame.set_pts(Some((index * FRAME_SAMPLES) as i64));
+        // the last frame is padded with silence
+        let data = frame.data_mut(0);
+        data.fill(0);
+        data[..chunk.len()].copy_from_slice(chunk);
+        encoder.send_frame(&frame).unwrap();
+        drain(&mut encoder, &mut stream);
+    }
+    encoder.send_eof().unwrap();
+    drain(&mut encoder, &mut stream);
+    stream
+}
+
+/// 24 kHz WAV with one entry of `channels` per channel, interleaved.
+fn multichannel_fixture(name: &str, channels: &[Vec<i16>]) -> PathBuf {
+    let path = temp_path(name);
+    let mut writer = WavWriter::new(
+        File::create(&path).unwrap(),
+        24000,
+        channels.len() as u16,
+        None,
+    )
+    .unwrap();
+    for frame in 0..channels[0].len() {
+        for channel in channels {
+            writer.write_all(&channel[frame].to_le_bytes()).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+    path
+}
+
+/// The `LIST`/`INFO` entries of `wav` as (id, value) without the NUL terminators.
+fn wav_info_entries(wav: &[u8]) -> Vec<(String, String)> {
+    let chunk_len = |at: usize| u32::from_le_bytes(wav[at..at + 4].try_into().unwrap()) as usize;
+    let mut entries = vec![];
+    let mut offset = 12;
+    while offset + 8 <= wav.len() {
+        let len = chunk_len(offset + 4);
+        if &wav[offset..offset + 4] == b"LIST" && &wav[offset + 8..offset + 12] == b"INFO" {
+            let mut entry = offset + 12;
+            while entry < offset + 8 + len {
+                let entry_len = chunk_len(entry + 4);
+                let value = String::from_utf8_lossy(&wav[entry + 8..entry + 8 + entry_len]);
+                entries.push((
+                    String::from_utf8_lossy(&wav[entry..entry + 4]).into_owned(),
+                    value.trim_end_matches('\0').to_owned(),
+                ));
+                entry += 8 + entry_len + entry_len % 2;
+            }
+        }
+        offset += 8 + len + len % 2;
+    }
+    entries
+}
+
+/// Counts the writes and flushes of the converter, discarding the bytes.
+#[derive(Default)]
+struct FlushCounter {
+    writes: usize,
+    flushes: usize,
+    largest_write: usize,
+}
+
+impl Write for FlushCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.largest_write = self.largest_write.max(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes += 1;
+        Ok(())
+    }
+}
+
+/// Fundamental of 24 kHz `samples` in the 100..=400 Hz range, by autocorrelation.
+fn pitch_hz(samples: &[i16]) -> f32 {
+    let lag = (60..=240)
+        .max_by_key(|&lag| {
+            samples
+                .iter()
+                .zip(&samples[lag..])
+                .map(|(&a, &b)| a as i64 * b as i64)
+                .sum::<i64>()
+        })
+        .unwrap();
+    24000.0 / lag as f32
+}
+
+/// The converted s16le output, without the stats.
+fn convert_pcm(converter: &AudioConverter, input: &Path, name: &str) -> Vec<u8> {
+    let output = temp_path(name);
+    converter.convert_to_pcm(input, &output).unwrap();
+    let pcm = fs::read(&output).unwrap();
+    fs::remove_file(output).unwrap();
+    pcm
+}
+
+fn rms_dbfs(pcm: &[u8]) -> f32 {
+    analyze_pcm(pcm, 24000).rms_dbfs
+}
+
+/// `ms` milliseconds of 24 kHz mono s16le `pcm` starting at `start_ms`.
+fn pcm_window(pcm: &[u8], start_ms: usize, ms: usize) -> &[u8] {
+    &pcm[start_ms * 48..(start_ms + ms) * 48]
+}
+
+#[test]
+fn stats_confirm_the_estimate() {
+    let input = wav_fixture("estimate.wav", &speech(44100, 2000).channels(2));
+    // 40 ms of tolerance for the resampler and atempo delays
+    let tolerance = 24000 * 2 / 25;
+    for converter in [
+        AudioConverter::new().unwrap(),
+        AudioConverter::new().unwrap().with_tempo(1.5),
+    ] {
+        let (stats, len) = convert(&converter, &input, "estimate.pcm");
+        assert_eq!((stats.sample_rate, stats.channels), (24000, 1));
+        assert_eq!(stats.output_bytes, len);
+
+        let info = converter.probe(&input).unwrap();
+        let estimate = converter.estimate_output_len(&info).unwrap();
+        assert!(estimate.abs_diff(len) <= tolerance, "{estimate} vs {len}");
+    }
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn native_spec_reports_the_source_spec() {
+    let signal = speech(44100, 500).channels(2);
+    let input = wav_fixture("native.wav", &signal);
+    let converter = AudioConverter::new().unwrap().with_native_spec();
+    let (stats, len) = convert(&converter, &input, "native.pcm");
+    assert_eq!((stats.sample_rate, stats.channels), (44100, 2));
+    assert_eq!(len, signal.pcm_bytes().len() as u64);
+    assert_eq!(stats.output_bytes, len);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn native_spec_follows_format_changes_in_segments() {
+    let mut mp2 = mp2_stream(&speech(24000, 1000), 24000, 1, 64000);
+    mp2.extend(mp2_stream(
+        &speech(48000, 1000).channels(2),
+        48000,
+        2,
+        192000,
+    ));
+    let input = temp_path("spec-change.mp2");
+    fs::write(&input, mp2).unwrap();
+
+    let converter = AudioConverter::new().unwrap().with_native_spec();
+    let err = converter
+        .convert_to_pcm(&input, temp_path("spec-change-error.pcm"))
+        .unwrap_err();
+    assert!(matches!(err, PcmError::FormatChanged), "{err}");
+
+    let converter = converter.with_format_change_policy(FormatChangePolicy::Segment);
+    let (stats, len) = convert(&converter, &input, "spec-change.pcm");
+    assert_eq!(stats.output_bytes, len);
+    let [first, second] = stats.segments[..] else {
+        panic!("{:?}", stats.segments);
+    };
+    assert_eq!(
+        first,
+        PcmSegment {
+            start_byte: 0,
+            sample_rate: 24000,
+            channels: 1
+        }
+    );
+    assert_eq!((second.sample_rate, second.channels), (48000, 2));
+    // one second of 24 kHz mono, give or take the padding of the last MP2 frames
+    assert!(
+        second.start_byte.abs_diff(24000 * 2) <= 4 * 1152 * 2,
+        "{}",
+        second.start_byte
+    );
+    assert!(len - second.start_byte >= 48000 * 2 * 2, "{len}");
+
+    // the reader reports the spec of the bytes it hands out
+    let mut reader = converter.reader(&input).unwrap();
+    assert_eq!((reader.sample_rate(), reader.channels()), (24000, 1));
+    let mut pcm = vec![];
+    reader.read_to_end(&mut pcm).unwrap();
+    assert_eq!((reader.sample_rate(), reader.channels()), (48000, 2));
+    assert_eq!(reader.stats().segments, stats.segments);
+    assert_eq!(pcm.len() as u64, len);
+
+    // a WAV header holds a single spec
+    let err = converter
+        .convert_to_wav(&input, temp_path("spec-change.wav"))
+        .unwrap_err();
+    assert!(matches!(err, PcmError::FormatChanged), "{err}");
+    fs::remove_file(temp_path("spec-change-error.pcm")).ok();
+    fs::remove_file(temp_path("spec-change.wav")).ok();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn limiter_reports_the_gain_reduction() {
+    let signal = TestSignal::sine(440.0)
+        .sample_rate(24000)
+        .amplitude(1.0)
+        .duration(Duration::from_millis(500));
+    let input = wav_fixture("limiter.wav", &signal);
+
+    let (stats, _) = convert(&AudioConverter::new().unwrap(), &input, "plain.pcm");
+    assert_eq!(stats.max_gain_reduction_db, 0.0);
+
+    // a full-scale sine limited to -6 dBFS loses about 6 dB at its peaks
+    let converter = AudioConverter::new().unwrap().with_limiter(-6.0, 50.0);
+    let (stats, _) = convert(&converter, &input, "limited.pcm");
+    assert!(
+        (5.0..=7.0).contains(&stats.max_gain_reduction_db),
+        "{}",
+        stats.max_gain_reduction_db
+    );
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn untagged_sources_report_no_adjustments() {
+    let input = wav_fixture("untagged.wav", &speech(24000, 300));
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_replaygain(ReplayGainMode::Track)
+        .with_preserve_start_offset(true);
+    let (stats, _) = convert(&converter, &input, "untagged.pcm");
+    assert_eq!(stats.replaygain_db, None);
+    assert_eq!(stats.start_offset_secs, 0.0);
+    assert!(stats.warnings.is_empty());
+
+    let mut reader = converter.reader(&input).unwrap();
+    let mut pcm = vec![];
+    std::io::Read::read_to_end(&mut reader, &mut pcm).unwrap();
+    assert_eq!(reader.stats().output_bytes, pcm.len() as u64);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn converter_output_pipes_into_the_silk_writer() {
+    let input = wav_fixture("pipe.wav", &speech(44100, 1500).channels(2));
+    let converter = AudioConverter::new().unwrap();
+    let options = SilkEncodeOptions::new();
+
+    let mut reader = converter.reader(&input).unwrap();
+    let mut writer = SilkWriter::new(vec![], &options).unwrap();
+    std::io::copy(&mut reader, &mut writer).unwrap();
+    let piped = writer.finish().unwrap();
+
+    let output = temp_path("pipe.pcm");
+    converter.convert_to_pcm(&input, &output).unwrap();
+    let pcm = fs::read(&output).unwrap();
+    assert_eq!(piped, encode_silk_with_options(&pcm, &options).unwrap());
+    assert_eq!(
+        decode_silk(&piped, 24000).unwrap().len(),
+        pcm.len().next_multiple_of(960)
+    );
+    fs::remove_file(output).unwrap();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn eq_gains_apply_at_their_bands() {
+    // each tone sits well inside one band, where the shelves reach close to their gain
+    for (frequency, gains, expected_db) in [
+        (50.0, (6.0, 0.0, 0.0), 6.0),
+        (3000.0, (0.0, 6.0, 0.0), 6.0),
+        (3000.0, (0.0, -6.0, 0.0), -6.0),
+        (10000.0, (0.0, 0.0, 6.0), 6.0),
+        // the shelves leave the presence band alone
+        (3000.0, (-6.0, 0.0, -6.0), 0.0),
+    ] {
+        let signal = TestSignal::sine(frequency)
+            .sample_rate(48000)
+            .amplitude(0.25);
+        let input = wav_fixture("eq-tone.wav", &signal);
+        let plain = convert_pcm(&AudioConverter::new().unwrap(), &input, "eq-off.pcm");
+        let (low, mid, high) = gains;
+        let converter = AudioConverter::new().unwrap().with_eq(low, mid, high);
+        let equalized = convert_pcm(&converter, &input, "eq.pcm");
+
+        let gain_db = rms_dbfs(&equalized) - rms_dbfs(&plain);
+        assert!(
+            (gain_db - expected_db).abs() <= 1.0,
+            "{frequency} Hz with {gains:?}: {gain_db} dB"
+        );
+        fs::remove_file(input).unwrap();
+    }
+}
+
+#[test]
+fn flat_eq_is_bit_identical_to_no_eq() {
+    let input = wav_fixture("flat-eq.wav", &speech(44100, 1000).channels(2));
+    let plain = convert_pcm(&AudioConverter::new().unwrap(), &input, "no-eq.pcm");
+    let converter = AudioConverter::new().unwrap().with_eq(0.0, 0.0, 0.0);
+    assert_eq!(convert_pcm(&converter, &input, "zero-eq.pcm"), plain);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn compressor_narrows_the_level_difference() {
+    // one second at -30 dBFS, below the threshold, then one second at -6 dBFS
+    let tone = |amplitude| {
+        TestSignal::sine(440.0)
+            .sample_rate(24000)
+            .amplitude(amplitude)
+            .pcm_bytes()
+    };
+    let input = temp_path("two-level.wav");
+    let mut writer = WavWriter::new(File::create(&input).unwrap(), 24000, 1, None).unwrap();
+    writer.write_all(&tone(0.0316)).unwrap();
+    writer.write_all(&tone(0.5)).unwrap();
+    writer.finish().unwrap();
+
+    let plain = convert_pcm(&AudioConverter::new().unwrap(), &input, "uncompressed.pcm");
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_compressor(-18.0, 3.0, 10.0, 150.0, 0.0);
+    let compressed = convert_pcm(&converter, &input, "compressed.pcm");
+
+    // skips the attack and release settling after each level change
+    let gain_db = |start_ms| {
+        rms_dbfs(pcm_window(&compressed, start_ms, 500))
+            - rms_dbfs(pcm_window(&plain, start_ms, 500))
+    };
+    let (quiet_gain, loud_gain) = (gain_db(300), gain_db(1300));
+    assert!(quiet_gain.abs() <= 1.0, "{quiet_gain}");
+    // the loud section sits about 9 dB over the threshold at 3:1
+    assert!(
+        (4.0..=10.0).contains(&(quiet_gain - loud_gain)),
+        "{quiet_gain} vs {loud_gain}"
+    );
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn chapters_are_converted_to_one_file_each() {
+    let pcm = speech(24000, 3000).pcm_bytes();
+    let input = mka_fixture(
+        "chapters.mka",
+        &pcm,
+        &[],
+        &[(0, 1000, "Opening"), (1000, 3000, "Body")],
+    );
+    let output_dir = temp_path("chapters");
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let converter = AudioConverter::new().unwrap();
+    let conversion = converter.convert_by_chapters(&input, &output_dir).unwrap();
+    assert!(conversion.chapters_found);
+    let titles = conversion
+        .chapters
+        .iter()
+        .map(|chapter| chapter.title.as_deref())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, [Some("Opening"), Some("Body")]);
+
+    // chapters split on decoded frame boundaries, 1024 samples here
+    let mut written = 0;
+    for (chapter, expected) in conversion.chapters.iter().zip([1000, 2000]) {
+        let duration = chapter.duration.as_millis() as i64;
+        assert!((duration - expected).abs() <= 50, "{duration} ms");
+        written += fs::metadata(&chapter.output_path).unwrap().len();
+    }
+    assert_eq!(
+        written,
+        convert_pcm(&converter, &input, "unsplit.pcm").len() as u64
+    );
+    fs::remove_dir_all(output_dir).unwrap();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn inputs_without_chapters_convert_to_a_single_file() {
+    let input = wav_fixture("no-chapters.wav", &speech(44100, 1500));
+    let output_dir = temp_path("no-chapters");
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let converter = AudioConverter::new().unwrap();
+    let conversion = converter.convert_by_chapters(&input, &output_dir).unwrap();
+    assert!(!conversion.chapters_found);
+    assert_eq!(conversion.chapters.len(), 1);
+    let chapter = &conversion.chapters[0];
+    assert_eq!(chapter.title, None);
+    assert_eq!(
+        fs::read(&chapter.output_path).unwrap(),
+        convert_pcm(&converter, &input, "single.pcm")
+    );
+    fs::remove_dir_all(output_dir).unwrap();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn downmix_matrix_swaps_channels() {
+    let tone = TestSignal::sine(440.0).samples_i16();
+    let input = multichannel_fixture("swap.wav", &[tone.clone(), vec![0; tone.len()]]);
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_native_spec()
+        .with_downmix_matrix(vec![vec![0.0, 1.0], vec![1.0, 0.0]]);
+    let output = samples(&convert_pcm(&converter, &input, "swapped.pcm"));
+
+    let (left, right): (Vec<_>, Vec<_>) = output
+        .chunks_exact(2)
+        .map(|frame| (frame[0], frame[1]))
+        .unzip();
+    assert!(left.iter().all(|&sample| sample == 0));
+    assert_eq!(right, tone);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn downmix_matrix_isolates_the_center_channel() {
+    let center = TestSignal::sine(440.0).samples_i16();
+    let noise = TestSignal::white_noise().samples_i16();
+    // 5.1 in its default order: FL FR FC LFE BL BR
+    let mut channels = vec![noise; 6];
+    channels[2] = center.clone();
+    let input = multichannel_fixture("surround.wav", &channels);
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_downmix_matrix(vec![vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0]]);
+    let output = samples(&convert_pcm(&converter, &input, "center.pcm"));
+
+    assert_eq!(output.len(), center.len());
+    let max_error = output
+        .iter()
+        .zip(&center)
+        .map(|(&a, &b)| (a as i32 - b as i32).abs())
+        .max();
+    assert!(max_error <= Some(1), "{max_error:?}");
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn downmix_matrix_must_match_the_source_layout() {
+    let input = wav_fixture("mismatch.wav", &speech(24000, 200).channels(2));
+    for (matrix, message) in [
+        (
+            vec![vec![0.5, 0.5, 0.5]],
+            "row 0 has 3 coefficients but the source has 2 channels",
+        ),
+        (vec![], "at least one output row"),
+        (vec![vec![f32::NAN, 1.0]], "row 0, column 0 is not finite"),
+    ] {
+        let converter = AudioConverter::new().unwrap().with_downmix_matrix(matrix);
+        let output = temp_path("mismatch.pcm");
+        match converter.convert_to_pcm(&input, &output) {
+            Err(PcmError::InvalidDownmixMatrix(error)) => {
+                assert!(error.contains(message), "{error}")
+            }
+            other => panic!("{other:?}"),
+        }
+        let _ = fs::remove_file(output);
+    }
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn speech_segments_match_the_planted_bursts() {
+    let section = |signal: TestSignal, ms| {
+        signal
+            .sample_rate(44100)
+            .channels(2)
+            .duration(Duration::from_millis(ms))
+            .pcm_bytes()
+    };
+    let input = temp_path("bursts.wav");
+    let mut writer = WavWriter::new(File::create(&input).unwrap(), 44100, 2, None).unwrap();
+    for pcm in [
+        section(TestSignal::silence(), 1000),
+        section(TestSignal::sine(300.0), 1000),
+        section(TestSignal::silence(), 1000),
+        section(TestSignal::sine(300.0), 1500),
+        // loud but crossing zero far too often for speech
+        section(TestSignal::white_noise().amplitude(0.3), 500),
+        section(TestSignal::silence(), 500),
+    ] {
+        writer.write_all(&pcm).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let segments = detect_speech_segments(&input, &SpeechDetectionOptions::new()).unwrap();
+    let bounds = segments
+        .iter()
+        .map(|&(start, end)| (start.as_millis() as i64, end.as_millis() as i64))
+        .collect::<Vec<_>>();
+    assert_eq!(bounds.len(), 2, "{bounds:?}");
+    // two 20 ms detector frames of tolerance for the resampler delay
+    for (&(start, end), (expected_start, expected_end)) in
+        bounds.iter().zip([(1000, 2000), (3000, 4500)])
+    {
+        assert!(
+            (start - expected_start).abs() <= 40 && (end - expected_end).abs() <= 40,
+            "{bounds:?}"
+        );
+    }
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn planar_output_keeps_the_channels_aligned() {
+    let input = wav_fixture("planar.wav", &speech(44100, 1000).channels(2));
+    let planes = AudioConverter::new()
+        .unwrap()
+        .convert_to_planar_f32(&input)
+        .unwrap();
+    assert_eq!(planes.len(), 2);
+    // one second at 24 kHz, give or take the resampler rounding
+    assert!(planes[0].len().abs_diff(24000) <= 48, "{}", planes[0].len());
+    // identical content resampled with independent state stays identical
+    assert_eq!(planes[0], planes[1]);
+    fs::remove_file(input).unwrap();
+
+    let input = wav_fixture("planar-mono.wav", &speech(24000, 500));
+    let planes = AudioConverter::new()
+        .unwrap()
+        .convert_to_planar_f32(&input)
+        .unwrap();
+    assert_eq!(planes.len(), 1);
+    assert_eq!(planes[0].len(), 12000);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn wav_metadata_is_copied_into_the_info_chunk() {
+    let info = WavInfo {
+        // odd lengths with the terminator need a pad byte
+        title: Some("Voice memo".to_owned()),
+        artist: Some("Someone".to_owned()),
+        album: Some("Notes".to_owned()),
+        comment: Some("two words".to_owned()),
+    };
+    let input = temp_path("tagged.wav");
+    let mut writer = WavWriter::new(File::create(&input).unwrap(), 24000, 1, Some(&info)).unwrap();
+    writer.write_all(&speech(24000, 500).pcm_bytes()).unwrap();
+    writer.finish().unwrap();
+
+    let output = temp_path("tagged-output.wav");
+    let converter = AudioConverter::new().unwrap().with_wav_metadata(true);
+    converter.convert_to_wav(&input, &output).unwrap();
+    let wav = fs::read(&output).unwrap();
+    let expected = [
+        ("INAM", "Voice memo"),
+        ("IART", "Someone"),
+        ("IPRD", "Notes"),
+        ("ICMT", "two words"),
+    ]
+    .map(|(id, value)| (id.to_owned(), value.to_owned()));
+    assert_eq!(wav_info_entries(&wav), expected);
+    assert_eq!(
+        u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize,
+        wav.len() - 8
+    );
+    assert_eq!(
+        parse_wav(&wav).unwrap().data,
+        convert_pcm(&converter, &input, "tagged.pcm")
+    );
+
+    // off by default, keeping the outputs byte-stable
+    AudioConverter::new()
+        .unwrap()
+        .convert_to_wav(&input, &output)
+        .unwrap();
+    assert!(wav_info_entries(&fs::read(&output).unwrap()).is_empty());
+    fs::remove_file(output).unwrap();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn live_streams_flush_after_every_frame() {
+    let input = wav_fixture("live.wav", &speech(44100, 2000));
+    for live_stream in [true, false] {
+        let converter = AudioConverter::new().unwrap().with_live_stream(live_stream);
+        let mut output = FlushCounter::default();
+        converter
+            .convert_with_raw_access(&input, Some(&mut output), |_| ControlFlow::Continue(()))
+            .unwrap();
+        // every converted frame is a single write
+        assert!(output.writes > 1);
+        let expected_flushes = if live_stream { output.writes } else { 0 };
+        assert_eq!(output.flushes, expected_flushes, "live {live_stream}");
+    }
+    fs::remove_file(input).unwrap();
+}
+
+/// A FIFO fed by a thread with an endless 24 kHz mono WAV (unknown sizes, as
+/// streamed by a radio) in 100 ms chunks, until the converter closes it. The thread
+/// sets `cancel` once `cancel_after` of audio went in and returns the bytes it fed.
+#[cfg(unix)]
+fn live_fifo(
+    name: &str,
+    cancel: Option<(Arc<AtomicBool>, Duration)>,
+) -> (PathBuf, thread::JoinHandle<usize>) {
+    let path = temp_path(name);
+    let status = Command::new("mkfifo").arg(&path).status().unwrap();
+    assert!(status.success());
+
+    let fifo = path.clone();
+    let feeder = thread::spawn(move || {
+        let mut header = vec![];
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&24000u32.to_le_bytes());
+        header.extend_from_slice(&48000u32.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        let chunk = speech(24000, 100).pcm_bytes();
+        let mut fifo = File::create(fifo).unwrap();
+        let mut fed = 0;
+        let mut write = |bytes: &[u8]| fifo.write_all(bytes).map(|()| fed += bytes.len());
+        if write(&header).is_err() {
+            return fed;
+        }
+        for chunks in 1.. {
+            // the reader went away, the stream was cut
+            if write(&chunk).is_err() {
+                break;
+            }
+            if let Some((cancel, after)) = &cancel
+                && Duration::from_millis(chunks * 100) >= *after
+            {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        fed
+    });
+    (path, feeder)
+}
+
+#[cfg(unix)]
+#[test]
+fn live_streams_end_at_the_max_duration() {
+    let (input, feeder) = live_fifo("live-max.wav", None);
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_live_stream(true)
+        .with_max_duration(Duration::from_secs(3));
+    let mut output = FlushCounter::default();
+    let stats = converter
+        .convert_with_raw_access(&input, Some(&mut output), |_| ControlFlow::Continue(()))
+        .unwrap();
+    let fed = feeder.join().unwrap();
+
+    assert_eq!(stats.output_bytes, 3 * 24000 * 2);
+    // every frame is flushed on its own, nothing piles up in the writer
+    assert!(output.writes > 10, "{}", output.writes);
+    assert_eq!(output.flushes, output.writes);
+    assert!(
+        output.largest_write <= 24000 * 2 / 10,
+        "{}",
+        output.largest_write
+    );
+    // the source was only read as far as needed, give or take the pipe and io buffers
+    assert!(fed < 3 * 24000 * 2 + 512 * 1024, "{fed}");
+    fs::remove_file(input).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn live_streams_end_when_cancelled() {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (input, feeder) = live_fifo(
+        "live-cancel.wav",
+        Some((cancel.clone(), Duration::from_secs(2))),
+    );
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_live_stream(true)
+        .with_cancel_flag(cancel);
+    let output = temp_path("live-cancel-out.wav");
+    let stats = converter.convert_to_wav(&input, &output).unwrap();
+    let fed = feeder.join().unwrap();
+
+    // the wav is finished as usual with what came in before the flag
+    let wav = parse_wav(&fs::read(&output).unwrap()).unwrap();
+    assert_eq!(wav.data.len() as u64, stats.output_bytes);
+    assert!(stats.output_bytes > 0);
+    assert!(
+        stats.output_bytes < fed as u64,
+        "{} of {fed}",
+        stats.output_bytes
+    );
+    fs::remove_file(output).unwrap();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn max_duration_cuts_every_output() {
+    let input = wav_fixture("max-duration.wav", &speech(44100, 2000));
+    let converter = AudioConverter::new()
+        .unwrap()
+        .with_max_duration(Duration::from_millis(500));
+    let (stats, len) = convert(&converter, &input, "max-duration.pcm");
+    assert_eq!((stats.output_bytes, len), (24000, 24000));
+
+    let mut reader = converter.reader(&input).unwrap();
+    let mut pcm = vec![];
+    reader.read_to_end(&mut pcm).unwrap();
+    assert_eq!(pcm.len(), 24000);
+    assert_eq!(reader.stats().output_bytes, 24000);
+
+    let planes = converter.convert_to_planar_f32(&input).unwrap();
+    assert_eq!(planes[0].len(), 12000);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn tempo_changes_the_duration_but_not_the_pitch() {
+    // a steady vowel-like tone: a 180 Hz fundamental with two weaker harmonics
+    let partial = |frequency, amplitude| {
+        TestSignal::sine(frequency)
+            .duration(Duration::from_secs(2))
+            .amplitude(amplitude)
+            .samples_i16()
+    };
+    let (first, second, third) = (
+        partial(180.0, 0.3),
+        partial(360.0, 0.15),
+        partial(540.0, 0.1),
+    );
+    let vowel = (0..first.len())
+        .map(|index| first[index] + second[index] + third[index])
+        .collect::<Vec<_>>();
+    let input = multichannel_fixture("vowel.wav", &[vowel]);
+
+    for tempo in [0.5, 1.5, 3.0] {
+        let converter = AudioConverter::new().unwrap().with_tempo(tempo);
+        let output = samples(&convert_pcm(&converter, &input, "tempo.pcm"));
+        // within one 50 ms WSOLA window of the stretched length
+        let expected_len = (48000.0 / tempo) as usize;
+        assert!(
+            output.len().abs_diff(expected_len) <= 1200,
+            "tempo {tempo}: {} samples",
+            output.len()
+        );
+
+        let middle = output.len() / 2;
+        let pitch = pitch_hz(&output[middle - 2400..middle + 2400]);
+        assert!(
+            (pitch - 180.0).abs() <= 180.0 * 0.03,
+            "tempo {tempo}: {pitch} Hz"
+        );
+    }
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn aiff_output_has_the_expected_header_and_samples() {
+    let input = wav_fixture("aiff-source.wav", &speech(44100, 500).channels(2));
+    let output = temp_path("output.aiff");
+    // 80-bit extended sample rates: sign and exponent, then the explicit-one mantissa
+    for (converter, channels, extended_rate) in [
+        (
+            AudioConverter::new().unwrap(),
+            1,
+            [0x40u8, 0x0d, 0xbb, 0x80, 0, 0, 0, 0, 0, 0],
+        ),
+        (
+            AudioConverter::new().unwrap().with_native_spec(),
+            2,
+            [0x40u8, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0],
+        ),
+    ] {
+        converter.convert_to_aiff(&input, &output).unwrap();
+        let aiff = fs::read(&output).unwrap();
+        let be_u32 = |at: usize| u32::from_be_bytes(aiff[at..at + 4].try_into().unwrap());
+        let pcm = convert_pcm(&converter, &input, "aiff-source.pcm");
+
+        assert_eq!(&aiff[..4], b"FORM");
+        assert_eq!(be_u32(4) as usize, aiff.len() - 8);
+        assert_eq!(&aiff[8..12], b"AIFF");
+        assert_eq!(&aiff[12..16], b"COMM");
+        assert_eq!(be_u32(16), 18);
+        assert_eq!(u16::from_be_bytes([aiff[20], aiff[21]]), channels);
+        assert_eq!(be_u32(22) as usize, pcm.len() / (2 * channels as usize));
+        assert_eq!(u16::from_be_bytes([aiff[26], aiff[27]]), 16);
+        assert_eq!(aiff[28..38], extended_rate);
+        assert_eq!(&aiff[38..42], b"SSND");
+        assert_eq!(be_u32(42) as usize, 8 + pcm.len());
+        assert_eq!(aiff[46..54], [0u8; 8]);
+
+        // the same samples as the raw output, big-endian
+        let samples = aiff[54..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_be_bytes([sample[0], sample[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(samples, common::samples(&pcm));
+    }
+    fs::remove_file(output).unwrap();
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn raw_access_sees_every_source_frame() {
+    let signal = speech(44100, 1000).channels(2);
+    let input = wav_fixture("raw.wav", &signal);
+    let converter = AudioConverter::new().unwrap();
+
+    let (mut frames, mut first) = (0, None);
+    converter
+        .convert_with_raw_access(&input, None, |chunk| {
+            assert_eq!((chunk.channels(), chunk.sample_rate), (2, 44100));
+            frames += chunk.frames();
+            first.get_or_insert_with(|| chunk.planes[0].clone());
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    assert_eq!(frames, signal.frames());
+    // untouched samples, only converted to f32
+    let left = signal
+        .samples_i16()
+        .into_iter()
+        .step_by(2)
+        .map(|sample| sample as f32 / 32768.0);
+    assert!(first.unwrap().into_iter().zip(left).all(|(a, b)| a == b));
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn raw_access_break_stops_the_conversion() {
+    let input = wav_fixture("raw-break.wav", &speech(44100, 2000).channels(2));
+    let converter = AudioConverter::new().unwrap();
+    let full = convert_pcm(&converter, &input, "raw-full.pcm");
+
+    let mut calls = 0;
+    let mut output = vec![];
+    let stats = converter
+        .convert_with_raw_access(&input, Some(&mut output), |_| {
+            calls += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+    assert_eq!(calls, 1);
+    assert!(
+        output.len() < full.len(),
+        "{} of {}",
+        output.len(),
+        full.len()
+    );
+    assert_eq!(stats.output_bytes, output.len() as u64);
+
+    // without an output the callback is the only consumer and stops the same way
+    let mut calls = 0;
+    converter
+        .convert_with_raw_access(&input, None, |_| {
+            calls += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+    assert_eq!(calls, 1);
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn replaygain_tags_shift_the_output_level() {
+    let pcm = TestSignal::sine(440.0).pcm_bytes();
+    let input = mka_fixture(
+        "replaygain.mka",
+        &pcm,
+        &[
+            ("REPLAYGAIN_TRACK_GAIN", "-6.00 dB"),
+            ("REPLAYGAIN_TRACK_PEAK", "0.500000"),
+            // more than the 0.5 peak allows, capped at full scale
+            ("REPLAYGAIN_ALBUM_GAIN", "+10.00 dB"),
+            ("REPLAYGAIN_ALBUM_PEAK", "0.500000"),
+        ],
+        &[],
+    );
+    let plain = convert_pcm(&AudioConverter::new().unwrap(), &input, "rg-off.pcm");
+    let peak_cap = -20.0 * 0.5f32.log10();
+    for (mode, expected_db) in [
+        (ReplayGainMode::Off, None),
+        (ReplayGainMode::Track, Some(-6.0)),
+        (ReplayGainMode::Album, Some(peak_cap)),
+    ] {
+        let converter = AudioConverter::new().unwrap().with_replaygain(mode);
+        let output = temp_path("rg.pcm");
+        let stats = converter.convert_to_pcm(&input, &output).unwrap();
+        let shift_db = rms_dbfs(&fs::read(&output).unwrap()) - rms_dbfs(&plain);
+        fs::remove_file(output).unwrap();
+
+        match (stats.replaygain_db, expected_db) {
+            (Some(applied), Some(expected)) => assert!((applied - expected).abs() < 0.01),
+            (applied, expected) => assert_eq!(applied, expected),
+        }
+        let expected_shift = expected_db.unwrap_or(0.0);
+        assert!(
+            (shift_db - expected_shift).abs() <= 0.2,
+            "{mode:?}: {shift_db} dB"
+        );
+    }
+    fs::remove_file(input).unwrap();
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "dasp")]
+
+mod common;
+
+use common::{pcm_duration, samples, speech, speech_pcm};
+use dasp_signal::Signal;
+use silk_codec::{
+    SampleRate, SilkEncodeOptions, SilkError, SilkSignal, decode_silk, encode_signal,
+    encode_silk_auto,
+};
+
+#[test]
+fn resamples_unsupported_rates() {
+    let (encoded, rate) =
+        encode_silk_auto(speech_pcm(22050, 1000), 22050, None, 24000, false).unwrap();
+    assert_eq!(rate, SampleRate::Hz24000);
+    let decoded = decode_silk(&encoded, 24000).unwrap();
+    assert_eq!(pcm_duration(&decoded, 24000).as_millis(), 1000);
+}
+
+#[test]
+fn keeps_supported_rates() {
+    let pcm = speech_pcm(16000, 500);
+    let (encoded, rate) = encode_silk_auto(&pcm, 16000, None, 24000, false).unwrap();
+    assert_eq!(rate, SampleRate::Hz16000);
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::Hz16000)
+        .bit_rate(24000);
+    assert_eq!(
+        encoded,
+        silk_codec::encode_silk_with_options(&pcm, &options).unwrap()
+    );
+}
+
+#[test]
+fn signal_rate_mismatch_needs_resample() {
+    let samples = speech(12000, 400).samples_i16();
+    let signal = || dasp_signal::from_iter(samples.iter().map(|&sample| [sample]));
+    let options = SilkEncodeOptions::new().sample_rate(SampleRate::Hz24000);
+    assert!(matches!(
+        encode_signal(signal(), 12000, &options, false),
+        Err(SilkError::SampleRateMismatch {
+            signal: 12000,
+            target: 24000
+        })
+    ));
+    let encoded = encode_signal(signal(), 12000, &options, true).unwrap();
+    let decoded = decode_silk(&encoded, 24000).unwrap();
+    assert_eq!(pcm_duration(&decoded, 24000).as_millis(), 400);
+}
+
+#[test]
+fn dasp_sine_keeps_its_frequency_and_duration() {
+    let sine = dasp_signal::rate(24000.0)
+        .const_hz(440.0)
+        .sine()
+        .scale_amp(0.5);
+    let options = SilkEncodeOptions::new().sample_rate(SampleRate::Hz24000);
+    let encoded = encode_signal(
+        dasp_signal::from_iter(sine.take(24000)),
+        24000,
+        &options,
+        false,
+    )
+    .unwrap();
+
+    let mut decoded = SilkSignal::<f32>::new(&encoded, 24000).unwrap();
+    let output = decoded
+        .by_ref()
+        .until_exhausted()
+        .map(|[sample]| sample)
+        .collect::<Vec<_>>();
+    assert_eq!(output.len(), 24000);
+    assert!(decoded.is_exhausted());
+    // an exhausted signal keeps yielding silence
+    assert_eq!(decoded.next(), [0.0]);
+
+    // two zero crossings per period, a few lost to the codec delay at the start
+    let crossings = output
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    assert!(crossings.abs_diff(880) <= 20, "{crossings}");
+}
+
+#[test]
+fn silk_signal_yields_the_decoded_samples() {
+    let encoded = silk_codec::encode_silk_with_options(
+        speech_pcm(16000, 300),
+        &SilkEncodeOptions::new().sample_rate(SampleRate::Hz16000),
+    )
+    .unwrap();
+    // decoded at another rate than it was encoded at, as decode_silk allows
+    let pcm = decode_silk(&encoded, 24000).unwrap();
+    let signal = SilkSignal::<i16>::new(&encoded, 24000).unwrap();
+    assert_eq!(
+        signal
+            .until_exhausted()
+            .map(|[sample]| sample)
+            .collect::<Vec<_>>(),
+        samples(&pcm)
+    );
+
+    assert!(SilkSignal::<i16>::new(b"not silk", 24000).is_err());
+}
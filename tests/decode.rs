@@ -0,0 +1,685 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{encoded_speech, packets, samples, silk_stream, speech_pcm};
+use silk_codec::{
+    CorruptPacketFill, SampleRate, SilkDecodeOptions, SilkDecoder, SilkEncodeOptions, SilkError,
+    SilkHeaderVariant, TestSignal, assemble_silk, decode_silk, decode_silk_f32,
+    decode_silk_f32_with_options, decode_silk_i16, decode_silk_i16_with_options, decode_silk_into,
+    decode_silk_packets, decode_silk_to_writer, decode_silk_with_options, decode_silk_with_report,
+    encode_silk_with_options, silk_duration,
+};
+use std::time::Duration;
+
+fn long_packet_stream(sample_rate: u32) -> Vec<u8> {
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::try_from(sample_rate as i32).unwrap())
+        .packet_duration_ms(100);
+    encoded_speech(sample_rate, 1000, &options)
+}
+
+// one packet decodes to five 20 ms SDK frames, each within the decoder buffer (checked
+// by its debug assertion) and together exactly 100 ms of output
+#[test]
+fn decodes_100ms_packets_at_44_1_and_48khz() {
+    for encode_rate in [24000, 44100, 48000] {
+        let encoded = long_packet_stream(encode_rate);
+        for rate in [44100, 48000] {
+            let packet_samples = rate as usize / 10;
+            let decoded = decode_silk(&encoded, rate).unwrap();
+            assert_eq!(
+                decoded.len(),
+                10 * packet_samples * 2,
+                "{encode_rate} -> {rate} Hz"
+            );
+
+            let mut decoder = SilkDecoder::new(rate).unwrap();
+            for packet in packets(&encoded) {
+                assert_eq!(
+                    decoder.decode_packet(&packet).unwrap().len(),
+                    packet_samples
+                );
+            }
+            // concealment stands in for a whole long packet too
+            assert_eq!(decoder.conceal().unwrap().len(), packet_samples);
+
+            let packets = packets(&encoded);
+            let packets = packets
+                .iter()
+                .map(|packet| Some(&packet[..]))
+                .collect::<Vec<_>>();
+            let options = at(rate);
+            assert_eq!(decode_silk_packets(&packets, &options).unwrap(), decoded);
+        }
+    }
+}
+
+fn decode(src: &[u8], options: SilkDecodeOptions) -> Result<Vec<i16>, SilkError> {
+    decode_silk_with_options(src, &options).map(|pcm| samples(&pcm))
+}
+
+fn at(rate: i32) -> SilkDecodeOptions {
+    SilkDecodeOptions::new().sample_rate(SampleRate::try_from(rate).unwrap())
+}
+
+#[test]
+fn sample_rate_sets_the_output_length() {
+    let encoded = silk_stream(24000, 1000);
+    for rate in [8000, 16000, 44100] {
+        let decoded = decode(&encoded, at(rate)).unwrap();
+        assert_eq!(decoded.len(), rate as usize);
+    }
+    assert_eq!(
+        decode(&encoded, SilkDecodeOptions::new()).unwrap().len(),
+        24000
+    );
+    assert!(matches!(
+        decode_silk(&encoded, 22050),
+        Err(SilkError::UnsupportedSampleRate(22050))
+    ));
+}
+
+#[test]
+fn skip_leading_ms_drops_the_start() {
+    let encoded = silk_stream(16000, 1000);
+    let full = decode(
+        &encoded,
+        SilkDecodeOptions::new().sample_rate(SampleRate::Hz16000),
+    )
+    .unwrap();
+    let options = SilkDecodeOptions::new()
+        .sample_rate(SampleRate::Hz16000)
+        .skip_leading_ms(30);
+    assert_eq!(decode(&encoded, options).unwrap(), full[480..]);
+}
+
+#[test]
+fn max_duration_counts_after_the_skip() {
+    let encoded = silk_stream(16000, 1000);
+    let full = decode(
+        &encoded,
+        SilkDecodeOptions::new().sample_rate(SampleRate::Hz16000),
+    )
+    .unwrap();
+    let options = SilkDecodeOptions::new()
+        .sample_rate(SampleRate::Hz16000)
+        .max_duration(Duration::from_millis(105));
+    assert_eq!(decode(&encoded, options).unwrap(), full[..1680]);
+    let options = options.skip_leading_ms(10);
+    assert_eq!(decode(&encoded, options).unwrap(), full[160..1840]);
+}
+
+#[test]
+fn output_channels_duplicates_samples() {
+    let encoded = silk_stream(24000, 500);
+    let mono = decode(&encoded, SilkDecodeOptions::new()).unwrap();
+    let stereo = decode(&encoded, SilkDecodeOptions::new().output_channels(2)).unwrap();
+    assert_eq!(stereo.len(), 2 * mono.len());
+    assert!(
+        stereo
+            .chunks_exact(2)
+            .zip(&mono)
+            .all(|(frame, &sample)| frame == [sample, sample])
+    );
+    for channels in [0, 3] {
+        assert!(matches!(
+            decode(&encoded, SilkDecodeOptions::new().output_channels(channels)),
+            Err(SilkError::UnsupportedChannels(c)) if c == channels
+        ));
+    }
+}
+
+#[test]
+fn typed_decoders_take_the_channel_layout() {
+    let encoded = silk_stream(16000, 500);
+    let options = SilkDecodeOptions::new()
+        .sample_rate(SampleRate::Hz16000)
+        .output_channels(2)
+        .constant_power(true);
+    let stereo = decode(&encoded, options).unwrap();
+    assert_eq!(stereo.len(), 2 * 16000 / 2);
+    assert_eq!(
+        decode_silk_i16_with_options(&encoded, &options).unwrap(),
+        stereo
+    );
+
+    let floats = decode_silk_f32_with_options(&encoded, &options).unwrap();
+    assert_eq!(floats.len(), stereo.len());
+    for (frame, expected) in floats.chunks_exact(2).zip(stereo.chunks_exact(2)) {
+        assert_eq!(frame[0], frame[1]);
+        assert_eq!(frame[0], expected[0] as f32 / 32768.0);
+    }
+}
+
+#[test]
+fn constant_power_only_scales_stereo() {
+    let encoded = silk_stream(24000, 500);
+    let mono = decode(&encoded, SilkDecodeOptions::new()).unwrap();
+    let options = SilkDecodeOptions::new().constant_power(true);
+    assert_eq!(decode(&encoded, options).unwrap(), mono);
+
+    let stereo = decode(&encoded, options.output_channels(2)).unwrap();
+    for (frame, &sample) in stereo.chunks_exact(2).zip(&mono) {
+        let scaled = (sample as f32 * std::f32::consts::FRAC_1_SQRT_2).round() as i16;
+        assert_eq!(frame, [scaled, scaled]);
+    }
+}
+
+#[test]
+fn in_band_fec_rebuilds_lost_packets() {
+    let options = SilkEncodeOptions::new()
+        .in_band_fec(true)
+        .packet_loss_percentage(20);
+    let encoded = encoded_speech(24000, 1000, &options);
+    let full = decode(&encoded, SilkDecodeOptions::new()).unwrap();
+    let packets = packets(&encoded);
+    let mut received = packets
+        .iter()
+        .map(|packet| Some(&packet[..]))
+        .collect::<Vec<_>>();
+    received[20] = None;
+
+    let lost = 20 * 480..21 * 480;
+    let error = |options: SilkDecodeOptions| {
+        let decoded = samples(&decode_silk_packets(&received, &options).unwrap());
+        assert_eq!(decoded.len(), full.len());
+        decoded[lost.clone()]
+            .iter()
+            .zip(&full[lost.clone()])
+            .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+            .sum::<f64>()
+    };
+    let recovered = error(SilkDecodeOptions::new());
+    let concealed = error(SilkDecodeOptions::new().in_band_fec(false));
+    assert!(recovered < concealed / 10.0, "{recovered} vs {concealed}");
+
+    // containers never have lost packets, so the option changes nothing there
+    let options = SilkDecodeOptions::new().in_band_fec(false);
+    assert_eq!(decode(&encoded, options).unwrap(), full);
+}
+
+/// A stream whose packet `index` the decoder rejects, and the offset of its prefix.
+fn corrupt_stream(index: usize) -> (Vec<u8>, usize) {
+    let mut packets = packets(&silk_stream(24000, 1000));
+    packets[index] = vec![0xff; 60];
+    let mut stream = b"#!SILK_V3".to_vec();
+    let mut offset = 0;
+    for (i, packet) in packets.iter().enumerate() {
+        if i == index {
+            offset = stream.len();
+        }
+        stream.extend_from_slice(&(packet.len() as i16).to_le_bytes());
+        stream.extend_from_slice(packet);
+    }
+    (stream, offset)
+}
+
+#[test]
+fn lenient_fills_rejected_packets() {
+    let (stream, offset) = corrupt_stream(10);
+    assert!(matches!(
+        decode(&stream, SilkDecodeOptions::new()),
+        Err(SilkError::Frame { index: 10, .. })
+    ));
+
+    let options = SilkDecodeOptions::new().lenient(CorruptPacketFill::Silence);
+    let (pcm, report) = decode_silk_with_report(&stream, &options).unwrap();
+    let silenced = samples(&pcm);
+    assert_eq!(report.skipped, [offset]);
+    assert_eq!(silenced.len(), 24000);
+    assert!(
+        silenced[10 * 480..11 * 480]
+            .iter()
+            .all(|&sample| sample == 0)
+    );
+
+    let options = SilkDecodeOptions::new().lenient(CorruptPacketFill::Drop);
+    assert_eq!(decode(&stream, options).unwrap().len(), 24000 - 480);
+
+    let options = SilkDecodeOptions::new().lenient(CorruptPacketFill::Conceal);
+    let concealed = decode(&stream, options).unwrap();
+    assert_eq!(concealed.len(), 24000);
+    assert_eq!(concealed[..10 * 480], silenced[..10 * 480]);
+}
+
+// the SDK call that sets up the resampler loses the error of its frame, which made a
+// corrupt first packet decode as audio at every rate but 24 kHz
+#[test]
+fn corrupt_first_packet_is_rejected_at_every_rate() {
+    let (stream, offset) = corrupt_stream(0);
+    for rate in [8000, 16000, 24000, 48000] {
+        let options = at(rate);
+        assert!(matches!(
+            decode(&stream, options),
+            Err(SilkError::Frame { index: 0, .. })
+        ));
+        let options = options.lenient(CorruptPacketFill::Silence);
+        let (_, report) = decode_silk_with_report(&stream, &options).unwrap();
+        assert_eq!(report.skipped, [offset], "{rate} Hz");
+
+        let packets = packets(&stream);
+        let mut decoder = SilkDecoder::new(rate).unwrap();
+        assert!(decoder.decode_packet(&packets[0]).is_err());
+        assert!(decoder.decode_packet(&packets[1]).is_ok());
+    }
+}
+
+#[test]
+fn max_output_bytes_counts_every_channel() {
+    let encoded = silk_stream(24000, 1000);
+    let options = SilkDecodeOptions::new().max_output_bytes(48000);
+    assert_eq!(decode(&encoded, options).unwrap().len(), 24000);
+    assert!(matches!(
+        decode(&encoded, options.output_channels(2)),
+        Err(SilkError::OutputLimitExceeded { limit: 48000 })
+    ));
+    // the limit applies to what is left after max_duration
+    let options = options
+        .output_channels(2)
+        .max_duration(Duration::from_millis(500));
+    assert_eq!(decode(&encoded, options).unwrap().len(), 24000);
+}
+
+#[test]
+fn decode_into_reuses_the_decoder_state() {
+    let first = silk_stream(24000, 300);
+    let second = encoded_speech(24000, 500, &SilkEncodeOptions::new().bit_rate(12000));
+    let mut decoder = SilkDecoder::new(24000).unwrap();
+    // leftovers of a chunked stream are dropped as well
+    decoder
+        .feed_container_bytes(&first[..first.len() / 2])
+        .unwrap();
+
+    let mut out = Vec::new();
+    for src in [&first, &second, &first] {
+        let len = decoder.decode_into(src, &mut out).unwrap();
+        assert_eq!(len, out.len());
+        assert_eq!(out, decode_silk(src, 24000).unwrap());
+    }
+}
+
+fn error(a: &[i16], b: &[i16]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum::<f64>()
+        / a.len() as f64
+}
+
+// the second half of a split stream starts from a cold decoder, the skipped warm-up is
+// where it disagrees most with the stream decoded in one go
+#[test]
+fn skip_leading_ms_trims_the_cold_start_of_a_split() {
+    let encoded = silk_stream(16000, 1000);
+    let full = decode(
+        &encoded,
+        SilkDecodeOptions::new().sample_rate(SampleRate::Hz16000),
+    )
+    .unwrap();
+    let second_half = assemble_silk(&packets(&encoded)[25..], SilkHeaderVariant::Standard).unwrap();
+    let boundary = 25 * 320;
+
+    let cold = decode(
+        &second_half,
+        SilkDecodeOptions::new().sample_rate(SampleRate::Hz16000),
+    )
+    .unwrap();
+    assert_eq!(cold.len(), full.len() - boundary);
+    let skipped = decode(
+        &second_half,
+        SilkDecodeOptions::new()
+            .sample_rate(SampleRate::Hz16000)
+            .skip_leading_ms(40),
+    )
+    .unwrap();
+    assert_eq!(skipped, cold[640..]);
+
+    let window = 160;
+    let cold_error = error(&cold[..window], &full[boundary..]);
+    let skipped_error = error(&skipped[..window], &full[boundary + 640..]);
+    assert!(
+        skipped_error < cold_error,
+        "{skipped_error} vs {cold_error}"
+    );
+}
+
+#[test]
+fn skip_leading_ms_defaults_to_zero_and_can_skip_everything() {
+    let encoded = silk_stream(24000, 200);
+    assert_eq!(
+        decode(&encoded, SilkDecodeOptions::new()).unwrap(),
+        samples(&decode_silk(&encoded, 24000).unwrap())
+    );
+    let options = SilkDecodeOptions::new().skip_leading_ms(500);
+    assert!(decode(&encoded, options).unwrap().is_empty());
+}
+
+#[test]
+fn terminator_ends_the_stream() {
+    let terminated = silk_stream(24000, 300);
+    assert!(terminated.ends_with(&[0xff, 0xff]));
+    let expected = decode_silk(&terminated, 24000).unwrap();
+    assert_eq!(expected.len(), 24000 / 1000 * 300 * 2);
+
+    let unterminated = &terminated[..terminated.len() - 2];
+    assert_eq!(decode_silk(unterminated, 24000).unwrap(), expected);
+
+    // whatever follows the terminator is not part of the stream
+    let mut junk = terminated.clone();
+    junk.extend_from_slice(b"\x05\x00garbage after the end");
+    assert_eq!(decode_silk(&junk, 24000).unwrap(), expected);
+    // any negative length is taken as the end
+    let end = terminated.len() - 2;
+    junk[end..end + 2].copy_from_slice(&i16::MIN.to_le_bytes());
+    assert_eq!(decode_silk(&junk, 24000).unwrap(), expected);
+
+    let mut tencent = vec![0x02];
+    tencent.extend_from_slice(&terminated);
+    assert_eq!(decode_silk(&tencent, 24000).unwrap(), expected);
+    assert_eq!(
+        SilkHeaderVariant::detect(&tencent).unwrap(),
+        SilkHeaderVariant::TencentTerminated
+    );
+}
+
+#[test]
+fn multi_frame_packets_decode_every_frame() {
+    let pcm = speech_pcm(16000, 1200);
+    let twenty_ms = SilkEncodeOptions::new().sample_rate(SampleRate::Hz16000);
+    for duration_ms in [40, 60, 80, 100] {
+        let options = twenty_ms.packet_duration_ms(duration_ms);
+        let encoded = encode_silk_with_options(&pcm, &options).unwrap();
+        let decoded = decode_silk(&encoded, 16000).unwrap();
+        assert_eq!(decoded.len(), pcm.len(), "{duration_ms} ms");
+        assert_eq!(
+            silk_duration(&encoded, 16000).unwrap(),
+            Duration::from_millis(1200)
+        );
+
+        // a packet at a time comes out as its whole duration
+        let mut decoder = SilkDecoder::new(16000).unwrap();
+        let mut streamed = vec![];
+        for packet in packets(&encoded) {
+            let frame = decoder.decode_packet(&packet).unwrap();
+            assert_eq!(frame.len(), 16 * duration_ms as usize);
+            streamed.extend(frame);
+        }
+        assert_eq!(streamed, samples(&decoded));
+    }
+}
+
+#[test]
+fn lost_packets_are_concealed_at_full_length() {
+    let encoded = silk_stream(24000, 1000);
+    let full = decode_silk(&encoded, 24000).unwrap();
+    let packets = packets(&encoded);
+    let received = packets
+        .iter()
+        .enumerate()
+        .map(|(index, packet)| (index % 5 != 4).then_some(&packet[..]))
+        .collect::<Vec<_>>();
+    for rate in [8000, 24000, 48000] {
+        let options = at(rate);
+        let decoded = decode_silk_packets(&received, &options).unwrap();
+        assert_eq!(decoded.len(), rate as usize * 2, "{rate} Hz");
+    }
+
+    // the packets that did arrive before the first gap decode as without loss
+    let options = SilkDecodeOptions::new();
+    let decoded = decode_silk_packets(&received, &options).unwrap();
+    assert_eq!(decoded[..4 * 960], full[..4 * 960]);
+    assert_ne!(decoded, full);
+
+    // the stateful decoder conceals the same way
+    let mut decoder = SilkDecoder::new(24000).unwrap();
+    let mut streamed = vec![];
+    for packet in &received {
+        streamed.extend(match packet {
+            Some(packet) => decoder.decode_packet(packet).unwrap(),
+            None => decoder.conceal().unwrap(),
+        });
+    }
+    let options = options.in_band_fec(false);
+    assert_eq!(
+        streamed,
+        samples(&decode_silk_packets(&received, &options).unwrap())
+    );
+
+    // nothing received at all is near silence of the right length
+    let decoded = decode_silk_packets(&[None; 10], &SilkDecodeOptions::new()).unwrap();
+    assert_eq!(decoded.len(), 10 * 960);
+    assert!(samples(&decoded).iter().all(|sample| sample.abs() <= 1));
+}
+
+#[test]
+fn i16_decoder_matches_the_byte_decoder() {
+    for encoded in [
+        silk_stream(8000, 300),
+        silk_stream(24000, 500),
+        long_packet_stream(48000),
+    ] {
+        let bytes = decode_silk(&encoded, 24000).unwrap();
+        let pcm = decode_silk_i16(&encoded, 24000).unwrap();
+        let pcm_bytes = pcm.iter().flat_map(|sample| sample.to_le_bytes());
+        assert_eq!(pcm_bytes.collect::<Vec<_>>(), bytes);
+    }
+    assert!(matches!(
+        decode_silk_i16(b"#!SILK_V2", 24000),
+        Err(SilkError::Invalid)
+    ));
+}
+
+#[test]
+fn f32_decoder_scales_the_i16_samples() {
+    // a full-scale tone overshoots in the codec and decodes clipped
+    let loud = TestSignal::sine(300.0)
+        .amplitude(1.0)
+        .sample_rate(24000)
+        .duration(Duration::from_millis(500))
+        .pcm_bytes();
+    let options = SilkEncodeOptions::new().bit_rate(60000);
+    let encoded = encode_silk_with_options(loud, &options).unwrap();
+    let pcm = decode_silk_i16(&encoded, 24000).unwrap();
+    let floats = decode_silk_f32(&encoded, 24000).unwrap();
+    assert_eq!(floats.len(), pcm.len());
+    for (&float, &sample) in floats.iter().zip(&pcm) {
+        assert_eq!(float, sample as f32 / 32768.0);
+    }
+
+    assert_eq!(pcm.iter().min(), Some(&i16::MIN));
+    let min = floats.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = floats.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    assert_eq!(min, -1.0);
+    assert!((0.9..1.0).contains(&max), "{max}");
+}
+
+#[test]
+fn stateful_decoder_matches_the_one_shot_decode() {
+    let encoded = silk_stream(24000, 700);
+    let expected = samples(&decode_silk(&encoded, 24000).unwrap());
+
+    let mut decoder = SilkDecoder::new(24000).unwrap();
+    let streamed = packets(&encoded)
+        .iter()
+        .flat_map(|packet| decoder.decode_packet(packet).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(streamed, expected);
+
+    let mut tencent = vec![0x02];
+    tencent.extend_from_slice(&encoded);
+    tencent.extend_from_slice(b"after the terminator");
+    for chunk_len in [1, 2, 7, 100, tencent.len()] {
+        let mut decoder = SilkDecoder::new(24000).unwrap();
+        let mut fed = vec![];
+        for chunk in tencent.chunks(chunk_len) {
+            fed.extend(decoder.feed_container_bytes(chunk).unwrap());
+        }
+        assert_eq!(fed, expected, "chunks of {chunk_len}");
+    }
+
+    let mut decoder = SilkDecoder::new(24000).unwrap();
+    assert!(matches!(
+        decoder.feed_container_bytes(b"#!SILK_V2"),
+        Err(SilkError::Invalid)
+    ));
+    assert!(matches!(
+        SilkDecoder::new(11025),
+        Err(SilkError::UnsupportedSampleRate(11025))
+    ));
+}
+
+#[test]
+fn stateful_decoder_rejects_oversized_packets() {
+    let first = packets(&silk_stream(24000, 100)).remove(0);
+    let mut container = b"#!SILK_V3".to_vec();
+    container.extend_from_slice(&(first.len() as i16).to_le_bytes());
+    container.extend_from_slice(&first);
+    container.extend_from_slice(&2000i16.to_le_bytes());
+    container.resize(container.len() + 2000, 0);
+
+    // the offset points at the length prefix, whatever the chunking
+    let offset = 9 + 2 + first.len();
+    for chunk_len in [1, 100, container.len()] {
+        let mut decoder = SilkDecoder::new(24000).unwrap();
+        let err = container
+            .chunks(chunk_len)
+            .map(|chunk| decoder.feed_container_bytes(chunk))
+            .find_map(Result::err);
+        assert!(
+            matches!(
+                err,
+                Some(SilkError::InvalidPacketLength { offset: at, len: 2000 }) if at == offset
+            ),
+            "chunks of {chunk_len}: {err:?}"
+        );
+    }
+
+    let mut decoder = SilkDecoder::new(24000).unwrap();
+    assert!(matches!(
+        decoder.decode_packet(&[0; 2000]),
+        Err(SilkError::DecPayloadTooLarge)
+    ));
+}
+
+#[test]
+fn errors_point_at_the_rejected_packet() {
+    for index in [0, 7, 49] {
+        let (stream, offset) = corrupt_stream(index);
+        let err = decode_silk(&stream, 24000).unwrap_err();
+        let SilkError::Frame {
+            index: given,
+            offset: at,
+            ref source,
+        } = err
+        else {
+            panic!("{err:?}");
+        };
+        assert_eq!((given, at), (index, offset));
+        assert!(matches!(**source, SilkError::DecPayloadError));
+        let position = format!("Frame {index} at {offset}: DecPayloadError");
+        assert_eq!(err.to_string(), position);
+
+        // the other entry points report the same position
+        for result in [
+            decode_silk_i16(&stream, 16000).map(drop),
+            silk_duration(&stream, 16000).map(drop),
+        ] {
+            let err = result.unwrap_err();
+            assert_eq!(err.to_string(), position);
+        }
+    }
+}
+
+#[test]
+fn every_output_rate_gets_whole_frames() {
+    // 44.1 kHz is the one rate without a whole number of samples per millisecond, the
+    // SDK resamples to it like to the others and a 20 ms frame is exactly 882 samples
+    let encoded = silk_stream(16000, 340);
+    for (rate, frame_samples) in [
+        (8000, 160),
+        (12000, 240),
+        (16000, 320),
+        (24000, 480),
+        (32000, 640),
+        (44100, 882),
+        (48000, 960),
+    ] {
+        let mut decoder = SilkDecoder::new(rate).unwrap();
+        for packet in packets(&encoded) {
+            assert_eq!(decoder.decode_packet(&packet).unwrap().len(), frame_samples);
+        }
+        let decoded = decode_silk(&encoded, rate).unwrap();
+        assert_eq!(decoded.len(), 17 * frame_samples * 2, "{rate} Hz");
+    }
+
+    for rate in [0, 11025, 22050, 96000] {
+        let unsupported =
+            |err| matches!(err, SilkError::UnsupportedSampleRate(given) if given == rate);
+        assert!(decode_silk(&encoded, rate).is_err_and(unsupported));
+        assert!(SilkDecoder::new(rate).is_err_and(unsupported));
+        assert!(SampleRate::try_from(rate).is_err_and(unsupported));
+    }
+}
+
+// a 100 ms packet and then two bytes per 100 ms of concealment: 400 kB claiming
+// more than five hours, close to 2 GB of 48 kHz PCM
+#[test]
+fn default_limit_stops_a_dtx_bomb() {
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::Hz48000)
+        .packet_duration_ms(100);
+    let first = &packets(&encoded_speech(48000, 100, &options))[0];
+    let mut bomb = assemble_silk([first], SilkHeaderVariant::StandardUnterminated).unwrap();
+    bomb.resize(bomb.len() + 400_000, 0);
+    assert_eq!(
+        silk_duration(&bomb, 48000).unwrap(),
+        Duration::from_millis(100 * 200_001)
+    );
+
+    let limit = 10 * 60 * 48000 * 2 * 2;
+    let mut out = Vec::new();
+    assert!(matches!(
+        decode_silk_into(&bomb, 48000, &mut out),
+        Err(SilkError::OutputLimitExceeded { limit: given }) if given == limit
+    ));
+    assert!(out.len() as u64 <= limit);
+    assert!(matches!(
+        decode_silk_to_writer(&bomb, 48000, std::io::sink()),
+        Err(SilkError::OutputLimitExceeded { limit: given }) if given == limit
+    ));
+}
+
+// every empty packet after the first conceals another 100 ms, 9600 bytes at 48 kHz
+// mono, so 12000 packets land exactly on the 115.2 MB default
+#[test]
+fn default_limit_holds_up_to_the_byte() {
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::Hz48000)
+        .packet_duration_ms(100);
+    let first = &packets(&encoded_speech(48000, 100, &options))[0];
+    let stream = |packets: usize| {
+        let mut stream = assemble_silk([first], SilkHeaderVariant::StandardUnterminated).unwrap();
+        stream.resize(stream.len() + 2 * (packets - 1), 0);
+        stream
+    };
+    let limit = 10 * 60 * 48000 * 2 * 2;
+    let fits = (limit / 9600) as usize;
+
+    assert_eq!(
+        decode_silk(stream(fits), 48000).unwrap().len() as u64,
+        limit
+    );
+    assert!(matches!(
+        decode_silk(stream(fits + 1), 48000),
+        Err(SilkError::OutputLimitExceeded { limit: given }) if given == limit
+    ));
+    assert_eq!(
+        decode_silk_to_writer(stream(fits), 48000, std::io::sink()).unwrap(),
+        limit
+    );
+    assert!(matches!(
+        decode_silk_to_writer(stream(fits + 1), 48000, std::io::sink()),
+        Err(SilkError::OutputLimitExceeded { limit: given }) if given == limit
+    ));
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "denoise")]
+
+mod common;
+
+use common::{samples, speech, temp_path};
+use silk_codec::{AudioConverter, TestSignal, WavWriter};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+fn read_pcm(converter: &AudioConverter, input: &Path) -> Vec<i16> {
+    let mut pcm = vec![];
+    converter
+        .reader(input)
+        .unwrap()
+        .read_to_end(&mut pcm)
+        .unwrap();
+    samples(&pcm)
+}
+
+/// SNR of `output` against `clean`, at the best of the few-sample shifts the
+/// resamplers may leave.
+fn snr_db(clean: &[i16], output: &[i16]) -> f64 {
+    (0..=48)
+        .map(|delay| {
+            let shifted = output.get(delay..).unwrap_or_default();
+            let (signal, noise) =
+                clean
+                    .iter()
+                    .zip(shifted)
+                    .fold((0.0, 0.0), |(signal, noise), (&a, &b)| {
+                        let error = a as f64 - b as f64;
+                        (signal + a as f64 * a as f64, noise + error * error)
+                    });
+            10.0 * (signal / noise).log10()
+        })
+        .fold(f64::MIN, f64::max)
+}
+
+#[test]
+fn denoising_raises_the_snr_of_noisy_speech() {
+    let clean = speech(24000, 3000).samples_i16();
+    let noise = TestSignal::white_noise()
+        .sample_rate(24000)
+        .duration(Duration::from_millis(3000))
+        .amplitude(0.1)
+        .samples_i16();
+    let input = temp_path("noisy.wav");
+    let mut writer = WavWriter::new(File::create(&input).unwrap(), 24000, 1, None).unwrap();
+    for (&speech, &noise) in clean.iter().zip(&noise) {
+        writer
+            .write_all(&speech.saturating_add(noise).to_le_bytes())
+            .unwrap();
+    }
+    writer.finish().unwrap();
+
+    let plain = read_pcm(&AudioConverter::new().unwrap(), &input);
+    let denoised = read_pcm(&AudioConverter::new().unwrap().with_denoise(true), &input);
+    // the model latency is compensated, only the 48 kHz round trip may round the length
+    assert!(
+        denoised.len().abs_diff(plain.len()) <= 48,
+        "{} vs {}",
+        denoised.len(),
+        plain.len()
+    );
+
+    let (before, after) = (snr_db(&clean, &plain), snr_db(&clean, &denoised));
+    assert!(after > before + 3.0, "{before:.1} dB -> {after:.1} dB");
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn chapter_conversion_is_denoised_too() {
+    let input = temp_path("denoise-chapters.wav");
+    speech(24000, 1000)
+        .write_wav(File::create(&input).unwrap())
+        .unwrap();
+    let output_dir = temp_path("denoise-chapters");
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let converter = AudioConverter::new().unwrap().with_denoise(true);
+    let conversion = converter.convert_by_chapters(&input, &output_dir).unwrap();
+    let chapter = fs::read(&conversion.chapters[0].output_path).unwrap();
+    assert_eq!(samples(&chapter), read_pcm(&converter, &input));
+    fs::remove_dir_all(output_dir).unwrap();
+    fs::remove_file(input).unwrap();
+}
@@ -0,0 +1,910 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{PITCH, packets, pcm_duration, samples, speech, speech_pcm};
+use silk_codec::{
+    EncoderStatus, InputReport, NormalizeMode, SampleRate, SilkDecodeOptions, SilkEncodeOptions,
+    SilkEncoder, SilkEncoderHandle, SilkError, SilkHeaderVariant, SilkPreset, TestSignal,
+    analyze_pcm, decode_silk, decode_silk_packets, decode_silk_with_options,
+    encode_silk_cancellable, encode_silk_f32_with_options, encode_silk_multi, encode_silk_preset,
+    encode_silk_samples_with_options, encode_silk_to_size, encode_silk_with_options,
+    encode_silk_with_progress, encode_silk_with_stats, estimate_encoded_size, estimate_silk_size,
+    max_bitrate_for_size,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// full-scale noise is the hardest input, the encoder spends the most bits on it
+fn loud_noise(sample_rate: u32, duration_ms: u64) -> Vec<u8> {
+    TestSignal::white_noise()
+        .amplitude(1.0)
+        .sample_rate(sample_rate)
+        .duration(Duration::from_millis(duration_ms))
+        .pcm_bytes()
+}
+
+#[test]
+fn estimate_bounds_the_output_at_max_bit_rate() {
+    for rate in [8000, 24000, 48000] {
+        for duration_ms in [20, 330, 1000, 3000] {
+            let pcm = loud_noise(rate, duration_ms);
+            let estimate = estimate_encoded_size(pcm.len(), rate as i32, 100000);
+            let options = SilkEncodeOptions::new()
+                .sample_rate(SampleRate::try_from(rate as i32).unwrap())
+                .bit_rate(100000);
+            let output = encode_silk_with_options(&pcm, &options).unwrap();
+            assert!(output.len() <= estimate, "{rate} Hz, {duration_ms} ms");
+        }
+    }
+}
+
+#[test]
+fn output_buffers_are_sized_from_the_bit_rate() {
+    // 60 s at 10 kbps is about 80 KB, the worst-case bound is over 3 MB
+    let pcm = speech_pcm(24000, 60_000);
+    let options = SilkEncodeOptions::new().bit_rate(10000);
+    let output = encode_silk_with_options(&pcm, &options).unwrap();
+    assert!(estimate_encoded_size(pcm.len(), 24000, 10000) > 20 * output.len());
+    assert!(
+        output.capacity() < 3 * output.len(),
+        "{}",
+        output.capacity()
+    );
+
+    for output in encode_silk_multi(&pcm, 24000, &[10000, 40000], false) {
+        let output = output.unwrap();
+        assert!(
+            output.capacity() < 3 * output.len(),
+            "{}",
+            output.capacity()
+        );
+    }
+}
+
+#[test]
+fn long_packets_fit_at_max_bit_rate() {
+    for rate in [24000, 44100, 48000] {
+        for in_band_fec in [false, true] {
+            let options = SilkEncodeOptions::new()
+                .sample_rate(SampleRate::try_from(rate as i32).unwrap())
+                .bit_rate(100000)
+                .packet_duration_ms(100)
+                .in_band_fec(in_band_fec);
+            let encoded = encode_silk_with_options(speech_pcm(rate, 2000), &options).unwrap();
+            let packets = packets(&encoded);
+            assert_eq!(packets.len(), 20);
+            assert!(packets.iter().all(|packet| packet.len() <= 1024));
+            let decoded = decode_silk(&encoded, rate as i32).unwrap();
+            assert_eq!(decoded.len(), speech_pcm(rate, 2000).len(), "{rate} Hz");
+        }
+    }
+}
+
+/// Error energy of the first packet against the input, relative to the input, at the
+/// codec delay that fits best. The primer is one 20 ms packet and skipped on decode.
+fn first_frame_error(pcm: &[i16], prime_ms: u16) -> f64 {
+    let options = SilkEncodeOptions::new().prime_ms(prime_ms);
+    let encoded = encode_silk_samples_with_options(pcm, &options).unwrap();
+    let skip = if prime_ms == 0 { 0 } else { 20 };
+    let decode_options = SilkDecodeOptions::new().skip_leading_ms(skip);
+    let decoded = samples(&decode_silk_with_options(&encoded, &decode_options).unwrap());
+    assert_eq!(decoded.len(), pcm.len(), "only the primer is skipped");
+    let energy = pcm[..480].iter().map(|&a| (a as f64).powi(2)).sum::<f64>();
+    (0..240)
+        .map(|delay| {
+            pcm[..480]
+                .iter()
+                .zip(&decoded[delay..delay + 480])
+                .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+                .sum::<f64>()
+        })
+        .fold(f64::INFINITY, f64::min)
+        / energy
+}
+
+#[test]
+fn priming_rounds_up_to_whole_packets() {
+    let pcm = TestSignal::sine(300.0)
+        .duration(Duration::from_millis(500))
+        .samples_i16();
+    let encode = |prime_ms| {
+        let options = SilkEncodeOptions::new().prime_ms(prime_ms);
+        encode_silk_samples_with_options(&pcm, &options).unwrap()
+    };
+    assert_eq!(encode(5), encode(20));
+    assert_ne!(encode(20), encode(0));
+    assert_ne!(encode(21), encode(20));
+}
+
+// A/B of the first frame: the primer goes out in the stream, so the decoder is as
+// warmed up as the encoder when the real audio starts
+#[test]
+fn priming_lowers_the_first_frame_error() {
+    for signal in [
+        TestSignal::sine(300.0),
+        TestSignal::sweep(200.0, 3000.0),
+        TestSignal::speech_like(PITCH),
+    ] {
+        let pcm = signal.duration(Duration::from_millis(500)).samples_i16();
+        let cold = first_frame_error(&pcm, 0);
+        let primed = first_frame_error(&pcm, 10);
+        assert!(primed < cold, "{signal:?}: {primed} vs {cold}");
+    }
+}
+
+#[test]
+fn primer_packets_lead_the_stream() {
+    let pcm = speech_pcm(24000, 500);
+    let cold = encode_silk_with_options(&pcm, &SilkEncodeOptions::new()).unwrap();
+    let options = SilkEncodeOptions::new().prime_ms(40);
+    let primed = encode_silk_with_options(&pcm, &options).unwrap();
+    assert_eq!(packets(&primed).len(), packets(&cold).len() + 2);
+
+    let stats = encode_silk_with_stats(&pcm, &options).unwrap().1;
+    assert_eq!(
+        (stats.frames, stats.duration),
+        (25, Duration::from_millis(500))
+    );
+    assert_eq!(stats.output_bytes, primed.len());
+    let decoded = decode_silk(&primed, 24000).unwrap();
+    assert_eq!(decoded.len(), pcm.len() + 2 * 960);
+}
+
+#[test]
+fn sample_input_matches_the_byte_input() {
+    let pcm = speech_pcm(24000, 330);
+    for options in [
+        SilkEncodeOptions::new(),
+        SilkEncodeOptions::new().channels(2).tencent(true),
+    ] {
+        assert_eq!(
+            encode_silk_samples_with_options(&samples(&pcm), &options).unwrap(),
+            encode_silk_with_options(&pcm, &options).unwrap()
+        );
+    }
+    for result in [
+        encode_silk_samples_with_options(&[], &SilkEncodeOptions::new()),
+        encode_silk_with_options([], &SilkEncodeOptions::new()),
+    ] {
+        assert!(matches!(result, Err(SilkError::InvalidPcmLength(0))));
+    }
+}
+
+#[test]
+fn f32_input_is_sanitized_before_quantizing() {
+    let pcm = samples(&speech_pcm(24000, 200));
+    let mut floats = pcm
+        .iter()
+        .map(|&sample| sample as f32 / 32768.0)
+        .collect::<Vec<_>>();
+    let mut expected = pcm.clone();
+    // NaN becomes silence, anything past full scale clips instead of wrapping
+    for (index, sample, quantized) in [
+        (100, f32::NAN, 0),
+        (200, f32::INFINITY, i16::MAX),
+        (300, f32::NEG_INFINITY, i16::MIN),
+        (400, 1.5, i16::MAX),
+        (500, -7.0, i16::MIN),
+        (600, 1.0, i16::MAX),
+        (700, -1.0, i16::MIN),
+    ] {
+        floats[index] = sample;
+        expected[index] = quantized;
+    }
+
+    let options = SilkEncodeOptions::new();
+    let from_floats = encode_silk_f32_with_options(&floats, &options).unwrap();
+    assert_eq!(
+        from_floats,
+        encode_silk_samples_with_options(&expected, &options).unwrap()
+    );
+    assert_eq!(
+        decode_silk(&from_floats, 24000).unwrap().len(),
+        pcm.len() * 2
+    );
+}
+
+#[test]
+fn encodes_stop_when_cancelled_from_another_thread() {
+    // long enough that the encode is still running when the flag is set
+    let pcm = speech_pcm(24000, 60_000);
+    let options = SilkEncodeOptions::new();
+    let cancel = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let encode = scope.spawn(|| encode_silk_cancellable(&pcm, &options, &cancel));
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(encode.join().unwrap(), Err(SilkError::Cancelled)));
+    });
+    // the primer, here the first half of the work, checks the flag as well
+    let cancel = AtomicBool::new(false);
+    let primed = options.prime_ms(60_000);
+    thread::scope(|scope| {
+        let encode = scope.spawn(|| encode_silk_cancellable(&pcm, &primed, &cancel));
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(encode.join().unwrap(), Err(SilkError::Cancelled)));
+    });
+    // set up front, the SDK encoder is never created
+    assert!(matches!(
+        encode_silk_cancellable(&pcm, &options, &cancel),
+        Err(SilkError::Cancelled)
+    ));
+
+    // a streaming encode checking the same flag keeps a valid prefix of the stream
+    let cancel = AtomicBool::new(false);
+    let (partial, fed) = thread::scope(|scope| {
+        let encode = scope.spawn(|| {
+            let mut encoder = SilkEncoder::new(&options).unwrap();
+            let mut output = vec![];
+            let mut fed = 0;
+            for chunk in pcm.chunks(960) {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                output.extend(encoder.feed(chunk).unwrap());
+                fed += chunk.len();
+            }
+            output.extend(encoder.finish().unwrap());
+            (output, fed)
+        });
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, Ordering::Relaxed);
+        encode.join().unwrap()
+    });
+    assert!(fed < pcm.len(), "the encode finished before the cancel");
+    assert_eq!(
+        partial,
+        encode_silk_with_options(&pcm[..fed], &options).unwrap()
+    );
+    assert_eq!(decode_silk(&partial, 24000).unwrap().len(), fed);
+}
+
+#[test]
+fn fit_to_size_keeps_noise_under_the_cap() {
+    let pcm = TestSignal::white_noise()
+        .amplitude(0.3)
+        .sample_rate(24000)
+        .duration(Duration::from_secs(3))
+        .pcm_bytes();
+    let mut last_bit_rate = 0;
+    for max_bytes in [3000, 8000, 16000, 40000] {
+        let options = SilkEncodeOptions::new().fit_to_size(max_bytes);
+        let (encoded, stats) = encode_silk_with_stats(&pcm, &options).unwrap();
+        assert!(
+            encoded.len() as u64 <= max_bytes,
+            "{} > {max_bytes}",
+            encoded.len()
+        );
+        // at most the estimated rate, lower where the SDK overshot it
+        let estimated = max_bitrate_for_size(stats.duration, max_bytes, &options).unwrap();
+        assert!(stats.target_bit_rate <= estimated);
+        assert!(stats.target_bit_rate > last_bit_rate);
+        last_bit_rate = stats.target_bit_rate;
+    }
+    // 40 kB leaves room for more than the SDK maximum
+    assert_eq!(last_bit_rate, 100000);
+
+    // 150 packets need 300 bytes of length prefixes alone
+    let options = SilkEncodeOptions::new().fit_to_size(1000);
+    assert!(matches!(
+        encode_silk_with_stats(&pcm, &options),
+        Err(SilkError::SizeBudgetTooSmall { max_bytes: 1000 })
+    ));
+}
+
+/// What the SDK reports after encoding 200 ms of 24 kHz speech with `options`.
+fn sdk_status(options: SilkEncodeOptions) -> EncoderStatus {
+    sdk_status_at(options, 24000)
+}
+
+/// [`sdk_status`] for `options` at `sample_rate`.
+fn sdk_status_at(options: SilkEncodeOptions, sample_rate: u32) -> EncoderStatus {
+    let mut handle = SilkEncoderHandle::new(&options).unwrap();
+    handle.encode(speech_pcm(sample_rate, 200)).unwrap();
+    handle.status().unwrap()
+}
+
+#[test]
+fn options_reach_the_sdk_encoder() {
+    let defaults = sdk_status(SilkEncodeOptions::new());
+    assert_eq!(
+        defaults,
+        EncoderStatus {
+            sample_rate: 24000,
+            max_internal_sample_rate: 24000,
+            packet_size: 480,
+            bit_rate: 24000,
+            packet_loss_percentage: 0,
+            complexity: 2,
+            in_band_fec: false,
+            dtx: false,
+        }
+    );
+
+    let options = SilkEncodeOptions::new();
+    let cases = [
+        (
+            options.bit_rate(16000),
+            EncoderStatus {
+                bit_rate: 16000,
+                ..defaults
+            },
+        ),
+        (
+            options.complexity(0),
+            EncoderStatus {
+                complexity: 0,
+                ..defaults
+            },
+        ),
+        (
+            options.dtx(true),
+            EncoderStatus {
+                dtx: true,
+                ..defaults
+            },
+        ),
+        (
+            options.packet_loss_percentage(20),
+            EncoderStatus {
+                packet_loss_percentage: 20,
+                ..defaults
+            },
+        ),
+        (
+            options.in_band_fec(true),
+            EncoderStatus {
+                in_band_fec: true,
+                packet_loss_percentage: 10,
+                ..defaults
+            },
+        ),
+        (
+            options.max_internal_sample_rate(16000),
+            EncoderStatus {
+                max_internal_sample_rate: 16000,
+                ..defaults
+            },
+        ),
+        (
+            options.packet_duration_ms(60),
+            EncoderStatus {
+                packet_size: 1440,
+                ..defaults
+            },
+        ),
+    ];
+    for (options, expected) in cases {
+        assert_eq!(sdk_status(options), expected, "{options:?}");
+    }
+}
+
+#[test]
+fn invalid_combinations_are_rejected_before_the_sdk() {
+    let pcm = speech_pcm(24000, 100);
+    let options = SilkEncodeOptions::new();
+    let cases = [
+        (
+            options.in_band_fec(true).packet_loss_percentage(0),
+            SilkError::EncInvalidInbandFecSetting,
+        ),
+        (
+            options.complexity(3),
+            SilkError::EncInvalidComplexitySetting,
+        ),
+        (
+            options.packet_loss_percentage(101),
+            SilkError::EncInvalidLossRate,
+        ),
+        (
+            options.max_internal_sample_rate(11025),
+            SilkError::EncFsNotSupported,
+        ),
+        (
+            options.packet_duration_ms(30),
+            SilkError::EncPacketSizeNotSupported,
+        ),
+    ];
+    for (options, expected) in cases {
+        let err = encode_silk_with_options(&pcm, &options).unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string(), "{options:?}");
+        assert!(SilkEncoderHandle::new(&options).is_err(), "{options:?}");
+    }
+}
+
+#[test]
+fn packet_loss_composes_with_fec() {
+    let pcm = speech_pcm(24000, 1000);
+    let fec = SilkEncodeOptions::new().in_band_fec(true);
+    let robust = fec.packet_loss_percentage(20);
+    let status = sdk_status(robust);
+    assert_eq!(
+        (status.packet_loss_percentage, status.in_band_fec),
+        (20, true)
+    );
+
+    // the SDK spends bits on robustness as the expected loss goes up
+    let low = encode_silk_with_options(&pcm, &fec).unwrap();
+    let high = encode_silk_with_options(&pcm, &robust).unwrap();
+    assert_ne!(low, high);
+    assert_eq!(decode_silk(&high, 24000).unwrap().len(), pcm.len());
+
+    for loss in [0, 100] {
+        let options = SilkEncodeOptions::new().packet_loss_percentage(loss);
+        assert!(encode_silk_with_options(&pcm, &options).is_ok(), "{loss}%");
+    }
+}
+
+#[test]
+fn narrowband_cap_shrinks_the_stream() {
+    let pcm = speech_pcm(24000, 1000);
+    let options = SilkEncodeOptions::new().bit_rate(30000);
+    let wideband = encode_silk_with_options(&pcm, &options).unwrap();
+    let narrowband =
+        encode_silk_with_options(&pcm, &options.max_internal_sample_rate(8000)).unwrap();
+    assert!(narrowband.len() < wideband.len());
+    assert_eq!(decode_silk(&narrowband, 24000).unwrap().len(), pcm.len());
+
+    // the default follows the input rate up to 24 kHz
+    for (rate, internal) in [(8000, 8000), (16000, 16000), (48000, 24000)] {
+        let options = SilkEncodeOptions::new().sample_rate(SampleRate::try_from(rate).unwrap());
+        let status = sdk_status_at(options, rate as u32);
+        assert_eq!(status.max_internal_sample_rate, internal);
+    }
+}
+
+#[test]
+fn bit_rate_range_is_enforced() {
+    let pcm = speech_pcm(24000, 100);
+    for bit_rate in [5000, 100000] {
+        let options = SilkEncodeOptions::new().bit_rate(bit_rate);
+        assert!(
+            encode_silk_with_options(&pcm, &options).is_ok(),
+            "{bit_rate}"
+        );
+    }
+    for bit_rate in [24, 4999, 100001, -1] {
+        let options = SilkEncodeOptions::new().bit_rate(bit_rate);
+        assert!(matches!(
+            encode_silk_with_options(&pcm, &options),
+            Err(SilkError::InvalidBitRate { given, min: 5000, max: 100000 }) if given == bit_rate
+        ));
+    }
+
+    // in range but more than narrowband can use: the SDK keeps the target and spends
+    // what its rate table allows
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::Hz8000)
+        .bit_rate(100000);
+    let (_, stats) = encode_silk_with_stats(speech_pcm(8000, 2000), &options).unwrap();
+    assert_eq!(stats.target_bit_rate, 100000);
+    assert!(stats.bit_rate < 30000, "{} bps", stats.bit_rate);
+    assert_eq!(sdk_status_at(options, 8000).bit_rate, 100000);
+}
+
+#[test]
+fn pcm_has_to_be_whole_samples() {
+    for len in [0, 1, 481] {
+        assert!(matches!(
+            encode_silk_with_options(vec![0; len], &SilkEncodeOptions::new()),
+            Err(SilkError::InvalidPcmLength(given)) if given == len
+        ));
+    }
+    // an input starting at an odd address encodes like an aligned copy
+    let pcm = speech_pcm(24000, 100);
+    let mut shifted = vec![0];
+    shifted.extend_from_slice(&pcm);
+    let options = SilkEncodeOptions::new();
+    assert_eq!(
+        encode_silk_with_options(&shifted[1..], &options).unwrap(),
+        encode_silk_with_options(&pcm, &options).unwrap()
+    );
+}
+
+#[test]
+fn multi_rate_outputs_match_single_rate_encodes() {
+    let pcm = speech_pcm(24000, 1000);
+    let bit_rates = [10000, 24000, 40000, 1000];
+    let outputs = encode_silk_multi(&pcm, 24000, &bit_rates, true);
+    assert_eq!(outputs.len(), bit_rates.len());
+    for (&bit_rate, output) in bit_rates[..3].iter().zip(&outputs) {
+        let options = SilkEncodeOptions::new().bit_rate(bit_rate).tencent(true);
+        assert_eq!(
+            output.as_ref().unwrap(),
+            &encode_silk_with_options(&pcm, &options).unwrap(),
+            "{bit_rate} bps"
+        );
+    }
+    let sizes = outputs[..3]
+        .iter()
+        .map(|output| output.as_ref().unwrap().len())
+        .collect::<Vec<_>>();
+    assert!(sizes.is_sorted_by(|low, high| low < high), "{sizes:?}");
+    // the rejected rate only fails its own slot
+    assert!(matches!(
+        outputs[3],
+        Err(SilkError::InvalidBitRate { given: 1000, .. })
+    ));
+}
+
+#[test]
+fn progress_is_reported_every_interval_and_at_the_end() {
+    // 49.5 frames, the padded last one counts
+    let pcm = speech_pcm(24000, 990);
+    let options = SilkEncodeOptions::new();
+    let mut reports = vec![];
+    let encoded =
+        encode_silk_with_progress(&pcm, &options, 8, |progress| reports.push(progress)).unwrap();
+    assert_eq!(encoded, encode_silk_with_options(&pcm, &options).unwrap());
+
+    let frames = reports
+        .iter()
+        .map(|progress| progress.frames_done)
+        .collect::<Vec<_>>();
+    assert_eq!(frames, [8, 16, 24, 32, 40, 48, 50]);
+    assert!(reports.iter().all(|progress| progress.total_frames == 50));
+    assert!(
+        reports
+            .windows(2)
+            .all(|pair| pair[0].bytes_written < pair[1].bytes_written)
+    );
+    // everything but the terminator was written when the last frame was reported
+    assert_eq!(reports.last().unwrap().bytes_written, encoded.len() - 2);
+
+    // an interval of 0 reports every frame, the primer packets are not counted
+    let mut calls = 0;
+    let mut last = 0;
+    encode_silk_with_progress(&pcm, &options.prime_ms(40), 0, |progress| {
+        calls += 1;
+        assert!(progress.frames_done > last);
+        last = progress.frames_done;
+    })
+    .unwrap();
+    assert_eq!((calls, last), (50, 50));
+}
+
+#[test]
+fn presets_expand_to_their_documented_settings() {
+    for (preset, sample_rate, bit_rate, variant) in [
+        (
+            SilkPreset::QqVoice,
+            24000,
+            24000,
+            SilkHeaderVariant::Tencent,
+        ),
+        (
+            SilkPreset::WeChatVoice,
+            24000,
+            30000,
+            SilkHeaderVariant::Tencent,
+        ),
+        (
+            SilkPreset::HighQuality,
+            24000,
+            40000,
+            SilkHeaderVariant::Standard,
+        ),
+        (
+            SilkPreset::LowBandwidth,
+            8000,
+            8000,
+            SilkHeaderVariant::Standard,
+        ),
+    ] {
+        let options = SilkEncodeOptions::from(preset);
+        assert_eq!(options, preset.options());
+        let status = sdk_status_at(options, sample_rate as u32);
+        assert_eq!(
+            (status.sample_rate, status.bit_rate, status.complexity),
+            (sample_rate, bit_rate, 2),
+            "{preset:?}"
+        );
+        assert_eq!(status.packet_size, sample_rate / 50, "{preset:?}");
+
+        let pcm = speech_pcm(sample_rate as u32, 1000);
+        let encoded = encode_silk_preset(&pcm, preset).unwrap();
+        assert_eq!(encoded, encode_silk_with_options(&pcm, &options).unwrap());
+        assert_eq!(SilkHeaderVariant::detect(&encoded).unwrap(), variant);
+        let decoded = decode_silk(&encoded, sample_rate).unwrap();
+        assert_eq!(decoded.len(), pcm.len(), "{preset:?}");
+    }
+}
+
+#[test]
+fn fec_switched_on_mid_stream_grows_the_packets() {
+    let pcm = speech_pcm(24000, 2000);
+    let (first, second) = pcm.split_at(pcm.len() / 2);
+    let options = SilkEncodeOptions::new();
+    let mut encoder = SilkEncoder::new(&options).unwrap();
+    let mut switched = encoder.feed(first).unwrap();
+    encoder.set_fec(true).unwrap();
+    switched.extend(encoder.feed(second).unwrap());
+    let status = encoder.status().unwrap();
+    assert_eq!(
+        (status.in_band_fec, status.packet_loss_percentage),
+        (true, 10)
+    );
+    switched.extend(encoder.finish().unwrap());
+    assert_eq!(decode_silk(&switched, 24000).unwrap().len(), pcm.len());
+
+    let plain = encode_silk_with_options(&pcm, &options).unwrap();
+    let (switched, plain) = (packets(&switched), packets(&plain));
+    assert_eq!(switched.len(), 100);
+    assert_eq!(switched[..50], plain[..50]);
+    let average = |packets: &[Vec<u8>]| {
+        packets.iter().map(Vec::len).sum::<usize>() as f64 / packets.len() as f64
+    };
+    let (with_fec, without_fec) = (average(&switched[50..]), average(&plain[50..]));
+    assert!(with_fec > without_fec, "{with_fec} vs {without_fec}");
+
+    // only packets lost after the switch have a FEC copy to rebuild them from
+    let recovers = |lost: usize| {
+        let mut received = switched
+            .iter()
+            .map(|packet| Some(&packet[..]))
+            .collect::<Vec<_>>();
+        received[lost] = None;
+        let decode = |options: SilkDecodeOptions| decode_silk_packets(&received, &options).unwrap();
+        decode(SilkDecodeOptions::new()) != decode(SilkDecodeOptions::new().in_band_fec(false))
+    };
+    assert!(!recovers(20));
+    assert!(recovers(70));
+}
+
+#[test]
+fn mid_stream_loss_settings_are_validated() {
+    let mut encoder = SilkEncoder::new(&SilkEncodeOptions::new()).unwrap();
+    assert!(matches!(
+        encoder.set_packet_loss(101),
+        Err(SilkError::EncInvalidLossRate)
+    ));
+    encoder.set_packet_loss(20).unwrap();
+    encoder.set_fec(true).unwrap();
+    // FEC needs some expected loss, as at construction
+    assert!(matches!(
+        encoder.set_packet_loss(0),
+        Err(SilkError::EncInvalidInbandFecSetting)
+    ));
+    encoder.feed(&speech_pcm(24000, 100)).unwrap();
+    let status = encoder.status().unwrap();
+    assert_eq!(
+        (status.in_band_fec, status.packet_loss_percentage),
+        (true, 20)
+    );
+}
+
+#[test]
+fn encode_to_size_picks_the_highest_bit_rate_that_fits() {
+    let pcm = loud_noise(24000, 3000);
+    let encode = |bit_rate| {
+        let options = SilkEncodeOptions::new().bit_rate(bit_rate).tencent(true);
+        encode_silk_with_options(&pcm, &options).unwrap()
+    };
+
+    let max_bytes = 6000;
+    let (encoded, bit_rate) = encode_silk_to_size(&pcm, 24000, max_bytes, true).unwrap();
+    assert!(encoded.len() as u64 <= max_bytes, "{}", encoded.len());
+    assert!(bit_rate < 24000, "{bit_rate} bps");
+    assert_eq!(encoded, encode(bit_rate));
+    // the search goes in 1 kbps steps, the next one up is over the budget
+    assert!(encode(bit_rate + 1000).len() as u64 > max_bytes);
+
+    let (encoded, bit_rate) = encode_silk_to_size(&pcm, 24000, 1_000_000, true).unwrap();
+    assert_eq!(bit_rate, 100000);
+    assert_eq!(encoded, encode(100000));
+
+    // 150 packets need 300 bytes of length prefixes alone
+    assert!(matches!(
+        encode_silk_to_size(&pcm, 24000, 1000, true),
+        Err(SilkError::SizeBudgetTooSmall { max_bytes: 1000 })
+    ));
+}
+
+#[test]
+fn size_estimates_bound_real_encodes() {
+    let duration = Duration::from_secs(3);
+    let noise = TestSignal::white_noise()
+        .amplitude(0.3)
+        .duration(duration)
+        .pcm_bytes();
+    for (input, pcm) in [("noise", noise), ("speech", speech_pcm(24000, 3000))] {
+        // the rates 24 kHz streams use
+        for bit_rate in [16000, 24000, 32000, 40000] {
+            for (packet_duration_ms, tencent) in [(20, false), (60, true)] {
+                let options = SilkEncodeOptions::new()
+                    .bit_rate(bit_rate)
+                    .packet_duration_ms(packet_duration_ms)
+                    .tencent(tencent);
+                let estimate = estimate_silk_size(duration, bit_rate, &options);
+                let actual = encode_silk_with_options(&pcm, &options).unwrap().len() as u64;
+                // the documented margin
+                assert!(
+                    actual * 10 <= estimate * 11,
+                    "{input} at {bit_rate} bps, {packet_duration_ms} ms: {actual} > {estimate}"
+                );
+            }
+        }
+    }
+
+    // the inverse picks the last rate whose estimate fits, in steps of one byte per
+    // 20 ms packet
+    let options = SilkEncodeOptions::new();
+    let bit_rate = max_bitrate_for_size(duration, 20000, &options).unwrap();
+    assert!(estimate_silk_size(duration, bit_rate, &options) <= 20000);
+    assert!(estimate_silk_size(duration, bit_rate + 400, &options) > 20000);
+    assert_eq!(max_bitrate_for_size(duration, 1000, &options), None);
+}
+
+#[test]
+fn normalize_brings_the_input_to_the_target_level() {
+    let pcm = TestSignal::sine(440.0)
+        .amplitude(0.05)
+        .duration(Duration::from_millis(500))
+        .pcm_bytes();
+    let level = |options: SilkEncodeOptions| {
+        let (_, stats) = encode_silk_with_stats(&pcm, &options).unwrap();
+        stats.input.unwrap()
+    };
+    let options = SilkEncodeOptions::new().analyze_input(true);
+
+    let peak = level(options.normalize(NormalizeMode::Peak(-6.0)));
+    assert!((peak.peak_dbfs + 6.0).abs() < 0.05, "{peak:?}");
+    let rms = level(options.normalize(NormalizeMode::Rms(-20.0)));
+    assert!((rms.rms_dbfs + 20.0).abs() < 0.05, "{rms:?}");
+    // a sine peaks 3 dB above its RMS, so -1 dBFS RMS stops at full scale
+    let capped = level(options.normalize(NormalizeMode::Rms(-1.0)));
+    assert!(
+        capped.peak_dbfs > -0.01 && capped.clipped_samples > 0,
+        "{capped:?}"
+    );
+    assert!(capped.rms_dbfs < -2.9, "{capped:?}");
+
+    // without it the encoder sees the input as it is
+    assert_eq!(level(options), analyze_pcm(&pcm, 24000));
+    let silence = vec![0u8; pcm.len()];
+    assert_eq!(
+        encode_silk_with_options(&silence, &options.normalize(NormalizeMode::Peak(-1.0))).unwrap(),
+        encode_silk_with_options(&silence, &options).unwrap()
+    );
+}
+
+#[test]
+fn input_report_is_opt_in() {
+    let pcm = speech_pcm(24000, 200);
+    let (_, stats) = encode_silk_with_stats(&pcm, &SilkEncodeOptions::new()).unwrap();
+    assert_eq!(stats.input, None);
+}
+
+/// [`InputReport`] of `pcm` as measured by the encoder.
+fn input_report(pcm: &[u8]) -> InputReport {
+    let options = SilkEncodeOptions::new().analyze_input(true);
+    let (_, stats) = encode_silk_with_stats(pcm, &options).unwrap();
+    stats.input.unwrap()
+}
+
+#[test]
+fn input_report_flags_silent_input() {
+    let pcm = vec![0u8; 24000];
+    let report = input_report(&pcm);
+    assert!(report.is_silent());
+    assert_eq!(report.peak_dbfs, f32::NEG_INFINITY);
+    assert_eq!(report.clipped_samples, 0);
+    // silent throughout counts as leading silence
+    assert_eq!(
+        (report.leading_silence_ms, report.trailing_silence_ms),
+        (500, 0)
+    );
+
+    let strict = SilkEncodeOptions::new().reject_silence(true);
+    assert!(matches!(
+        encode_silk_with_options(&pcm, &strict),
+        Err(SilkError::SilentInput)
+    ));
+}
+
+#[test]
+fn input_report_counts_clipped_samples() {
+    // a full-scale sine driven 12 dB into the rails
+    let pcm = TestSignal::sine(440.0)
+        .duration(Duration::from_millis(500))
+        .samples_i16()
+        .into_iter()
+        .map(|sample| (sample as i32 * 4).clamp(-32767, 32767) as i16)
+        .collect::<Vec<_>>();
+    let bytes = pcm
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect::<Vec<_>>();
+    let report = input_report(&bytes);
+    assert!(report.peak_dbfs > -0.01, "{report:?}");
+    assert!(report.clipped_samples > pcm.len() / 2, "{report:?}");
+    assert!(!report.is_silent());
+}
+
+#[test]
+fn input_report_measures_speech_and_its_silent_edges() {
+    let mut pcm = vec![0u8; 200 * 48];
+    pcm.extend(speech_pcm(24000, 1000));
+    pcm.extend(vec![0u8; 100 * 48]);
+    let report = input_report(&pcm);
+    assert!(!report.is_silent());
+    assert_eq!(report.clipped_samples, 0);
+    assert!(
+        report.peak_dbfs < 0.0 && report.peak_dbfs > -20.0,
+        "{report:?}"
+    );
+    assert!(report.rms_dbfs < report.peak_dbfs, "{report:?}");
+    assert!(
+        (200..=205).contains(&report.leading_silence_ms),
+        "{report:?}"
+    );
+    assert!(
+        (100..=105).contains(&report.trailing_silence_ms),
+        "{report:?}"
+    );
+}
+
+#[test]
+fn stereo_input_is_mixed_down_before_framing() {
+    let mono = speech_pcm(24000, 1000);
+    let stereo = speech(24000, 1000).channels(2).pcm_bytes();
+    let options = SilkEncodeOptions::new();
+    let encoded = encode_silk_with_options(&stereo, &options.channels(2)).unwrap();
+    // identical channels mix down to the mono signal itself
+    assert_eq!(encoded, encode_silk_with_options(&mono, &options).unwrap());
+    let decoded = decode_silk(&encoded, 24000).unwrap();
+    assert_eq!(pcm_duration(&decoded, 24000), Duration::from_secs(1));
+
+    // read as mono the same bytes play twice as long
+    let misread = encode_silk_with_options(&stereo, &options).unwrap();
+    let misread = decode_silk(&misread, 24000).unwrap();
+    assert_eq!(pcm_duration(&misread, 24000), Duration::from_secs(2));
+
+    // content on one channel only stays audible at half the level
+    let silent_right = samples(&mono)
+        .into_iter()
+        .flat_map(|sample| [sample, 0])
+        .flat_map(i16::to_le_bytes)
+        .collect::<Vec<_>>();
+    let encoded = encode_silk_with_options(&silent_right, &options.channels(2)).unwrap();
+    let level = analyze_pcm(decode_silk(&encoded, 24000).unwrap(), 24000).rms_dbfs;
+    let expected = analyze_pcm(&mono, 24000).rms_dbfs - 6.0;
+    assert!((level - expected).abs() <= 2.0, "{level} vs {expected}");
+
+    assert!(matches!(
+        encode_silk_with_options(&stereo[..6], &options.channels(2)),
+        Err(SilkError::InvalidPcmLength(6))
+    ));
+    assert!(matches!(
+        encode_silk_with_options(&stereo, &options.channels(3)),
+        Err(SilkError::UnsupportedChannels(3))
+    ));
+}
+
+#[test]
+fn one_shot_encodes_are_capped_at_max_duration() {
+    // a QQ voice message limit, 8 kHz keeps the 65 s encode quick
+    let pcm = speech_pcm(8000, 65_000);
+    let limit = Duration::from_secs(60);
+    let options = SilkEncodeOptions::new()
+        .sample_rate(SampleRate::Hz8000)
+        .max_duration(limit);
+    let (encoded, stats) = encode_silk_with_stats(&pcm, &options).unwrap();
+    assert!(stats.truncated);
+    assert_eq!(stats.duration, limit);
+    assert_eq!(encoded, encode_silk_with_options(&pcm, &options).unwrap());
+    let decoded = decode_silk(&encoded, 8000).unwrap();
+    assert_eq!(pcm_duration(&decoded, 8000), limit);
+
+    let (_, stats) = encode_silk_with_stats(&pcm[..pcm.len() / 2], &options).unwrap();
+    assert!(!stats.truncated);
+
+    assert!(matches!(
+        encode_silk_with_options(&pcm, &options.strict_max_duration(true)),
+        Err(SilkError::InputTooLong { limit: l, actual })
+            if l == limit && actual == Duration::from_secs(65)
+    ));
+}
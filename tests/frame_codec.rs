@@ -0,0 +1,69 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use bytes::Bytes;
+use common::{packets, silk_stream};
+use futures::{SinkExt, StreamExt};
+use silk_codec::{SilkFrameCodec, SilkFrameError, SilkHeaderVariant, assemble_silk, decode_silk};
+use tokio::io::{AsyncWriteExt, duplex};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+#[tokio::test]
+async fn relays_a_stream_between_client_and_server() {
+    let original = silk_stream(24000, 1000);
+    // smaller than most packets, so the server reads them in pieces
+    let (client, server) = duplex(64);
+
+    let send = async {
+        let mut client = FramedWrite::new(client, SilkFrameCodec::new().header(true));
+        for packet in packets(&original) {
+            client.send(Bytes::from(packet)).await.unwrap();
+        }
+        // dropping the writer ends the stream for the server
+        client.close().await.unwrap();
+    };
+    let receive = async {
+        FramedRead::new(server, SilkFrameCodec::new().header(true))
+            .map(|frame| frame.unwrap())
+            .collect::<Vec<_>>()
+            .await
+    };
+    let ((), received) = tokio::join!(send, receive);
+
+    assert_eq!(received.len(), 50);
+    let reassembled = assemble_silk(&received, SilkHeaderVariant::Standard).unwrap();
+    assert_eq!(reassembled, original);
+    assert_eq!(
+        decode_silk(&reassembled, 24000).unwrap(),
+        decode_silk(&original, 24000).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn rejects_packets_the_decoder_would_refuse() {
+    let (mut client, server) = duplex(4096);
+    let mut server = FramedRead::new(server, SilkFrameCodec::new());
+
+    let mut oversized = 1025i16.to_le_bytes().to_vec();
+    oversized.resize(2 + 1025, 0);
+    client.write_all(&oversized).await.unwrap();
+    assert!(matches!(
+        server.next().await,
+        Some(Err(SilkFrameError::FrameTooLarge {
+            len: 1025,
+            max: 1024
+        }))
+    ));
+
+    let (writer, _reader) = duplex(4096);
+    let mut client = FramedWrite::new(writer, SilkFrameCodec::new());
+    assert!(matches!(
+        client.send(Bytes::from(vec![0; 1025])).await,
+        Err(SilkFrameError::FrameTooLarge {
+            len: 1025,
+            max: 1024
+        })
+    ));
+    client.send(Bytes::from(vec![0; 1024])).await.unwrap();
+}
@@ -0,0 +1,156 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{packets, samples, silk_stream, speech, speech_pcm};
+use silk_codec::{
+    SampleRate, SilkEncodeOptions, SilkError, TestSignal, decode_silk, encode_silk_to_size,
+    encode_silk_with_options, encode_silk_with_stats, measure_roundtrip_quality,
+};
+use std::time::Duration;
+
+const RATES: [u32; 6] = [8000, 12000, 16000, 24000, 44100, 48000];
+
+#[test]
+fn decodes_the_encoded_length_at_every_rate() {
+    for rate in RATES {
+        let encoded = silk_stream(rate, 1000);
+        let decoded = decode_silk(&encoded, rate as i32).unwrap();
+        assert_eq!(
+            decoded.len(),
+            speech(rate, 1000).pcm_bytes().len(),
+            "{rate} Hz"
+        );
+    }
+}
+
+#[test]
+fn speech_survives_the_round_trip() {
+    for rate in [8000, 16000, 24000] {
+        let pcm = speech(rate, 2000).samples_i16();
+        let options = SilkEncodeOptions::new().bit_rate(24000);
+        let report = measure_roundtrip_quality(&pcm, rate as i32, &options).unwrap();
+        assert!(report.segmental_snr_db > 3.0, "{rate} Hz: {report:?}");
+    }
+}
+
+fn snr_db(original: &[i16], decoded: &[i16]) -> f64 {
+    let (signal, noise) =
+        original
+            .iter()
+            .zip(decoded)
+            .fold((0.0, 0.0), |(signal, noise), (&a, &b)| {
+                let error = a as f64 - b as f64;
+                (signal + a as f64 * a as f64, noise + error * error)
+            });
+    10.0 * (signal / noise).log10()
+}
+
+#[test]
+fn a_sine_clears_the_snr_floor() {
+    let signal = TestSignal::sine(440.0)
+        .sample_rate(24000)
+        .duration(Duration::from_secs(2));
+    let pcm = signal.samples_i16();
+    let options = SilkEncodeOptions::new().bit_rate(24000);
+    let report = measure_roundtrip_quality(&pcm, 24000, &options).unwrap();
+    assert!(report.snr_db > 20.0, "{report:?}");
+    assert!(report.segmental_snr_db > 20.0, "{report:?}");
+    assert!(report.delay_samples <= 24000 * 40 / 1000, "{report:?}");
+
+    // the report compares against the decoded signal shifted by the delay it found,
+    // without that shift the codec delay alone drags the SNR down
+    let encoded = encode_silk_with_options(signal.pcm_bytes(), &options).unwrap();
+    let decoded = samples(&decode_silk(&encoded, 24000).unwrap());
+    let aligned = &decoded[report.delay_samples..];
+    let len = aligned.len();
+    assert!((snr_db(&pcm[..len], aligned) - report.snr_db).abs() < 1e-9);
+    assert!(snr_db(&pcm, &decoded) < report.snr_db - 10.0, "{report:?}");
+}
+
+#[test]
+fn dtx_shrinks_silence_and_keeps_the_duration() {
+    let speech = speech_pcm(24000, 400);
+    let pcm = [speech.clone(), vec![0; 24000 * 2 * 2], speech].concat();
+    let plain = encode_silk_with_options(&pcm, &SilkEncodeOptions::new()).unwrap();
+    let dtx = encode_silk_with_options(&pcm, &SilkEncodeOptions::new().dtx(true)).unwrap();
+    assert!(dtx.len() < plain.len(), "{} vs {}", dtx.len(), plain.len());
+
+    // the silent stretch goes out as empty packets, with a short noise update now and
+    // then, and every one of them still decodes to a frame
+    let packets = packets(&dtx);
+    assert_eq!(packets.len(), 140);
+    let empty = packets.iter().filter(|packet| packet.is_empty()).count();
+    assert!(empty >= 80, "{empty} empty packets");
+    assert_eq!(decode_silk(&dtx, 24000).unwrap().len(), pcm.len());
+}
+
+#[test]
+fn every_complexity_survives_the_round_trip() {
+    let pcm = speech(24000, 1000).samples_i16();
+    let mut streams = vec![];
+    for complexity in 0..=2 {
+        let options = SilkEncodeOptions::new().complexity(complexity);
+        let report = measure_roundtrip_quality(&pcm, 24000, &options).unwrap();
+        assert!(report.segmental_snr_db > 3.0, "{complexity}: {report:?}");
+
+        let encoded = encode_silk_with_options(speech_pcm(24000, 1000), &options).unwrap();
+        assert_eq!(decode_silk(&encoded, 24000).unwrap().len(), pcm.len() * 2);
+        streams.push(encoded);
+    }
+    assert_ne!(streams[0], streams[2]);
+    // 2 stays the default
+    assert_eq!(streams[2], silk_stream(24000, 1000));
+}
+
+#[test]
+fn every_packet_duration_round_trips() {
+    let pcm = speech_pcm(24000, 1200);
+    for duration_ms in [20, 40, 60, 80, 100] {
+        let options = SilkEncodeOptions::new().packet_duration_ms(duration_ms);
+        let encoded = encode_silk_with_options(&pcm, &options).unwrap();
+        assert_eq!(packets(&encoded).len(), 1200 / duration_ms as usize);
+        let decoded = decode_silk(&encoded, 24000).unwrap();
+        assert_eq!(decoded.len(), pcm.len(), "{duration_ms} ms");
+    }
+}
+
+#[test]
+fn sample_rates_are_checked_up_front() {
+    for rate in [8000, 12000, 16000, 24000, 32000, 44100, 48000] {
+        assert_eq!(SampleRate::try_from(rate).unwrap().hz(), rate);
+    }
+    let stream = silk_stream(24000, 100);
+    for rate in [0, -8000, 11025, 96000] {
+        assert!(matches!(
+            SampleRate::try_from(rate),
+            Err(SilkError::UnsupportedSampleRate(given)) if given == rate
+        ));
+        assert!(matches!(
+            encode_silk_to_size(speech_pcm(24000, 100), rate, 10_000, false),
+            Err(SilkError::UnsupportedSampleRate(given)) if given == rate
+        ));
+        assert!(matches!(
+            decode_silk(&stream, rate),
+            Err(SilkError::UnsupportedSampleRate(given)) if given == rate
+        ));
+    }
+}
+
+#[test]
+fn final_partial_frame_is_padded_by_default() {
+    // 330 ms is 16 packets of 20 ms and 10 ms over
+    let pcm = speech_pcm(24000, 330);
+    let padded = encode_silk_with_options(&pcm, &SilkEncodeOptions::new()).unwrap();
+    let decoded = decode_silk(&padded, 24000).unwrap();
+    assert!(decoded.len() >= pcm.len());
+    assert_eq!(decoded.len(), speech_pcm(24000, 340).len());
+
+    let options = SilkEncodeOptions::new().pad_final_frame(false);
+    let (dropped, stats) = encode_silk_with_stats(&pcm, &options).unwrap();
+    assert_eq!((stats.frames, stats.dropped_samples), (16, 240));
+    assert_eq!(
+        decode_silk(&dropped, 24000).unwrap().len(),
+        speech_pcm(24000, 320).len()
+    );
+}
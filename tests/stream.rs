@@ -0,0 +1,422 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{encoded_speech, packets, samples, silk_stream, speech, speech_pcm};
+use silk_codec::{
+    NormalizeMode, SilkEncodeOptions, SilkEncoder, SilkError, SilkHeaderVariant, SilkReader,
+    SilkWriter, assemble_silk, decode_silk, decode_silk_to_writer, encode_silk_from_iter,
+    encode_silk_samples_with_options, encode_silk_stream, encode_silk_with_callback,
+    encode_silk_with_options,
+};
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Feeds `pcm` to a [`SilkEncoder`] in chunks of `chunk_len` bytes.
+fn encode_chunked(pcm: &[u8], chunk_len: usize, options: &SilkEncodeOptions) -> Vec<u8> {
+    let mut encoder = SilkEncoder::new(options).unwrap();
+    let mut output = vec![];
+    for chunk in pcm.chunks(chunk_len) {
+        output.extend(encoder.feed(chunk).unwrap());
+    }
+    output.extend(encoder.finish().unwrap());
+    output
+}
+
+#[test]
+fn chunk_sizes_do_not_change_the_stream() {
+    let mono = speech_pcm(24000, 510);
+    let stereo = speech(24000, 510).channels(2).pcm_bytes();
+    let options = SilkEncodeOptions::new();
+    for (pcm, options) in [
+        (&mono, options),
+        (&mono, options.tencent(true).prime_ms(20)),
+        (&mono, options.packet_duration_ms(60).pad_final_frame(false)),
+        (&stereo, options.channels(2)),
+    ] {
+        let expected = encode_silk_with_options(pcm, &options).unwrap();
+        // odd sizes split samples, 961 splits a frame of 480 samples
+        for chunk_len in [1, 7, 960, 961, pcm.len()] {
+            assert_eq!(
+                encode_chunked(pcm, chunk_len, &options),
+                expected,
+                "{options:?} in chunks of {chunk_len}"
+            );
+        }
+    }
+}
+
+#[test]
+fn iterator_chunking_does_not_change_the_stream() {
+    let mono = samples(&speech_pcm(24000, 510));
+    let stereo = samples(&speech(24000, 510).channels(2).pcm_bytes());
+    let options = SilkEncodeOptions::new();
+    for (pcm, options) in [(&mono, options), (&stereo, options.channels(2))] {
+        let expected = encode_silk_samples_with_options(pcm, &options).unwrap();
+        // odd sizes split stereo frames, 481 splits a packet of 480 samples
+        for chunk_len in [1, 7, 480, 481, pcm.len()] {
+            assert_eq!(
+                encode_silk_from_iter(pcm.chunks(chunk_len), &options).unwrap(),
+                expected,
+                "{options:?} in chunks of {chunk_len}"
+            );
+        }
+
+        let mut uneven = vec![];
+        let mut rest = &pcm[..];
+        for &len in [3, 1000, 0, 1, 4801].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, tail) = rest.split_at(len.min(rest.len()));
+            uneven.push(chunk.to_vec());
+            rest = tail;
+        }
+        assert_eq!(encode_silk_from_iter(uneven, &options).unwrap(), expected);
+    }
+}
+
+#[test]
+fn packet_callbacks_rebuild_the_container() {
+    let pcm = speech_pcm(24000, 510);
+    for variant in [SilkHeaderVariant::Standard, SilkHeaderVariant::Tencent] {
+        let options = SilkEncodeOptions::new().header(variant).prime_ms(40);
+        let expected = encode_silk_with_options(&pcm, &options).unwrap();
+
+        let mut packets = vec![];
+        encode_silk_with_callback(&pcm, &options, |index, packet| {
+            assert_eq!(index, packets.len());
+            packets.push(packet.to_vec());
+        })
+        .unwrap();
+        assert_eq!(assemble_silk(&packets, variant).unwrap(), expected);
+
+        // the streaming encoder hands out the same packets, whatever the chunking
+        let mut streamed = vec![];
+        let mut on_packet = |index: usize, packet: &[u8]| {
+            assert_eq!(index, streamed.len());
+            streamed.push(packet.to_vec());
+        };
+        let mut encoder = SilkEncoder::new(&options).unwrap();
+        for chunk in pcm.chunks(1001) {
+            encoder.feed_with(chunk, &mut on_packet).unwrap();
+        }
+        encoder.finish_with(&mut on_packet).unwrap();
+        assert_eq!(streamed, packets);
+    }
+}
+
+#[test]
+fn header_goes_out_with_the_first_output() {
+    let pcm = speech_pcm(24000, 20);
+    for (tencent, header) in [(false, &b"#!SILK_V3"[..]), (true, b"\x02#!SILK_V3")] {
+        let options = SilkEncodeOptions::new().tencent(tencent);
+        let mut encoder = SilkEncoder::new(&options).unwrap();
+        // less than a frame, nothing to encode yet
+        assert_eq!(encoder.feed(&pcm[..200]).unwrap(), header);
+        assert!(encoder.feed(&pcm[200..400]).unwrap().is_empty());
+        let tail = encoder.finish().unwrap();
+
+        // the padded frame, terminated unless the stream is Tencent
+        let expected = encode_silk_with_options(&pcm[..400], &options).unwrap();
+        assert_eq!(tail, expected[header.len()..]);
+        assert_eq!(tail.ends_with(&[0xff, 0xff]), !tencent);
+        let len = i16::from_le_bytes([tail[0], tail[1]]) as usize;
+        assert_eq!(tail.len(), 2 + len + if tencent { 0 } else { 2 });
+    }
+}
+
+#[test]
+fn status_follows_set_bit_rate() {
+    let pcm = speech_pcm(24000, 100);
+    let frame = 960;
+    let mut encoder = SilkEncoder::new(&SilkEncodeOptions::new().bit_rate(20000)).unwrap();
+    encoder.feed(&pcm[..frame]).unwrap();
+    assert_eq!(encoder.status().unwrap().bit_rate, 20000);
+
+    // queried from the SDK, so the new rate shows after the next frame
+    encoder.set_bit_rate(32000).unwrap();
+    assert_eq!(encoder.status().unwrap().bit_rate, 20000);
+    encoder.feed(&pcm[frame..2 * frame]).unwrap();
+    assert_eq!(encoder.status().unwrap().bit_rate, 32000);
+
+    for bit_rate in [4999, 100001, 200000] {
+        assert!(matches!(
+            encoder.set_bit_rate(bit_rate),
+            Err(SilkError::InvalidBitRate { given, min: 5000, max: 100000 }) if given == bit_rate
+        ));
+    }
+    encoder.feed(&pcm[2 * frame..3 * frame]).unwrap();
+    assert_eq!(encoder.status().unwrap().bit_rate, 32000);
+    assert!(!encoder.finish().unwrap().is_empty());
+}
+
+#[test]
+fn lowering_the_bit_rate_mid_stream_shrinks_the_packets() {
+    let pcm = speech_pcm(24000, 2000);
+    let half = pcm.len() / 2;
+    let mut encoder = SilkEncoder::new(&SilkEncodeOptions::new().bit_rate(40000)).unwrap();
+    let mut output = encoder.feed(&pcm[..half]).unwrap();
+    encoder.set_bit_rate(12000).unwrap();
+    output.extend(encoder.feed(&pcm[half..]).unwrap());
+    output.extend(encoder.finish().unwrap());
+
+    let packets = packets(&output);
+    assert_eq!(packets.len(), 100);
+    let mean_len = |packets: &[Vec<u8>]| {
+        packets.iter().map(Vec::len).sum::<usize>() as f64 / packets.len() as f64
+    };
+    let (first, second) = packets.split_at(50);
+    // 12 kbps is less than a third of 40 kbps, leave room for the rate control
+    assert!(
+        mean_len(second) * 2.0 < mean_len(first),
+        "{} vs {}",
+        mean_len(first),
+        mean_len(second)
+    );
+    assert_eq!(decode_silk(&output, 24000).unwrap().len(), pcm.len());
+}
+
+#[test]
+fn finish_checks_the_whole_input() {
+    let options = SilkEncodeOptions::new();
+    let encoder = SilkEncoder::new(&options).unwrap();
+    assert!(matches!(
+        encoder.finish(),
+        Err(SilkError::InvalidPcmLength(0))
+    ));
+
+    let mut encoder = SilkEncoder::new(&options).unwrap();
+    encoder.feed(&[0; 1001]).unwrap();
+    assert!(matches!(
+        encoder.finish(),
+        Err(SilkError::InvalidPcmLength(1001))
+    ));
+
+    // silence is only known to be silence at the end
+    let mut encoder = SilkEncoder::new(&options.reject_silence(true)).unwrap();
+    assert!(!encoder.feed(&[0; 4800]).unwrap().is_empty());
+    assert!(matches!(encoder.finish(), Err(SilkError::SilentInput)));
+}
+
+#[test]
+fn max_duration_drops_or_fails_the_excess() {
+    let pcm = speech_pcm(24000, 500);
+    let limit = Duration::from_millis(200);
+    let options = SilkEncodeOptions::new().max_duration(limit);
+    assert_eq!(
+        encode_chunked(&pcm, 960, &options),
+        encode_silk_with_options(&pcm[..9600], &SilkEncodeOptions::new()).unwrap()
+    );
+
+    let mut encoder = SilkEncoder::new(&options.strict_max_duration(true)).unwrap();
+    encoder.feed(&pcm[..9600]).unwrap();
+    assert!(matches!(
+        encoder.feed(&pcm[9600..9602]),
+        Err(SilkError::InputTooLong { limit: given, .. }) if given == limit
+    ));
+}
+
+#[test]
+fn writer_encodes_whatever_it_is_written() {
+    let pcm = speech_pcm(24000, 510);
+    let options = SilkEncodeOptions::new().tencent(true);
+    let mut writer = SilkWriter::new(vec![], &options).unwrap();
+    for chunk in pcm.chunks(333) {
+        writer.write_all(chunk).unwrap();
+    }
+    writer.flush().unwrap();
+    let encoded = writer.finish().unwrap();
+    assert_eq!(encoded, encode_silk_with_options(&pcm, &options).unwrap());
+
+    // dropping the writer loses the tail frame, the full frames are already out
+    let mut output = vec![];
+    let mut writer = SilkWriter::new(&mut output, &options).unwrap();
+    writer.write_all(&pcm).unwrap();
+    drop(writer);
+    assert_eq!(
+        output.len(),
+        encoded.len() - packets(&encoded).last().unwrap().len() - 2
+    );
+}
+
+/// Serves `pcm` in short reads and counts what it served in `read`.
+struct CountingReader<'a> {
+    pcm: &'a [u8],
+    read: Rc<Cell<usize>>,
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // short reads that split samples and frames
+        let len = buf.len().min(self.pcm.len()).min(1001);
+        buf[..len].copy_from_slice(&self.pcm[..len]);
+        self.pcm = &self.pcm[len..];
+        self.read.set(self.read.get() + len);
+        Ok(len)
+    }
+}
+
+/// Collects the output and, for every write, how much input had been read by then.
+struct LaggingWriter {
+    output: Vec<u8>,
+    read: Rc<Cell<usize>>,
+    read_at_writes: Vec<usize>,
+}
+
+impl Write for LaggingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        self.read_at_writes.push(self.read.get());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn stream_encodes_megabytes_with_bounded_buffering() {
+    // 70 s at 24 kHz, a bit over 3 MB of PCM
+    let pcm = speech_pcm(24000, 70_010);
+    assert!(pcm.len() > 3 << 20);
+    let options = SilkEncodeOptions::new();
+    let read = Rc::new(Cell::new(0));
+    let reader = CountingReader {
+        pcm: &pcm,
+        read: read.clone(),
+    };
+    let mut writer = LaggingWriter {
+        output: vec![],
+        read,
+        read_at_writes: vec![],
+    };
+    let consumed = encode_silk_stream(reader, &mut writer, &options).unwrap();
+    assert_eq!(consumed, pcm.len() as u64);
+    assert_eq!(
+        writer.output,
+        encode_silk_with_options(&pcm, &options).unwrap()
+    );
+
+    // packets go out as the input comes in, the encoder never holds more than a
+    // read buffer and a frame however long the input is
+    let mut lags = writer
+        .read_at_writes
+        .windows(2)
+        .map(|pair| pair[1] - pair[0]);
+    assert!(lags.all(|lag| lag <= 64 << 10));
+    assert!(writer.read_at_writes[0] <= 64 << 10);
+}
+
+#[test]
+fn whole_input_options_are_refused() {
+    let options = SilkEncodeOptions::new();
+    assert!(matches!(
+        SilkEncoder::new(&options.fit_to_size(10000)),
+        Err(SilkError::UnsupportedOption("fit_to_size"))
+    ));
+    assert!(matches!(
+        SilkEncoder::new(&options.normalize(NormalizeMode::Peak(-1.0))),
+        Err(SilkError::UnsupportedOption("normalize"))
+    ));
+    assert!(matches!(
+        SilkEncoder::new(&options.channels(3)),
+        Err(SilkError::UnsupportedChannels(3))
+    ));
+}
+
+/// Accepts `capacity` bytes, then fails every write.
+struct FullWriter {
+    capacity: usize,
+}
+
+impl Write for FullWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > self.capacity {
+            return Err(io::Error::new(io::ErrorKind::StorageFull, "full"));
+        }
+        self.capacity -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn decode_to_writer_matches_decode_silk() {
+    for encoded in [
+        silk_stream(16000, 700),
+        encoded_speech(24000, 500, &SilkEncodeOptions::new().packet_duration_ms(60)),
+    ] {
+        let expected = decode_silk(&encoded, 24000).unwrap();
+        let mut output = vec![];
+        let written = decode_silk_to_writer(&encoded, 24000, &mut output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(written, expected.len() as u64);
+    }
+
+    // the writer's error comes back wrapped, a bad stream as the decoder's error
+    let encoded = silk_stream(24000, 500);
+    let err = decode_silk_to_writer(&encoded, 24000, FullWriter { capacity: 5000 }).unwrap_err();
+    assert!(matches!(err, SilkError::Io(err) if err.kind() == io::ErrorKind::StorageFull));
+    let err = decode_silk_to_writer(b"not silk", 24000, io::sink()).unwrap_err();
+    assert!(matches!(err, SilkError::Invalid));
+}
+
+/// Serves at most one byte per read, like a slow socket.
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(1);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn reader_yields_the_decoded_pcm() {
+    let standard = silk_stream(24000, 500);
+    let expected = decode_silk(&standard, 24000).unwrap();
+    let unterminated = standard[..standard.len() - 2].to_vec();
+    let mut tencent = vec![0x02];
+    tencent.extend_from_slice(&standard);
+    for src in [&standard, &unterminated, &tencent] {
+        let mut reader = SilkReader::new(&src[..], 24000).unwrap();
+        let mut pcm = vec![];
+        io::copy(&mut reader, &mut pcm).unwrap();
+        assert_eq!(pcm, expected);
+
+        // reads smaller than a frame, from a source that is slower still
+        let mut reader = SilkReader::new(Trickle(src), 24000).unwrap();
+        let mut pcm = vec![];
+        let mut buf = [0; 3];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                read => pcm.extend_from_slice(&buf[..read]),
+            }
+        }
+        assert_eq!(pcm, expected);
+    }
+
+    // the reader stops at the terminator and leaves the rest of the source alone
+    let mut trailed = standard.clone();
+    trailed.extend_from_slice(b"next");
+    let mut reader = SilkReader::new(&trailed[..], 24000).unwrap();
+    io::copy(&mut reader, &mut io::sink()).unwrap();
+    assert_eq!(reader.into_inner(), b"next");
+
+    let mut reader = SilkReader::new(&b"#!SILK_V2"[..], 24000).unwrap();
+    let err = reader.read(&mut [0; 16]).unwrap_err();
+    assert!(matches!(
+        err.get_ref().and_then(|err| err.downcast_ref()),
+        Some(SilkError::Invalid)
+    ));
+}
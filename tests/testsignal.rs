@@ -0,0 +1,80 @@
+#![cfg(feature = "std")]
+
+use silk_codec::{TestSignal, Waveform, parse_wav};
+use std::io::Cursor;
+use std::time::Duration;
+
+#[test]
+fn noise_is_reproducible_per_seed() {
+    let signal = TestSignal::pink_noise().duration(Duration::from_millis(100));
+    assert_eq!(signal.samples_i16(), signal.samples_i16());
+    assert_eq!(signal.seed(7).samples_i16(), signal.seed(7).samples_i16());
+    assert_ne!(signal.seed(7).samples_i16(), signal.seed(8).samples_i16());
+}
+
+#[test]
+fn length_follows_rate_duration_and_channels() {
+    let signal = TestSignal::sine(440.0)
+        .sample_rate(16000)
+        .channels(2)
+        .duration(Duration::from_millis(250));
+    assert_eq!(signal.frames(), 4000);
+    assert_eq!(signal.samples_f32().len(), 8000);
+    assert_eq!(signal.pcm_bytes().len(), 16000);
+}
+
+#[test]
+fn tones_are_identical_on_every_channel() {
+    let samples = TestSignal::sweep(100.0, 2000.0).channels(2).samples_i16();
+    assert!(samples.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+}
+
+#[test]
+fn amplitude_bounds_every_waveform() {
+    let waveforms = [
+        Waveform::Sine { frequency: 300.0 },
+        Waveform::Sweep {
+            start: 50.0,
+            end: 5000.0,
+        },
+        Waveform::WhiteNoise,
+        Waveform::PinkNoise,
+        Waveform::SpeechLike { pitch: 150.0 },
+    ];
+    for waveform in waveforms {
+        let samples = TestSignal::new(waveform).amplitude(0.25).samples_f32();
+        let peak = samples
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak <= 0.25, "{waveform:?} peaks at {peak}");
+        assert!(peak > 0.05, "{waveform:?} peaks at {peak}");
+    }
+    assert!(
+        TestSignal::silence()
+            .samples_i16()
+            .iter()
+            .all(|&sample| sample == 0)
+    );
+}
+
+#[test]
+fn sine_has_its_frequency() {
+    let samples = TestSignal::sine(500.0).sample_rate(8000).samples_f32();
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count();
+    assert!((499..=501).contains(&crossings), "{crossings}");
+}
+
+#[test]
+fn writes_a_readable_wav() {
+    let signal = TestSignal::speech_like(200.0)
+        .sample_rate(12000)
+        .channels(2)
+        .duration(Duration::from_millis(40));
+    let wav = signal.write_wav(Cursor::new(vec![])).unwrap().into_inner();
+    let parsed = parse_wav(&wav).unwrap();
+    assert_eq!((parsed.sample_rate, parsed.channels), (12000, 2));
+    assert_eq!(parsed.data, signal.pcm_bytes());
+}
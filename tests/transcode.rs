@@ -0,0 +1,214 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{packets, pcm_duration, samples, silk_stream, speech, speech_pcm, temp_path};
+use silk_codec::{
+    SampleRate, SilkEncodeOptions, SilkError, SilkFrameIter, SilkHeaderVariant, StereoMergeOptions,
+    TranscodeError, TranscodeOptions, analyze_pcm, decode_silk, encode_silk_with_options,
+    merge_silk_to_stereo_wav_with_options, pad_silk, parse_wav, resample_silk, silk_duration,
+    transcode, transcode_silk,
+};
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn encodes_wav_files_to_silk() {
+    for (sample_rate, channels) in [(16000, 1), (24000, 2)] {
+        let wav = temp_path(&format!("{sample_rate}-{channels}.wav"));
+        let silk = temp_path(&format!("{sample_rate}-{channels}.silk"));
+        let signal = speech(sample_rate, 1000).channels(channels);
+        signal.write_wav(fs::File::create(&wav).unwrap()).unwrap();
+
+        transcode(&wav, &silk, &TranscodeOptions::new()).unwrap();
+        let decoded = decode_silk(fs::read(&silk).unwrap(), 24000).unwrap();
+        assert_eq!(pcm_duration(&decoded, 24000), Duration::from_secs(1));
+        fs::remove_file(wav).unwrap();
+        fs::remove_file(silk).unwrap();
+    }
+}
+
+#[test]
+fn merge_offsets_insert_silence() {
+    let left = silk_stream(16000, 500);
+    let right = silk_stream(16000, 500);
+    let options = StereoMergeOptions::new()
+        .sample_rate(SampleRate::Hz16000)
+        .left_offset(Duration::from_millis(100));
+    let mut wav = vec![];
+    merge_silk_to_stereo_wav_with_options(&left, &right, &options, &mut wav).unwrap();
+
+    let wav = parse_wav(&wav).unwrap();
+    assert_eq!((wav.sample_rate, wav.channels), (16000, 2));
+    let frames = wav.data.len() / 4;
+    assert_eq!(frames, 16000 * 600 / 1000);
+    let left = samples(&wav.data)
+        .into_iter()
+        .step_by(2)
+        .collect::<Vec<_>>();
+    assert!(left[..1600].iter().all(|&sample| sample == 0));
+    assert!(left[1600..].iter().any(|&sample| sample != 0));
+}
+
+#[test]
+fn absurd_merge_offsets_fail() {
+    let track = silk_stream(16000, 100);
+    for offset in [Duration::MAX, Duration::from_secs(3600 * 24 * 365)] {
+        let options = StereoMergeOptions::new()
+            .sample_rate(SampleRate::Hz16000)
+            .right_offset(offset);
+        assert!(matches!(
+            merge_silk_to_stereo_wav_with_options(&track, &track, &options, &mut vec![]),
+            Err(TranscodeError::Silk(SilkError::OutputLimitExceeded { .. }))
+        ));
+    }
+}
+
+#[test]
+fn transcode_silk_keeps_the_variant_and_duration() {
+    let pcm = speech_pcm(24000, 1010);
+    for variant in [
+        SilkHeaderVariant::Standard,
+        SilkHeaderVariant::StandardUnterminated,
+        SilkHeaderVariant::Tencent,
+        SilkHeaderVariant::TencentTerminated,
+    ] {
+        let options = SilkEncodeOptions::new().bit_rate(40000).header(variant);
+        let src = encode_silk_with_options(&pcm, &options).unwrap();
+        let output = transcode_silk(&src, 24000, 12000, None).unwrap();
+        assert_eq!(SilkHeaderVariant::detect(&output).unwrap(), variant);
+        assert!(output.len() < src.len(), "{variant:?}");
+        assert_eq!(
+            silk_duration(&output, 24000).unwrap(),
+            silk_duration(&src, 24000).unwrap()
+        );
+    }
+
+    // longer source packets come out as 20 ms ones, within one frame of the input
+    let options = SilkEncodeOptions::new().packet_duration_ms(100);
+    let src = encode_silk_with_options(&pcm, &options).unwrap();
+    let output = transcode_silk(&src, 24000, 12000, Some(true)).unwrap();
+    assert_eq!(
+        SilkHeaderVariant::detect(&output).unwrap(),
+        SilkHeaderVariant::TencentTerminated
+    );
+    let decoded = decode_silk(&output, 24000).unwrap();
+    assert_eq!(decoded.len(), speech_pcm(24000, 1100).len());
+}
+
+/// Internal rate of the first packet that carries audio.
+fn internal_rate(src: &[u8]) -> i32 {
+    SilkFrameIter::new(src)
+        .map(Result::unwrap)
+        .find_map(|frame| frame.internal_sample_rate())
+        .unwrap()
+}
+
+#[test]
+fn resample_silk_changes_the_stream_rate() {
+    for (src_rate, dst_rate) in [(24000, 16000), (8000, 24000), (16000, 8000), (48000, 12000)] {
+        let src = silk_stream(src_rate, 500);
+        let output = resample_silk(&src, src_rate as i32, dst_rate as i32, 20000, None).unwrap();
+        let pair = format!("{src_rate} -> {dst_rate}");
+        // the stream itself moved to the new rate, not just the decoder output
+        assert_eq!(internal_rate(&src), src_rate.min(24000) as i32, "{pair}");
+        assert_eq!(internal_rate(&output), dst_rate.min(24000) as i32, "{pair}");
+        let duration = silk_duration(&output, dst_rate as i32).unwrap();
+        assert_eq!(duration, Duration::from_millis(500), "{pair}");
+
+        // the speech made it across, not just its length
+        let decoded = decode_silk(&output, dst_rate as i32).unwrap();
+        assert_eq!(pcm_duration(&decoded, dst_rate), Duration::from_millis(500));
+        let report = analyze_pcm(&decoded, dst_rate as i32);
+        assert!(report.rms_dbfs > -30.0, "{pair}: {report:?}");
+    }
+    assert!(matches!(
+        resample_silk(silk_stream(24000, 100), 24000, 22050, 20000, None),
+        Err(SilkError::UnsupportedSampleRate(22050))
+    ));
+}
+
+fn snr_db(original: &[i16], decoded: &[i16]) -> f64 {
+    let (signal, noise) =
+        original
+            .iter()
+            .zip(decoded)
+            .fold((0.0, 0.0), |(signal, noise), (&a, &b)| {
+                let error = a as f64 - b as f64;
+                (signal + a as f64 * a as f64, noise + error * error)
+            });
+    10.0 * (signal / noise).log10()
+}
+
+#[test]
+fn pad_silk_splices_silence_around_the_original_packets() {
+    let pcm = speech_pcm(24000, 500);
+    for variant in [SilkHeaderVariant::Standard, SilkHeaderVariant::Tencent] {
+        let src =
+            encode_silk_with_options(&pcm, &SilkEncodeOptions::new().header(variant)).unwrap();
+        let original = samples(&decode_silk(&src, 24000).unwrap());
+        // 30 ms rounds up to two packets
+        for (leading_ms, trailing_ms, leading_packets) in [(0, 50, 0), (30, 0, 2), (100, 60, 5)] {
+            let (leading, trailing) = (
+                Duration::from_millis(leading_ms),
+                Duration::from_millis(trailing_ms),
+            );
+            let padded = pad_silk(&src, leading, trailing, 24000, 20000).unwrap();
+            let case = format!("{variant:?} +{leading_ms}/{trailing_ms} ms");
+            assert_eq!(
+                SilkHeaderVariant::detect(&padded).unwrap(),
+                variant,
+                "{case}"
+            );
+
+            // the original packets are there byte for byte, between the silent ones
+            let padded_packets = packets(&padded);
+            let src_packets = packets(&src);
+            let trailing_packets = (trailing_ms as usize).div_ceil(20);
+            assert_eq!(
+                padded_packets.len(),
+                leading_packets + src_packets.len() + trailing_packets,
+                "{case}"
+            );
+            assert_eq!(
+                padded_packets[leading_packets..][..src_packets.len()],
+                src_packets,
+                "{case}"
+            );
+            let header_len = usize::from(variant == SilkHeaderVariant::Tencent) + 9;
+            let terminator_len = usize::from(variant.terminated()) * 2;
+            let body = &src[header_len..src.len() - terminator_len];
+            let body_start = header_len
+                + padded_packets[..leading_packets]
+                    .iter()
+                    .map(|packet| packet.len() + 2)
+                    .sum::<usize>();
+            assert_eq!(&padded[body_start..][..body.len()], body, "{case}");
+            assert_eq!(
+                padded.ends_with(&[0xff, 0xff]),
+                variant.terminated(),
+                "{case}"
+            );
+
+            let duration =
+                Duration::from_millis(500 + 20 * (leading_packets + trailing_packets) as u64);
+            assert_eq!(silk_duration(&padded, 24000).unwrap(), duration, "{case}");
+            let decoded = samples(&decode_silk(&padded, 24000).unwrap());
+            assert_eq!(decoded.len(), duration.as_millis() as usize * 24, "{case}");
+
+            // silence before the original leaves the decoder state a little different
+            // from a cold start, silence after it changes nothing
+            let middle = &decoded[leading_packets * 480..][..original.len()];
+            if leading_packets == 0 {
+                assert_eq!(middle, original, "{case}");
+            } else {
+                assert!(snr_db(&original, middle) > 30.0, "{case}");
+            }
+            let lead = &decoded[..leading_packets * 480];
+            assert!(
+                lead.iter().all(|sample| sample.unsigned_abs() < 64),
+                "{case}"
+            );
+        }
+    }
+}
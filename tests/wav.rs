@@ -0,0 +1,174 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::{silk_stream, speech_pcm, temp_path};
+use silk_codec::{
+    SampleRate, SilkDecodeOptions, SilkError, WavError, decode_silk, decode_silk_to_wav,
+    decode_silk_to_wav_with_options, decode_silk_with_options, encode_wav_to_silk, parse_wav,
+};
+use std::fs;
+
+const PCM: u16 = 1;
+const FLOAT: u16 = 3;
+const EXTENSIBLE: u16 = 0xfffe;
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn fmt_body(format_tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+    let block_align = channels * bits / 8;
+    let mut body = vec![];
+    body.extend_from_slice(&format_tag.to_le_bytes());
+    body.extend_from_slice(&channels.to_le_bytes());
+    body.extend_from_slice(&sample_rate.to_le_bytes());
+    body.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    body.extend_from_slice(&block_align.to_le_bytes());
+    body.extend_from_slice(&bits.to_le_bytes());
+    body
+}
+
+/// WAVE_FORMAT_EXTENSIBLE `fmt ` chunk whose sub format GUID starts with `sub_format`.
+fn extensible_fmt(sub_format: u16, channels: u16, sample_rate: u32) -> Vec<u8> {
+    let mut body = fmt_body(EXTENSIBLE, channels, sample_rate, 16);
+    body.extend_from_slice(&22u16.to_le_bytes());
+    body.extend_from_slice(&16u16.to_le_bytes());
+    body.extend_from_slice(&0x4u32.to_le_bytes());
+    body.extend_from_slice(&sub_format.to_le_bytes());
+    body.extend_from_slice(b"\x00\x00\x00\x00\x10\x00\x80\x00\x00\xaa\x00\x38\x9b\x71");
+    chunk(b"fmt ", &body)
+}
+
+fn fmt(format_tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+    chunk(b"fmt ", &fmt_body(format_tag, channels, sample_rate, bits))
+}
+
+fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = chunks.concat();
+    let mut wav = b"RIFF".to_vec();
+    wav.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(&body);
+    wav
+}
+
+#[test]
+fn skips_list_info_chunks() {
+    let pcm = speech_pcm(16000, 100);
+    // an odd-length entry makes the LIST chunk padded
+    let info = [b"INFO".as_slice(), &chunk(b"INAM", b"hello")].concat();
+    let wav = riff(&[
+        fmt(PCM, 1, 16000, 16),
+        chunk(b"LIST", &info),
+        chunk(b"data", &pcm),
+    ]);
+    let parsed = parse_wav(&wav).unwrap();
+    assert_eq!((parsed.sample_rate, parsed.channels), (16000, 1));
+    assert_eq!(parsed.data, pcm);
+}
+
+#[test]
+fn reads_wave_format_extensible() {
+    let pcm = speech_pcm(24000, 100);
+    let wav = riff(&[extensible_fmt(PCM, 1, 24000), chunk(b"data", &pcm)]);
+    assert_eq!(parse_wav(&wav).unwrap().data, pcm);
+
+    let wav = riff(&[extensible_fmt(FLOAT, 1, 24000), chunk(b"data", &pcm)]);
+    assert!(matches!(
+        parse_wav(&wav),
+        Err(WavError::UnsupportedFormat(FLOAT))
+    ));
+}
+
+#[test]
+fn rejects_other_sample_formats() {
+    let wav = riff(&[fmt(PCM, 1, 24000, 24), chunk(b"data", &[0; 6])]);
+    assert!(matches!(
+        parse_wav(&wav),
+        Err(WavError::UnsupportedBitsPerSample(24))
+    ));
+    let wav = riff(&[chunk(b"data", &[0; 4])]);
+    assert!(matches!(
+        parse_wav(&wav),
+        Err(WavError::MissingChunk("fmt "))
+    ));
+}
+
+#[test]
+fn reports_the_real_channel_count() {
+    for channels in [3, 300] {
+        let path = temp_path(&format!("{channels}-channels.wav"));
+        let data = vec![0; channels as usize * 2 * 480];
+        fs::write(
+            &path,
+            riff(&[fmt(PCM, channels, 24000, 16), chunk(b"data", &data)]),
+        )
+        .unwrap();
+        let result = encode_wav_to_silk(&path, 24000, false);
+        fs::remove_file(path).unwrap();
+        assert!(
+            matches!(result, Err(WavError::UnsupportedChannels(c)) if c == channels),
+            "{result:?}"
+        );
+    }
+}
+
+#[test]
+fn decodes_silk_to_a_playable_wav() {
+    for rate in [8000, 24000, 44100] {
+        let encoded = silk_stream(rate, 300);
+        let pcm = decode_silk(&encoded, rate as i32).unwrap();
+        let wav = decode_silk_to_wav(&encoded, rate as i32).unwrap();
+        assert_eq!(wav.len(), 44 + pcm.len());
+
+        let u16_at = |offset: usize| u16::from_le_bytes([wav[offset], wav[offset + 1]]);
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap());
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(4) as usize, wav.len() - 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(16), 16);
+        assert_eq!((u16_at(20), u16_at(22)), (PCM, 1));
+        assert_eq!(u32_at(24), rate);
+        assert_eq!(u32_at(28), rate * 2);
+        assert_eq!((u16_at(32), u16_at(34)), (2, 16));
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(40) as usize, pcm.len());
+
+        let parsed = parse_wav(&wav).unwrap();
+        assert_eq!((parsed.sample_rate, parsed.channels), (rate, 1));
+        assert_eq!(parsed.data, pcm);
+    }
+    assert!(matches!(
+        decode_silk_to_wav(b"RIFF", 24000),
+        Err(SilkError::Invalid)
+    ));
+}
+
+#[test]
+fn stereo_wav_carries_both_channels() {
+    let encoded = silk_stream(16000, 300);
+    let options = SilkDecodeOptions::new()
+        .sample_rate(SampleRate::Hz12000)
+        .output_channels(2);
+    let pcm = decode_silk_with_options(&encoded, &options).unwrap();
+    let wav = decode_silk_to_wav_with_options(&encoded, &options).unwrap();
+    assert_eq!(wav.len(), 44 + pcm.len());
+    let u16_at = |offset: usize| u16::from_le_bytes([wav[offset], wav[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap());
+    assert_eq!((u16_at(22), u32_at(24)), (2, 12000));
+    assert_eq!((u32_at(28), u16_at(32)), (12000 * 4, 4));
+
+    let parsed = parse_wav(&wav).unwrap();
+    assert_eq!((parsed.sample_rate, parsed.channels), (12000, 2));
+    assert_eq!(parsed.data, pcm);
+    // 300 ms per channel
+    assert_eq!(pcm.len(), 12000 * 3 / 10 * 4);
+}